@@ -15,6 +15,7 @@ load("//bazel/module_extensions/portage:remoteexec_info.bzl", "remoteexec_info")
 load("//bazel/module_extensions/private:hub_repo.bzl", "hub_init")
 load("//bazel/portage/bin/alchemist/src/bin/alchemist:repo_rule_srcs.bzl", "ALCHEMIST_REPO_RULE_SRCS")
 load("//bazel/portage/repo_defs/chrome:cros_chrome_repository.bzl", _cros_chrome_repository = "cros_chrome_repository")
+load("//bazel/repo_defs:git_commit_repository.bzl", _git_commit_repository = "git_commit_repository")
 load("//bazel/repo_defs:nested_bazel.bzl", "nested_bazel")
 load("//bazel/repo_defs:preflight_checks.bzl", "portage_preflight_checks")
 load("//bazel/repo_defs:repo_repository.bzl", _repo_repository = "repo_repository")
@@ -66,6 +67,10 @@ def _portage_deps_impl(module_ctx):
         _repo_repository,
         default_targets = {"src": "//:src"},
     )
+    git_commit_repository = hub.wrap_rule(
+        _git_commit_repository,
+        default_targets = {"src": "//:src"},
+    )
 
     for repo in deps:
         for rule, kwargs in repo.items():
@@ -80,6 +85,8 @@ def _portage_deps_impl(module_ctx):
                 hub.cipd_file.alias_only(**kwargs)
             elif rule == "CrosChromeRepository":
                 cros_chrome_repository.alias_only(**kwargs)
+            elif rule == "GitCommitRepository":
+                git_commit_repository.alias_only(**kwargs)
             else:
                 fail("Unknown rule %s" % rule)
 
@@ -84,6 +84,18 @@ pub struct DependencyExpressions {
     pub install_host: String,
 }
 
+impl DependencyExpressions {
+    pub fn get(&self, kind: DependencyKind) -> &str {
+        match kind {
+            DependencyKind::BuildTarget => &self.build_target,
+            DependencyKind::RunTarget => &self.run_target,
+            DependencyKind::PostTarget => &self.post_target,
+            DependencyKind::BuildHost => &self.build_host,
+            DependencyKind::InstallHost => &self.install_host,
+        }
+    }
+}
+
 /// Represents a package dependency type.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum DependencyKind {
@@ -99,6 +111,29 @@ pub enum DependencyKind {
     InstallHost,
 }
 
+impl DependencyKind {
+    /// All dependency kinds, in the order Portage lists them.
+    pub const ALL: [DependencyKind; 5] = [
+        DependencyKind::BuildTarget,
+        DependencyKind::RunTarget,
+        DependencyKind::PostTarget,
+        DependencyKind::BuildHost,
+        DependencyKind::InstallHost,
+    ];
+
+    /// Returns the name of the ebuild variable declaring this kind of
+    /// dependencies.
+    pub fn var_name(self) -> &'static str {
+        match self {
+            DependencyKind::BuildTarget => "DEPEND",
+            DependencyKind::RunTarget => "RDEPEND",
+            DependencyKind::PostTarget => "PDEPEND",
+            DependencyKind::BuildHost => "BDEPEND",
+            DependencyKind::InstallHost => "IDEPEND",
+        }
+    }
+}
+
 // TODO(b:299056510): Consider removing 4-argument variant of this function.
 fn extract_dependencies(
     details: &PackageDetails,
@@ -125,17 +160,12 @@ fn extract_dependencies_use(
     resolver: &PackageResolver,
     allow_list: Option<&[&str]>,
 ) -> Result<(Vec<Arc<PackageDetails>>, String)> {
-    let var_name = match kind {
-        DependencyKind::BuildTarget => Some("DEPEND"),
-        DependencyKind::RunTarget => Some("RDEPEND"),
-        DependencyKind::PostTarget => Some("PDEPEND"),
-        DependencyKind::BuildHost => Some("BDEPEND"),
-        DependencyKind::InstallHost => details.supports_idepend().then_some("IDEPEND"),
-    };
-
-    let raw_deps = var_name.map_or(Ok(""), |var_name| {
-        details.metadata.vars.get_scalar_or_default(var_name)
-    })?;
+    // Variables unsupported by the EAPI are always empty: ebuilds setting them
+    // fail to load, and those set by eclasses are dropped on evaluation.
+    let raw_deps = details
+        .metadata
+        .vars
+        .get_scalar_or_default(kind.var_name())?;
 
     let raw_extra_deps = get_extra_dependencies(details, kind, cross_compile);
 
@@ -230,6 +260,8 @@ pub fn analyze_direct_dependencies(
             )
         })?;
 
+        // Before EAPI 7, DEPEND also lists the build tools to run on the
+        // host, so some of them are picked up as host dependencies.
         if !details.supports_bdepend() {
             // We need to apply the allow list filtering during dependency
             // evaluation instead of post-dependency evaluation because
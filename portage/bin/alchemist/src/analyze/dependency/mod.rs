@@ -4,3 +4,4 @@
 
 pub mod direct;
 pub mod indirect;
+pub mod subslot;
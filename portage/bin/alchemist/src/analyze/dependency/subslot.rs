@@ -0,0 +1,237 @@
+// Copyright 2024 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    path::Path,
+    sync::Arc,
+};
+
+use anyhow::{Context, Result};
+use itertools::Itertools;
+
+use crate::{
+    analyze::{dependency::direct::DependencyKind, MaybePackage, Package},
+    data::Slot,
+    dependency::{
+        package::{PackageDependency, PackageDependencyAtom},
+        CompositeDependency, Dependency,
+    },
+    ebuild::PackageDetails,
+};
+
+/// Identifies the set of packages a package was analyzed in.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum World {
+    /// Packages built for the CrOS SDK.
+    Host,
+    /// Packages built for the board.
+    Target,
+}
+
+/// Describes why a package needs to be rebuilt after a sub-slot change.
+#[derive(Clone, Debug)]
+pub enum RebuildReason {
+    /// The package has slot operator (`:=`) dependencies on the changed
+    /// package, so its dependency expressions embed the changed SLOT.
+    SlotOperator { kinds: Vec<DependencyKind> },
+    /// A package installed to build this package is rebuilt.
+    BuildInput {
+        world: World,
+        package: Arc<PackageDetails>,
+    },
+}
+
+/// A package whose generated build inputs change after a sub-slot change.
+#[derive(Clone, Debug)]
+pub struct SubslotRebuild {
+    pub world: World,
+    pub package: Arc<PackageDetails>,
+    pub reason: RebuildReason,
+}
+
+type PackageKey<'a> = (World, &'a Path);
+
+fn package_key(world: World, details: &PackageDetails) -> PackageKey<'_> {
+    (world, details.as_basic_data().ebuild_path.as_path())
+}
+
+/// Returns true if the dependency expression contains a slot operator
+/// dependency on `package_name` that was resolved to `old_slot`.
+fn has_slot_operator_dependency(
+    expression: &str,
+    package_name: &str,
+    old_slot: &Slot<&str>,
+) -> Result<bool> {
+    if expression.trim().is_empty() {
+        return Ok(false);
+    }
+    let deps = expression
+        .parse::<PackageDependency>()
+        .with_context(|| format!("Failed to parse dependency expression: {expression}"))?;
+
+    Ok(any_atom(&deps, &|atom| {
+        atom.package_name() == package_name
+            && atom
+                .slot()
+                .is_some_and(|slot| slot.rebuild_on_slot_change() && slot.matches(old_slot))
+    }))
+}
+
+/// Returns true if any atom in the dependency tree satisfies `pred`,
+/// regardless of USE conditionals.
+fn any_atom(deps: &PackageDependency, pred: &impl Fn(&PackageDependencyAtom) -> bool) -> bool {
+    match deps {
+        Dependency::Leaf(atom) => pred(atom),
+        Dependency::Composite(composite) => match &**composite {
+            CompositeDependency::AllOf { children }
+            | CompositeDependency::AnyOf { children }
+            | CompositeDependency::UseConditional { children, .. } => {
+                children.iter().any(|child| any_atom(child, pred))
+            }
+            CompositeDependency::Constant { .. } => false,
+        },
+    }
+}
+
+/// Returns the packages installed in the ephemeral CrOS SDK when building the
+/// package, excluding the package itself.
+fn collect_build_inputs<'a>(
+    world: World,
+    package: &'a Package,
+    index: &HashMap<PackageKey<'a>, &'a Package>,
+) -> HashSet<PackageKey<'a>> {
+    let deps = &package.dependencies;
+    let direct_inputs = deps
+        .direct
+        .build_target
+        .iter()
+        .map(|details| (world, details))
+        // BDEPEND and IDEPEND are always resolved in the host world.
+        .chain(
+            deps.indirect
+                .build_host_set
+                .iter()
+                .map(|details| (World::Host, details)),
+        );
+
+    let mut inputs = HashSet::new();
+    for (input_world, details) in direct_inputs {
+        let key = package_key(input_world, details);
+        inputs.insert(key);
+        if let Some(input) = index.get(&key) {
+            inputs.extend(
+                input
+                    .dependencies
+                    .indirect
+                    .install_set
+                    .iter()
+                    .map(|details| package_key(input_world, details)),
+            );
+        }
+    }
+    inputs
+}
+
+/// Computes the packages that need to be rebuilt when the SLOT of
+/// `package_name` changes from `old_slot` to any other value.
+///
+/// Packages with slot operator (`:=`) dependencies on the changed package are
+/// affected directly because their dependency expressions record the SLOT.
+/// Packages that install an affected package to build are affected in turn.
+///
+/// `host_packages` are the packages analyzed for the CrOS SDK, and
+/// `target_packages` are the ones analyzed for the board, if any. Packages
+/// that failed to analyze are ignored.
+///
+/// The result is sorted by world, then by the order packages were found.
+pub fn analyze_subslot_impact(
+    host_packages: &[MaybePackage],
+    target_packages: Option<&[MaybePackage]>,
+    package_name: &str,
+    old_slot: &Slot<&str>,
+) -> Result<Vec<SubslotRebuild>> {
+    let worlds = std::iter::once((World::Host, host_packages))
+        .chain(target_packages.map(|packages| (World::Target, packages)));
+
+    let mut index: HashMap<PackageKey, &Package> = HashMap::new();
+    let mut ordered_keys: Vec<PackageKey> = Vec::new();
+    for (world, packages) in worlds {
+        for package in packages {
+            if let MaybePackage::Ok(package) = package {
+                let key = package_key(world, &package.details);
+                index.insert(key, package);
+                ordered_keys.push(key);
+            }
+        }
+    }
+
+    let mut rebuilds: Vec<SubslotRebuild> = Vec::new();
+    let mut visited: HashSet<PackageKey> = HashSet::new();
+    let mut queue: VecDeque<PackageKey> = VecDeque::new();
+
+    for key in &ordered_keys {
+        let package = index[key];
+        let kinds = DependencyKind::ALL
+            .into_iter()
+            .map(|kind| -> Result<_> {
+                let expression = package.dependencies.expressions.get(kind);
+                Ok(
+                    has_slot_operator_dependency(expression, package_name, old_slot)?
+                        .then_some(kind),
+                )
+            })
+            .flatten_ok()
+            .collect::<Result<Vec<_>>>()
+            .with_context(|| {
+                format!(
+                    "Failed to inspect dependencies of {}-{}",
+                    package.as_basic_data().package_name,
+                    package.as_basic_data().version
+                )
+            })?;
+        if kinds.is_empty() {
+            continue;
+        }
+        visited.insert(*key);
+        queue.push_back(*key);
+        rebuilds.push(SubslotRebuild {
+            world: key.0,
+            package: package.details.clone(),
+            reason: RebuildReason::SlotOperator { kinds },
+        });
+    }
+
+    // Build the reverse graph of build inputs.
+    let mut dependents: HashMap<PackageKey, Vec<PackageKey>> = HashMap::new();
+    for key in &ordered_keys {
+        for input in collect_build_inputs(key.0, index[key], &index) {
+            dependents.entry(input).or_default().push(*key);
+        }
+    }
+
+    // Propagate rebuilds with BFS.
+    while let Some(current) = queue.pop_front() {
+        let Some(current_package) = index.get(&current) else {
+            continue;
+        };
+        for dependent in dependents.get(&current).into_iter().flatten() {
+            if !visited.insert(*dependent) {
+                continue;
+            }
+            queue.push_back(*dependent);
+            rebuilds.push(SubslotRebuild {
+                world: dependent.0,
+                package: index[dependent].details.clone(),
+                reason: RebuildReason::BuildInput {
+                    world: current.0,
+                    package: current_package.details.clone(),
+                },
+            });
+        }
+    }
+
+    rebuilds.sort_by_key(|rebuild| rebuild.world);
+    Ok(rebuilds)
+}
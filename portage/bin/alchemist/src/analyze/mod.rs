@@ -31,6 +31,7 @@ use self::{
 };
 
 pub mod dependency;
+pub mod query;
 pub mod restrict;
 pub mod source;
 #[cfg(test)]
@@ -180,6 +181,7 @@ pub struct PackageGlobalAnalysis {
 
 pub type MaybePackageGlobalAnalysis = Result<Box<PackageGlobalAnalysis>, Arc<PackageAnalysisError>>;
 
+#[instrument(skip_all, fields(ebuild = %details.as_basic_data().ebuild_path.display()))]
 fn analyze_local(
     details: &MaybePackageDetails,
     config: &ConfigBundle,
@@ -237,6 +239,7 @@ fn analyze_local(
 }
 
 /// Runs package-local analysis, i.e. analysis that can be done independently of other packages.
+#[instrument(skip_all)]
 fn analyze_locals(
     all_details: &[MaybePackageDetails],
     config: &ConfigBundle,
@@ -262,6 +265,7 @@ fn analyze_locals(
         .collect()
 }
 
+#[instrument(skip_all, fields(ebuild = %details.as_basic_data().ebuild_path.display()))]
 fn analyze_global(
     details: &MaybePackageDetails,
     local_map: &HashMap<PathBuf, MaybePackageLocalAnalysis>,
@@ -293,6 +297,7 @@ fn analyze_global(
 }
 
 /// Runs package-global analysis, i.e. analysis taking other packages into account.
+#[instrument(skip_all)]
 fn analyze_globals(
     all_details: &[MaybePackageDetails],
     local_map: &HashMap<PathBuf, MaybePackageLocalAnalysis>,
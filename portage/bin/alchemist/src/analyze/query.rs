@@ -0,0 +1,380 @@
+// Copyright 2024 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! A small query language to search for packages by their ebuild metadata.
+//!
+//! A query is a boolean expression of predicates combined with `and`, `or`,
+//! `not` and parentheses. Adjacent predicates are implicitly combined with
+//! `and`. The following predicates are supported:
+//!
+//! - `inherit:ECLASS`: The package inherits `ECLASS`, directly or indirectly.
+//! - `iuse:FLAG`: IUSE of the package contains `FLAG`.
+//! - `use:FLAG`: The USE flag `FLAG` is enabled for the package.
+//! - `category:NAME`: The package is in the category `NAME`.
+//! - `repo:NAME`: The package is in the repository `NAME`.
+//! - `var:NAME`: The ebuild variable `NAME` is set to a non-empty value.
+//! - `var:NAME=VALUE`: The ebuild variable `NAME` is `VALUE`. For arrays,
+//!   any element may match.
+//! - `metallurgy-target`: `METALLURGY_TARGET` is set.
+//! - `interface-libraries`: The package supports interface libraries with its
+//!   USE flags.
+//! - `stable`: The package is stable.
+//! - `masked`: The package is masked.
+//!
+//! For example, `inherit:cros-rust and (iuse:test or not stable)`.
+
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Result};
+
+use crate::{bash::vars::BashValue, ebuild::PackageDetails};
+
+/// A predicate on a single property of a package.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PackagePredicate {
+    Inherit(String),
+    IUse(String),
+    Use(String),
+    Category(String),
+    Repo(String),
+    VarSet(String),
+    VarEquals(String, String),
+    MetallurgyTarget,
+    InterfaceLibraries,
+    Stable,
+    Masked,
+}
+
+impl PackagePredicate {
+    fn parse(token: &str) -> Result<Self> {
+        let Some((key, value)) = token.split_once(':') else {
+            return Ok(match token {
+                "metallurgy-target" => Self::MetallurgyTarget,
+                "interface-libraries" => Self::InterfaceLibraries,
+                "stable" => Self::Stable,
+                "masked" => Self::Masked,
+                _ => bail!("Unknown predicate: {token}"),
+            });
+        };
+        if value.is_empty() {
+            bail!("Missing value in predicate: {token}");
+        }
+        let value = value.to_owned();
+        Ok(match key {
+            "inherit" => Self::Inherit(value),
+            "iuse" => Self::IUse(value),
+            "use" => Self::Use(value),
+            "category" => Self::Category(value),
+            "repo" => Self::Repo(value),
+            "var" => match value.split_once('=') {
+                Some((name, expected)) => Self::VarEquals(name.to_owned(), expected.to_owned()),
+                None => Self::VarSet(value),
+            },
+            _ => bail!("Unknown predicate: {token}"),
+        })
+    }
+
+    fn matches(&self, details: &PackageDetails) -> Result<bool> {
+        let vars = details.metadata.vars.hash_map();
+        Ok(match self {
+            Self::Inherit(eclass) => details.inherited.contains(eclass),
+            Self::IUse(flag) => details
+                .metadata
+                .vars
+                .get_scalar_or_default("IUSE")?
+                .split_ascii_whitespace()
+                .any(|token| token.trim_start_matches(['+', '-']) == flag),
+            Self::Use(flag) => details.use_map.get(flag).copied().unwrap_or(false),
+            Self::Category(category) => &details.as_basic_data().category_name == category,
+            Self::Repo(repo) => &details.as_basic_data().repo_name == repo,
+            Self::VarSet(name) => match vars.get(name) {
+                None => false,
+                Some(BashValue::Scalar(value)) => !value.is_empty(),
+                Some(BashValue::IndexedArray(values)) => !values.is_empty(),
+                Some(BashValue::AssociativeArray(values)) => !values.is_empty(),
+            },
+            Self::VarEquals(name, expected) => match vars.get(name) {
+                None => false,
+                Some(BashValue::Scalar(value)) => value == expected,
+                Some(BashValue::IndexedArray(values)) => values.contains(expected),
+                Some(BashValue::AssociativeArray(values)) => values.values().any(|v| v == expected),
+            },
+            Self::MetallurgyTarget => details.direct_build_target.is_some(),
+            Self::InterfaceLibraries => details
+                .bazel_metadata
+                .eval_supports_interface_libraries(&details.use_map)?,
+            Self::Stable => details.stable,
+            Self::Masked => !details.readiness.ok(),
+        })
+    }
+}
+
+/// A parsed query. See the module documentation for the syntax.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PackageQuery {
+    Predicate(PackagePredicate),
+    Not(Box<PackageQuery>),
+    And(Vec<PackageQuery>),
+    Or(Vec<PackageQuery>),
+}
+
+impl PackageQuery {
+    /// Returns whether the package matches the query.
+    pub fn matches(&self, details: &PackageDetails) -> Result<bool> {
+        match self {
+            Self::Predicate(predicate) => predicate.matches(details),
+            Self::Not(query) => Ok(!query.matches(details)?),
+            Self::And(queries) => {
+                for query in queries {
+                    if !query.matches(details)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            Self::Or(queries) => {
+                for query in queries {
+                    if query.matches(details)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start: Option<usize> = None;
+    for (i, c) in input.char_indices() {
+        if c.is_whitespace() || c == '(' || c == ')' {
+            if let Some(s) = start.take() {
+                tokens.push(&input[s..i]);
+            }
+            if !c.is_whitespace() {
+                tokens.push(&input[i..i + 1]);
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(s) = start {
+        tokens.push(&input[s..]);
+    }
+    tokens
+}
+
+/// A recursive descent parser over the tokens of a query.
+struct Parser<'a> {
+    tokens: Vec<&'a str>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        let token = self.peek();
+        self.pos += 1;
+        token
+    }
+
+    fn parse_or(&mut self) -> Result<PackageQuery> {
+        let mut queries = vec![self.parse_and()?];
+        while self.peek() == Some("or") {
+            self.next();
+            queries.push(self.parse_and()?);
+        }
+        Ok(if queries.len() == 1 {
+            queries.pop().unwrap()
+        } else {
+            PackageQuery::Or(queries)
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<PackageQuery> {
+        let mut queries = vec![self.parse_not()?];
+        loop {
+            match self.peek() {
+                Some("and") => {
+                    self.next();
+                }
+                None | Some("or") | Some(")") => break,
+                Some(_) => {}
+            }
+            queries.push(self.parse_not()?);
+        }
+        Ok(if queries.len() == 1 {
+            queries.pop().unwrap()
+        } else {
+            PackageQuery::And(queries)
+        })
+    }
+
+    fn parse_not(&mut self) -> Result<PackageQuery> {
+        match self.next() {
+            Some("not") => Ok(PackageQuery::Not(Box::new(self.parse_not()?))),
+            Some("(") => {
+                let query = self.parse_or()?;
+                match self.next() {
+                    Some(")") => Ok(query),
+                    _ => bail!("Missing closing parenthesis"),
+                }
+            }
+            Some(token @ (")" | "and" | "or")) => bail!("Unexpected {token:?}"),
+            Some(token) => Ok(PackageQuery::Predicate(PackagePredicate::parse(token)?)),
+            None => bail!("Unexpected end of query"),
+        }
+    }
+}
+
+impl FromStr for PackageQuery {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(s),
+            pos: 0,
+        };
+        let query = parser
+            .parse_or()
+            .with_context(|| format!("Invalid query: {s}"))?;
+        if let Some(token) = parser.peek() {
+            bail!("Invalid query: {s}: Unexpected {token:?}");
+        }
+        Ok(query)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{HashMap, HashSet},
+        path::PathBuf,
+        sync::Arc,
+    };
+
+    use crate::{
+        bash::vars::BashVars,
+        data::{Slot, UseMap},
+        ebuild::{
+            metadata::{EBuildBasicData, EBuildMetadata},
+            PackageReadiness,
+        },
+    };
+
+    use super::*;
+
+    fn new_package(vars: HashMap<String, BashValue>, inherited: &[&str]) -> PackageDetails {
+        PackageDetails {
+            metadata: Arc::new(EBuildMetadata {
+                basic_data: EBuildBasicData {
+                    repo_name: "chromiumos".to_owned(),
+                    ebuild_path: PathBuf::from("/path/to/some.ebuild"),
+                    package_name: "dev-rust/foo".to_owned(),
+                    short_package_name: "foo".to_owned(),
+                    category_name: "dev-rust".to_owned(),
+                    version: "1.0".parse().unwrap(),
+                },
+                vars: BashVars::new(vars),
+            }),
+            slot: Slot::new("0"),
+            use_map: UseMap::from([("test".to_owned(), false), ("ssl".to_owned(), true)]),
+            stable: true,
+            readiness: PackageReadiness::Ok,
+            inherited: inherited
+                .iter()
+                .map(|s| s.to_string())
+                .collect::<HashSet<_>>(),
+            inherit_paths: vec![],
+            direct_build_target: None,
+            bazel_metadata: Default::default(),
+        }
+    }
+
+    fn pred(s: &str) -> PackageQuery {
+        PackageQuery::Predicate(PackagePredicate::parse(s).unwrap())
+    }
+
+    #[test]
+    fn parse() -> Result<()> {
+        assert_eq!(
+            "inherit:cros-rust iuse:test or not (stable and masked)".parse::<PackageQuery>()?,
+            PackageQuery::Or(vec![
+                PackageQuery::And(vec![pred("inherit:cros-rust"), pred("iuse:test")]),
+                PackageQuery::Not(Box::new(PackageQuery::And(vec![
+                    pred("stable"),
+                    pred("masked")
+                ]))),
+            ])
+        );
+        assert_eq!(
+            "var:CROS_RUST_FEATURES=z".parse::<PackageQuery>()?,
+            PackageQuery::Predicate(PackagePredicate::VarEquals(
+                "CROS_RUST_FEATURES".to_owned(),
+                "z".to_owned()
+            ))
+        );
+
+        for invalid in [
+            "",
+            "(stable",
+            "stable)",
+            "and stable",
+            "foo:bar",
+            "iuse:",
+            "bogus",
+        ] {
+            assert!(
+                invalid.parse::<PackageQuery>().is_err(),
+                "{invalid:?} should be rejected"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn matches() -> Result<()> {
+        let package = new_package(
+            HashMap::from([
+                ("IUSE".to_owned(), BashValue::Scalar("+ssl test".to_owned())),
+                (
+                    "FEATURES".to_owned(),
+                    BashValue::IndexedArray(vec!["x".to_owned(), "z".to_owned()]),
+                ),
+            ]),
+            &["cros-rust", "cros-workon"],
+        );
+
+        for (query, expected) in [
+            ("inherit:cros-rust", true),
+            ("inherit:cros-go", false),
+            ("iuse:ssl", true),
+            ("iuse:test", true),
+            ("use:ssl", true),
+            ("use:test", false),
+            ("category:dev-rust", true),
+            ("repo:chromiumos", true),
+            ("var:FEATURES", true),
+            ("var:FEATURES=z", true),
+            ("var:FEATURES=y", false),
+            ("var:METALLURGY_TARGET", false),
+            ("metallurgy-target", false),
+            ("interface-libraries", true),
+            ("inherit:cros-rust and var:FEATURES=z", true),
+            ("inherit:cros-go or use:ssl", true),
+            ("not (stable or masked)", false),
+        ] {
+            assert_eq!(
+                query.parse::<PackageQuery>()?.matches(&package)?,
+                expected,
+                "{query}"
+            );
+        }
+        Ok(())
+    }
+}
@@ -108,12 +108,24 @@ impl PackageDistSource {
     }
 }
 
+/// Represents a git repository pinned to a commit, fetched remotely to build a
+/// package inheriting git-r3.eclass.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct PackageGitSource {
+    pub name: String,
+    /// URIs of the repository from EGIT_REPO_URI, tried in order.
+    pub repo_uris: Vec<String>,
+    /// The full commit hash from EGIT_COMMIT.
+    pub commit: String,
+}
+
 /// Analyzed source information of a package. It is returned by
 /// [`analyze_sources`].
 pub struct PackageSources {
     pub local_sources: Vec<PackageLocalSource>,
     pub repo_sources: Vec<PackageRepoSource>,
     pub dist_sources: Vec<PackageDistSource>,
+    pub git_sources: Vec<PackageGitSource>,
 }
 
 fn get_cros_workon_array_variable(
@@ -335,6 +347,16 @@ fn extract_cros_workon_sources(
     Ok((sources, repo_sources))
 }
 
+/// Returns the repo sources pinned by CROS_WORKON_TREE of a stable cros-workon
+/// package. Returns an empty list for other packages.
+pub fn extract_repo_sources(
+    details: &PackageDetails,
+    src_dir: &Path,
+) -> Result<Vec<PackageRepoSource>> {
+    let (_, repo_sources) = extract_cros_workon_sources(details, src_dir)?;
+    Ok(repo_sources)
+}
+
 fn apply_local_sources_workarounds(
     details: &PackageDetails,
     local_sources: &mut Vec<PackageLocalSource>,
@@ -479,20 +501,109 @@ fn convert_public_gs_buckets_to_https(url: Url) -> Result<Url> {
     )?)
 }
 
+/// Describes where distfiles of a package are fetched from.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum FetchPolicy {
+    /// Fetch from the URIs in SRC_URI only.
+    SrcUriOnly,
+    /// Fetch from GENTOO_MIRRORS first, then from the URIs in SRC_URI.
+    MirrorFirst,
+    /// Fetch from the URIs in SRC_URI first, then from GENTOO_MIRRORS.
+    SrcUriFirst,
+    /// Fetch from GENTOO_MIRRORS only.
+    MirrorOnly,
+}
+
+impl FetchPolicy {
+    /// Computes the policy from FEATURES and RESTRICT.
+    ///
+    /// `FEATURES=force-mirror` restricts fetching to GENTOO_MIRRORS, and
+    /// `FEATURES=mirror` tries GENTOO_MIRRORS before SRC_URI, unless the
+    /// package sets `RESTRICT=primaryuri`. `RESTRICT=mirror` disables mirrors
+    /// regardless of FEATURES.
+    fn compute(features: &HashSet<&str>, restricts: &[RestrictAtom]) -> Self {
+        if restricts.contains(&RestrictAtom::Mirror) {
+            FetchPolicy::SrcUriOnly
+        } else if features.contains("force-mirror") {
+            FetchPolicy::MirrorOnly
+        } else if features.contains("mirror") {
+            if restricts.contains(&RestrictAtom::PrimaryUri) {
+                FetchPolicy::SrcUriFirst
+            } else {
+                FetchPolicy::MirrorFirst
+            }
+        } else {
+            FetchPolicy::SrcUriOnly
+        }
+    }
+
+    fn uses_mirrors(&self) -> bool {
+        *self != FetchPolicy::SrcUriOnly
+    }
+}
+
+/// A distfile that needs to be downloaded manually.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ManualDistFile {
+    pub filename: String,
+    pub urls: Vec<Url>,
+    pub size: u64,
+    pub hashes: HashMap<String, String>,
+}
+
+/// Error returned by [`analyze_sources`] for packages with `RESTRICT=fetch`,
+/// whose distfiles can't be fetched automatically.
+///
+/// Its message contains instructions to download the distfiles manually.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FetchRestrictedError {
+    pub package_name: String,
+    pub version: String,
+    pub homepage: String,
+    pub files: Vec<ManualDistFile>,
+}
+
+impl std::fmt::Display for FetchRestrictedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{}-{} has RESTRICT=fetch, so its distfiles must be downloaded manually.",
+            self.package_name, self.version
+        )?;
+        writeln!(
+            f,
+            "Download the following files and upload them to the distfiles/ \
+            directory of a mirror in GENTOO_MIRRORS:"
+        )?;
+        for file in &self.files {
+            write!(f, "  {} ({} bytes", file.filename, file.size)?;
+            for (name, hash) in file.hashes.iter().sorted() {
+                write!(f, ", {name} {hash}")?;
+            }
+            writeln!(f, ")")?;
+            for url in &file.urls {
+                writeln!(f, "    from {url}")?;
+            }
+        }
+        if !self.homepage.is_empty() {
+            write!(f, "See {} for details.", self.homepage)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for FetchRestrictedError {}
+
 fn extract_remote_sources(
     config: &ConfigBundle,
     details: &PackageDetails,
 ) -> Result<Vec<PackageDistSource>> {
     let restricts = analyze_restricts(details)?;
+    let fetch_restricted = restricts.contains(&RestrictAtom::Fetch);
 
-    // TODO: We should read the FEATURES field from the portage config and check
-    // for `mirror` and `force-mirror`. For our purposes we always want
-    // force-mirror so let's just hard code it for now.
-    let force_mirror = true;
+    let policy = FetchPolicy::compute(&config.features(), &restricts);
 
-    let use_mirror = force_mirror && !restricts.contains(&RestrictAtom::Mirror);
-
-    let mirrors = if use_mirror {
+    let mirrors = if policy.uses_mirrors() {
         let mirrors = config
             .env()
             .get("GENTOO_MIRRORS")
@@ -505,15 +616,14 @@ fn extract_remote_sources(
         mirrors.sort_by_key(|url| url.ends_with("/gentoo") != is_portage_stable);
 
         ensure!(
-            !mirrors.is_empty(),
+            policy != FetchPolicy::MirrorOnly || !mirrors.is_empty(),
             "Force mirror is enabled, but no mirrors were found"
         );
 
-        Some(mirrors)
+        mirrors
     } else {
-        None
+        Vec::new()
     };
-
     // Collect URIs from SRC_URI.
     let src_uri = details.metadata.vars.get_scalar_or_default("SRC_URI")?;
     let source_deps = src_uri.parse::<UriDependency>()?;
@@ -557,6 +667,35 @@ fn extract_remote_sources(
         .map(|dist| (dist.filename.clone(), dist))
         .collect();
 
+    if fetch_restricted {
+        let files = source_map
+            .into_iter()
+            .sorted_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(filename, urls)| {
+                let dist = dist_map
+                    .remove(&filename)
+                    .ok_or_else(|| anyhow!("{} not found in Manifest", &filename))?;
+                Ok(ManualDistFile {
+                    filename,
+                    urls,
+                    size: dist.size,
+                    hashes: dist.hashes,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        return Err(FetchRestrictedError {
+            package_name: details.as_basic_data().package_name.clone(),
+            version: details.as_basic_data().version.to_string(),
+            homepage: details
+                .metadata
+                .vars
+                .get_scalar_or_default("HOMEPAGE")?
+                .to_owned(),
+            files,
+        }
+        .into());
+    }
+
     let mut sources = source_map
         .into_iter()
         .map(|(filename, urls)| {
@@ -564,15 +703,21 @@ fn extract_remote_sources(
                 .remove(&filename)
                 .ok_or_else(|| anyhow!("{} not found in Manifest", &filename))?;
 
-            let urls = match &mirrors {
-                Some(mirrors) => mirrors
-                    .iter()
-                    .map(|mirror| Url::parse(format!("{}/distfiles/{}", mirror, filename).as_ref()))
-                    .collect::<::core::result::Result<_, _>>()?,
-                None => urls
-                    .into_iter()
+            let mirror_urls = mirrors
+                .iter()
+                .map(|mirror| Url::parse(format!("{}/distfiles/{}", mirror, filename).as_ref()))
+                .collect::<::core::result::Result<Vec<_>, _>>()?;
+            let src_uri_urls = if policy == FetchPolicy::MirrorOnly {
+                Vec::new()
+            } else {
+                urls.into_iter()
                     .map(convert_public_gs_buckets_to_https)
-                    .collect::<Result<Vec<_>>>()?,
+                    .collect::<Result<Vec<_>>>()?
+            };
+            let urls = if policy == FetchPolicy::SrcUriFirst {
+                src_uri_urls.into_iter().chain(mirror_urls).collect_vec()
+            } else {
+                mirror_urls.into_iter().chain(src_uri_urls).collect_vec()
             };
 
             // TODO: This should probably go into generate_repo, but failing
@@ -603,6 +748,60 @@ fn extract_remote_sources(
     Ok(sources)
 }
 
+/// Returns a name for a git source that is safe to use as a Bazel repository
+/// name, e.g. `git-github.com-foo-bar-<commit>` for
+/// `https://github.com/foo/bar.git`.
+fn git_source_name(repo_uri: &str, commit: &str) -> String {
+    let path = repo_uri
+        .split_once("://")
+        .map_or(repo_uri, |(_, rest)| rest)
+        .trim_end_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    let path: String = path
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    format!("git-{}-{}", path, commit)
+}
+
+/// Extracts the pinned git repository of a package inheriting git-r3.eclass.
+///
+/// Only packages pinning EGIT_COMMIT to a full commit hash are supported,
+/// since branches and tags can't be fetched reproducibly. Other packages, e.g.
+/// 9999 ebuilds tracking a branch, have no git sources and keep letting
+/// git-r3 fetch the repository at build time.
+fn extract_git_sources(details: &PackageDetails) -> Result<Vec<PackageGitSource>> {
+    if !details.inherited.contains("git-r3") {
+        return Ok(Vec::new());
+    }
+
+    let commit = details.metadata.vars.get_scalar_or_default("EGIT_COMMIT")?;
+    if commit.len() != 40 || !commit.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Ok(Vec::new());
+    }
+    let commit = commit.to_ascii_lowercase();
+
+    let repo_uris = match details.metadata.vars.hash_map().get("EGIT_REPO_URI") {
+        None => bail!("git-r3 is inherited, but EGIT_REPO_URI is not defined"),
+        Some(BashValue::Scalar(value)) => value.split_ascii_whitespace().map_into().collect_vec(),
+        Some(BashValue::IndexedArray(values)) => values.clone(),
+        Some(other) => bail!("Invalid EGIT_REPO_URI value: {:?}", other),
+    };
+    ensure!(!repo_uris.is_empty(), "EGIT_REPO_URI is empty");
+
+    Ok(vec![PackageGitSource {
+        name: git_source_name(&repo_uris[0], &commit),
+        repo_uris,
+        commit,
+    }])
+}
+
 /// Analyzes ebuild variables and returns [`PackageSources`] summarizing its
 /// source information.
 pub fn analyze_sources(
@@ -621,6 +820,7 @@ pub fn analyze_sources(
         local_sources,
         repo_sources,
         dist_sources: extract_remote_sources(config, details)?,
+        git_sources: extract_git_sources(details)?,
     })
 }
 
@@ -642,6 +842,13 @@ mod tests {
     const MIRRORS: &str = "https://mirror/a https://mirror/b";
 
     fn new_non_cros_workon_package(use_map: UseMap) -> Result<(PackageDetails, TempDir)> {
+        new_non_cros_workon_package_with_restrict(use_map, "extra? ( mirror )")
+    }
+
+    fn new_non_cros_workon_package_with_restrict(
+        use_map: UseMap,
+        restrict: &str,
+    ) -> Result<(PackageDetails, TempDir)> {
         let tmp = TempDir::new()?;
 
         write_files(
@@ -669,7 +876,9 @@ mod tests {
                     ("SRC_URI".to_owned(),
                         BashValue::Scalar("https://example/f00-0.1.0.tar.gz -> foo-0.1.0.tar.gz extra? ( gs://chromeos-localmirror/foo-extra.tar.gz )".to_owned())),
                     ("RESTRICT".to_owned(),
-                        BashValue::Scalar("extra? ( mirror )".to_owned())),
+                        BashValue::Scalar(restrict.to_owned())),
+                    ("HOMEPAGE".to_owned(),
+                        BashValue::Scalar("https://example/foo".to_owned())),
                     ])),
             }),
             slot: Slot::new("0"),
@@ -701,10 +910,10 @@ mod tests {
     fn src_uri_mirror_no_extra() -> Result<()> {
         let config = ConfigBundle::from_sources(vec![SimpleConfigSource::new(vec![ConfigNode {
             sources: vec![],
-            value: ConfigNodeValue::Vars(Vars::from([(
-                "GENTOO_MIRRORS".to_owned(),
-                MIRRORS.to_owned(),
-            )])),
+            value: ConfigNodeValue::Vars(Vars::from([
+                ("GENTOO_MIRRORS".to_owned(), MIRRORS.to_owned()),
+                ("FEATURES".to_owned(), "force-mirror".to_owned()),
+            ])),
         }])]);
 
         let (package, _tmpdir) = new_non_cros_workon_package(UseMap::new())?;
@@ -733,10 +942,10 @@ mod tests {
     fn src_uri_mirror_with_extra() -> Result<()> {
         let config = ConfigBundle::from_sources(vec![SimpleConfigSource::new(vec![ConfigNode {
             sources: vec![],
-            value: ConfigNodeValue::Vars(Vars::from([(
-                "GENTOO_MIRRORS".to_owned(),
-                MIRRORS.to_owned(),
-            )])),
+            value: ConfigNodeValue::Vars(Vars::from([
+                ("GENTOO_MIRRORS".to_owned(), MIRRORS.to_owned()),
+                ("FEATURES".to_owned(), "force-mirror".to_owned()),
+            ])),
         }])]);
 
         let (package, _tmpdir) =
@@ -774,6 +983,117 @@ mod tests {
         Ok(())
     }
 
+    fn new_config_with_features(features: &str) -> ConfigBundle {
+        ConfigBundle::from_sources(vec![SimpleConfigSource::new(vec![ConfigNode {
+            sources: vec![],
+            value: ConfigNodeValue::Vars(Vars::from([
+                ("GENTOO_MIRRORS".to_owned(), MIRRORS.to_owned()),
+                ("FEATURES".to_owned(), features.to_owned()),
+            ])),
+        }])])
+    }
+
+    #[test]
+    fn src_uri_without_mirror_features() -> Result<()> {
+        let config = new_config_with_features("sandbox -force-mirror");
+
+        let (package, _tmpdir) = new_non_cros_workon_package(UseMap::new())?;
+        let dist_sources = extract_remote_sources(&config, &package)?;
+
+        assert_eq!(
+            dist_sources
+                .into_iter()
+                .map(|source| source.urls)
+                .collect_vec(),
+            [vec![Url::parse("https://example/f00-0.1.0.tar.gz")?]]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn src_uri_mirror_feature_falls_back_to_src_uri() -> Result<()> {
+        let config = new_config_with_features("force-mirror -force-mirror mirror");
+
+        let (package, _tmpdir) = new_non_cros_workon_package(UseMap::new())?;
+        let dist_sources = extract_remote_sources(&config, &package)?;
+
+        assert_eq!(
+            dist_sources
+                .into_iter()
+                .map(|source| source.urls)
+                .collect_vec(),
+            [vec![
+                Url::parse("https://mirror/a/distfiles/foo-0.1.0.tar.gz")?,
+                Url::parse("https://mirror/b/distfiles/foo-0.1.0.tar.gz")?,
+                Url::parse("https://example/f00-0.1.0.tar.gz")?,
+            ]]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn fetch_policy() {
+        let features = |s: &'static str| s.split_whitespace().collect::<HashSet<_>>();
+
+        assert_eq!(
+            FetchPolicy::compute(&features(""), &[]),
+            FetchPolicy::SrcUriOnly
+        );
+        assert_eq!(
+            FetchPolicy::compute(&features("mirror"), &[]),
+            FetchPolicy::MirrorFirst
+        );
+        assert_eq!(
+            FetchPolicy::compute(&features("mirror"), &[RestrictAtom::PrimaryUri]),
+            FetchPolicy::SrcUriFirst
+        );
+        assert_eq!(
+            FetchPolicy::compute(&features("mirror force-mirror"), &[]),
+            FetchPolicy::MirrorOnly
+        );
+        assert_eq!(
+            FetchPolicy::compute(&features("force-mirror"), &[RestrictAtom::Mirror]),
+            FetchPolicy::SrcUriOnly
+        );
+    }
+
+    #[test]
+    fn src_uri_fetch_restricted() -> Result<()> {
+        let config = new_config_with_features("force-mirror");
+
+        let (package, _tmpdir) = new_non_cros_workon_package_with_restrict(UseMap::new(), "fetch")?;
+        let err = extract_remote_sources(&config, &package).unwrap_err();
+        let err = err
+            .downcast_ref::<FetchRestrictedError>()
+            .expect("FetchRestrictedError");
+
+        assert_eq!(
+            err,
+            &FetchRestrictedError {
+                package_name: "sys-libs/foo".to_owned(),
+                version: "0.1.0".to_owned(),
+                homepage: "https://example/foo".to_owned(),
+                files: vec![ManualDistFile {
+                    filename: "foo-0.1.0.tar.gz".to_owned(),
+                    urls: vec![Url::parse("https://example/f00-0.1.0.tar.gz")?],
+                    size: 12345,
+                    hashes: HashMap::from([(
+                        "SHA256".to_owned(),
+                        "01ba4719c80b6fe911b091a7c05124b64eeece964e09c058ef8f9805daca546b"
+                            .to_owned()
+                    )]),
+                }],
+            }
+        );
+        assert!(err
+            .to_string()
+            .contains("  foo-0.1.0.tar.gz (12345 bytes, SHA256 01ba4719"));
+
+        Ok(())
+    }
+
     #[test]
     fn cros_workon_pinned_package_with_subtree() -> Result<()> {
         let package = PackageDetails {
@@ -1544,4 +1864,87 @@ mod tests {
 
         Ok(())
     }
+
+    fn new_git_r3_package(vars: &[(&str, BashValue)]) -> PackageDetails {
+        PackageDetails {
+            metadata: Arc::new(EBuildMetadata {
+                basic_data: EBuildBasicData {
+                    repo_name: "baz".to_owned(),
+                    ebuild_path: PathBuf::from("/dev/null"),
+                    package_name: "dev-util/foo".to_owned(),
+                    short_package_name: "foo".to_owned(),
+                    category_name: "dev-util".to_owned(),
+                    version: Version::try_new("0.1.0").unwrap(),
+                },
+                vars: BashVars::new(
+                    vars.iter()
+                        .map(|(name, value)| (name.to_string(), value.clone()))
+                        .collect(),
+                ),
+            }),
+            slot: Slot::new("0"),
+            use_map: UseMap::new(),
+            stable: true,
+            readiness: PackageReadiness::Ok,
+            inherited: HashSet::from(["git-r3".to_owned()]),
+            inherit_paths: vec![],
+            direct_build_target: None,
+            bazel_metadata: Default::default(),
+        }
+    }
+
+    #[test]
+    fn git_r3_pinned_package() -> Result<()> {
+        let package = new_git_r3_package(&[
+            (
+                "EGIT_REPO_URI",
+                BashValue::Scalar(
+                    "https://github.com/foo/bar.git https://mirror.example/foo/bar".to_owned(),
+                ),
+            ),
+            (
+                "EGIT_COMMIT",
+                BashValue::Scalar("0123456789ABCDEF0123456789abcdef01234567".to_owned()),
+            ),
+        ]);
+
+        assert_eq!(
+            extract_git_sources(&package)?,
+            [PackageGitSource {
+                name: "git-github.com-foo-bar-0123456789abcdef0123456789abcdef01234567".to_owned(),
+                repo_uris: vec![
+                    "https://github.com/foo/bar.git".to_owned(),
+                    "https://mirror.example/foo/bar".to_owned(),
+                ],
+                commit: "0123456789abcdef0123456789abcdef01234567".to_owned(),
+            }]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn git_r3_unpinned_package() -> Result<()> {
+        // Live ebuilds tracking a branch are fetched by git-r3 at build time.
+        let package = new_git_r3_package(&[
+            (
+                "EGIT_REPO_URI",
+                BashValue::Scalar("https://github.com/foo/bar.git".to_owned()),
+            ),
+            ("EGIT_BRANCH", BashValue::Scalar("main".to_owned())),
+        ]);
+        assert_eq!(extract_git_sources(&package)?, vec![]);
+
+        // Abbreviated commit hashes can't be fetched reliably either.
+        let package = new_git_r3_package(&[
+            (
+                "EGIT_REPO_URI",
+                BashValue::Scalar("https://github.com/foo/bar.git".to_owned()),
+            ),
+            ("EGIT_COMMIT", BashValue::Scalar("0123456".to_owned())),
+        ]);
+        assert_eq!(extract_git_sources(&package)?, vec![]);
+
+        Ok(())
+    }
 }
@@ -5,17 +5,26 @@
 use std::{collections::BTreeMap, fmt::Write, path::Path, sync::Arc};
 
 use anyhow::{Context, Result};
+use itertools::Itertools;
 use tempfile::TempDir;
 use version::Version;
 
 use crate::{
     config::bundle::ConfigBundle,
+    data::Slot,
     ebuild::{metadata::CachedEBuildEvaluator, CachedPackageLoader, PackageDetails, PackageLoader},
     repository::{RepositoryLayout, RepositorySet},
     resolver::PackageResolver,
 };
 
-use super::{analyze_packages, dependency::direct::DependencyExpressions, MaybePackage};
+use super::{
+    analyze_packages,
+    dependency::{
+        direct::{DependencyExpressions, DependencyKind},
+        subslot::{analyze_subslot_impact, RebuildReason, SubslotRebuild},
+    },
+    MaybePackage,
+};
 
 /// Provides an easy way to generate an ebuild file.
 struct PackageSpec {
@@ -23,6 +32,7 @@ struct PackageSpec {
     short_package_name: String,
     version: Version,
     vars: BTreeMap<String, String>,
+    eclasses: BTreeMap<String, String>,
 }
 
 impl PackageSpec {
@@ -42,6 +52,7 @@ impl PackageSpec {
             short_package_name: short_package_name.to_string(),
             version,
             vars: default_vars,
+            eclasses: BTreeMap::new(),
         })
     }
 
@@ -51,6 +62,12 @@ impl PackageSpec {
         self
     }
 
+    /// Inherits an eclass, which is saved to the overlay with `content`.
+    fn inherit(mut self, name: &str, content: &str) -> Self {
+        self.eclasses.insert(name.to_string(), content.to_string());
+        self
+    }
+
     /// Saves an ebuild file according to the spec.
     fn save_ebuild(&self, overlay_dir: &Path) -> Result<()> {
         let ebuild_path = overlay_dir
@@ -74,6 +91,22 @@ impl PackageSpec {
             )?;
         }
 
+        if !self.eclasses.is_empty() {
+            let eclass_dir = overlay_dir.join("eclass");
+            std::fs::create_dir_all(&eclass_dir)
+                .with_context(|| format!("Failed to mkdir {}", eclass_dir.display()))?;
+            for (name, content) in self.eclasses.iter() {
+                let eclass_path = eclass_dir.join(format!("{}.eclass", name));
+                std::fs::write(&eclass_path, content)
+                    .with_context(|| format!("Failed to create {}", eclass_path.display()))?;
+            }
+            writeln!(
+                &mut ebuild_content,
+                "inherit {}",
+                self.eclasses.keys().join(" ")
+            )?;
+        }
+
         std::fs::write(&ebuild_path, ebuild_content)
             .with_context(|| format!("Failed to create {}", ebuild_path.display()))?;
 
@@ -187,6 +220,16 @@ impl From<MaybePackage> for MaybePackageDescription {
 /// After calling [`analyze_packages`], it converts the result (`Vec<MaybePackage>`) into
 /// `Vec<Result<PackageDescription, String>>` for easier comparison.
 fn analyze_packages_for_testing(specs: &[PackageSpec]) -> Result<Vec<MaybePackageDescription>> {
+    let packages = analyze_raw_packages_for_testing(specs)?;
+
+    let descriptions = packages.into_iter().map(|p| p.into()).collect();
+
+    Ok(descriptions)
+}
+
+/// Similar to [`analyze_packages_for_testing`], but returns the result of [`analyze_packages`]
+/// as-is.
+fn analyze_raw_packages_for_testing(specs: &[PackageSpec]) -> Result<Vec<MaybePackage>> {
     let temp_dir = TempDir::new()?;
     let temp_dir = temp_dir.path();
 
@@ -237,9 +280,7 @@ fn analyze_packages_for_testing(specs: &[PackageSpec]) -> Result<Vec<MaybePackag
         &target_resolver,
     )?;
 
-    let descriptions = packages.into_iter().map(|p| p.into()).collect();
-
-    Ok(descriptions)
+    Ok(packages)
 }
 
 #[test]
@@ -563,10 +604,11 @@ fn test_analyze_packages_install_host_deps_eapi7() -> Result<()> {
     //                  IDEPEND
     // sys-libs/libfoo ────X───► sys-apps/coreutils
     //
+    // IDEPEND set by an eclass is ignored before EAPI 8.
     let packages = analyze_packages_for_testing(&[
         PackageSpec::new("sys-libs/libfoo", "1")?
             .var("EAPI", "7")
-            .var("IDEPEND", "sys-apps/coreutils"),
+            .inherit("idepend", "IDEPEND=\"sys-apps/coreutils\"\n"),
         PackageSpec::new("sys-apps/coreutils", "1")?,
     ])?;
 
@@ -595,6 +637,39 @@ fn test_analyze_packages_install_host_deps_eapi7() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_analyze_packages_install_host_deps_eapi7_in_ebuild() -> Result<()> {
+    // IDEPEND is not allowed before EAPI 8, so sys-libs/libfoo fails to load.
+    let packages = analyze_packages_for_testing(&[
+        PackageSpec::new("sys-libs/libfoo", "1")?
+            .var("EAPI", "7")
+            .var("IDEPEND", "sys-apps/coreutils"),
+        PackageSpec::new("sys-apps/coreutils", "1")?,
+    ])?;
+
+    assert_eq!(
+        packages,
+        vec![
+            MaybePackageDescription::Ok {
+                package_name_version: "sys-apps/coreutils-1".into(),
+                dependencies: PackageDependenciesDescription {
+                    install_set: vec!["sys-apps/coreutils-1".into()],
+                    ..PackageDependenciesDescription::EMPTY
+                },
+                dependency_expressions: DependencyExpressions::default(),
+            },
+            MaybePackageDescription::Err {
+                package_name_version: "sys-libs/libfoo-1".into(),
+                reason: "IDEPEND requires EAPI 8 or later, but the ebuild uses EAPI 7 \
+                (see PMS 8.1: https://projects.gentoo.org/pms/8/pms.html#x1-720008.1)"
+                    .into(),
+            },
+        ]
+    );
+
+    Ok(())
+}
+
 #[test]
 fn test_analyze_packages_indirect_host_deps() -> Result<()> {
     //                 BDEPEND                     RDEPEND
@@ -996,3 +1071,63 @@ fn test_analyze_dep_xpak_values() -> Result<()> {
 
     Ok(())
 }
+
+/// Textual representation of [`SubslotRebuild`] suitable for comparison.
+fn describe_subslot_rebuild(rebuild: &SubslotRebuild) -> (String, String) {
+    let data = rebuild.package.as_basic_data();
+    let reason = match &rebuild.reason {
+        RebuildReason::SlotOperator { kinds } => format!("{kinds:?}"),
+        RebuildReason::BuildInput { package, .. } => format!(
+            "{}-{}",
+            package.as_basic_data().package_name,
+            package.as_basic_data().version
+        ),
+    };
+    (format!("{}-{}", data.package_name, data.version), reason)
+}
+
+#[test]
+fn test_analyze_subslot_impact() -> Result<()> {
+    //                 DEPEND                 DEPEND
+    // sys-apps/b──────────────►sys-apps/a────────────►sys-libs/foo:=
+    //                               ▲
+    //                               │ RDEPEND
+    //                 DEPEND        │
+    // sys-apps/d──────────────►sys-apps/c
+    //
+    //                 DEPEND
+    // sys-apps/e──────────────►sys-libs/foo
+    let packages = analyze_raw_packages_for_testing(&[
+        PackageSpec::new("sys-libs/foo", "1")?.var("SLOT", "0/1"),
+        PackageSpec::new("sys-apps/a", "1")?.var("DEPEND", "sys-libs/foo:="),
+        PackageSpec::new("sys-apps/b", "1")?.var("DEPEND", "sys-apps/a"),
+        PackageSpec::new("sys-apps/c", "1")?.var("RDEPEND", "sys-apps/a"),
+        PackageSpec::new("sys-apps/d", "1")?.var("DEPEND", "sys-apps/c"),
+        PackageSpec::new("sys-apps/e", "1")?.var("DEPEND", "sys-libs/foo"),
+    ])?;
+
+    let rebuilds = analyze_subslot_impact(&packages, None, "sys-libs/foo", &Slot::new("0/1"))?;
+    let rebuilds = rebuilds
+        .iter()
+        .map(describe_subslot_rebuild)
+        .sorted()
+        .collect_vec();
+
+    assert_eq!(
+        rebuilds,
+        vec![
+            (
+                "sys-apps/a-1".into(),
+                format!("{:?}", [DependencyKind::BuildTarget])
+            ),
+            ("sys-apps/b-1".into(), "sys-apps/a-1".into()),
+            ("sys-apps/d-1".into(), "sys-apps/a-1".into()),
+        ]
+    );
+
+    // Slot operator dependencies resolved to another SLOT are not affected.
+    let rebuilds = analyze_subslot_impact(&packages, None, "sys-libs/foo", &Slot::new("1/1"))?;
+    assert!(rebuilds.is_empty());
+
+    Ok(())
+}
@@ -13,7 +13,20 @@ use std::{env::current_dir, path::PathBuf};
 use crate::digest_repo::digest_repo_main;
 use crate::dump_package::dump_package_main;
 use crate::dump_profile::dump_profile_main;
-use crate::generate_repo::generate_repo_main;
+use crate::dump_toolchains::dump_toolchains_main;
+use crate::fmt_deps::fmt_deps_main;
+use crate::generate_repo::{backend::OutputBackendKind, generate_repo_main};
+use crate::keyword_matrix::keyword_matrix_main;
+use crate::lint::lint_main;
+use crate::query::query_main;
+use crate::sdk_stages::sdk_stages_main;
+use crate::serve::serve_main;
+use crate::slow_report::print_slow_report;
+use crate::subslot_impact::subslot_impact_main;
+use crate::verify_distfiles::verify_distfiles_main;
+use crate::verify_workon_trees::verify_workon_trees_main;
+use crate::what_if::{check_pretend_versions, create_pretend_repos, what_if_main};
+use crate::why_depends::why_depends_main;
 
 use alchemist::data::Vars;
 use alchemist::fakechroot;
@@ -32,6 +45,7 @@ use alchemist::{
 };
 use anyhow::{bail, Context, Result};
 use clap::{ArgAction, Parser, Subcommand};
+use itertools::Itertools;
 use tempfile::TempDir;
 
 /// Returns true when running inside ChromeOS SDK chroot.
@@ -52,8 +66,12 @@ fn is_inside_chroot() -> Result<bool> {
 #[command(about = "Analyzes Portage trees", long_about = None)]
 pub struct Args {
     /// Board name to build packages for.
+    ///
+    /// generate-repo accepts this flag multiple times to generate several
+    /// boards in one repository. Each value may be given as `NAME:PROFILE` to
+    /// use a profile other than --profile for the board.
     #[arg(short = 'b', long, value_name = "NAME", global = true)]
-    board: Option<String>,
+    board: Vec<String>,
 
     /// Build packages for the host.
     #[arg(
@@ -142,11 +160,24 @@ pub struct Args {
     )]
     force_accept_9999_ebuilds: bool,
 
+    /// Translates paths under bind mounts of the current mount namespace to
+    /// their paths outside, as found in /proc/self/mountinfo.
+    ///
+    /// This is useful when running alchemist in a container whose generated
+    /// repository is used outside of it, e.g. from the host Bazel.
+    #[arg(long, global = true)]
+    translate_bind_mounts: bool,
+
     /// Path to the ChromiumOS source directory root.
     /// If unset, it is inferred from the current directory.
     #[arg(short = 's', long, value_name = "DIR", global = true)]
     source_dir: Option<String>,
 
+    /// Prints the N slowest ebuilds and eclasses to evaluate after running
+    /// the subcommand.
+    #[arg(long, value_name = "N", global = true)]
+    slow_report: Option<usize>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -163,6 +194,18 @@ pub enum Commands {
         #[command(flatten)]
         args: crate::dump_profile::Args,
     },
+    /// Prints the toolchains of the host and each board, and reports problems
+    /// in their toolchain.conf files.
+    DumpToolchains {
+        #[command(flatten)]
+        args: crate::dump_toolchains::Args,
+    },
+    /// Prints dependency expressions of packages in the canonical form, with
+    /// USE conditionals elided for their USE configurations.
+    FmtDeps {
+        #[command(flatten)]
+        args: crate::fmt_deps::Args,
+    },
     /// Generates a Bazel repository containing overlays and packages.
     GenerateRepo {
         /// Output directory path.
@@ -172,6 +215,10 @@ pub enum Commands {
         #[arg(long)]
         /// An output path for a json-encoded Vec<deps::Repository>.
         output_repos_json: PathBuf,
+
+        /// The format to generate package and image build files in.
+        #[arg(long, value_enum, default_value_t)]
+        backend: OutputBackendKind,
     },
     /// Generates a digest of the repository that can be used to indicate if
     /// any of the overlays, ebuilds, eclasses, etc have changed.
@@ -180,6 +227,65 @@ pub enum Commands {
         #[command(flatten)]
         args: crate::digest_repo::Args,
     },
+    /// Reports the version selected for each package per board and whether
+    /// it is accepted as stable, testing or only by
+    /// --force-accept-9999-ebuilds.
+    KeywordMatrix {
+        #[command(flatten)]
+        args: crate::keyword_matrix::Args,
+    },
+    /// Reports all packages failing to load or analyze and suspicious
+    /// overlay and profile entries, and fails if there are errors.
+    Lint {
+        #[command(flatten)]
+        args: crate::lint::Args,
+    },
+    /// Lists packages matching a query over their ebuild metadata, e.g.
+    /// inherited eclasses, IUSE and variables.
+    Query {
+        #[command(flatten)]
+        args: crate::query::Args,
+    },
+    /// Prints the packages installed by each SDK stage, why they are included
+    /// and how they differ from the previous stage.
+    SdkStages {
+        #[command(flatten)]
+        args: crate::sdk_stages::Args,
+    },
+    /// Keeps packages loaded in memory and answers requests over a Unix
+    /// domain socket, re-evaluating ebuilds as files change.
+    Serve {
+        #[command(flatten)]
+        args: crate::serve::Args,
+    },
+    /// Lists packages that need to be rebuilt when the SLOT of a package
+    /// changes, e.g. on bumping a library's SONAME.
+    SubslotImpact {
+        #[command(flatten)]
+        args: crate::subslot_impact::Args,
+    },
+    /// Checks that the distfiles needed by packages exist in a local
+    /// directory with the sizes and hashes recorded in Manifest files.
+    VerifyDistfiles {
+        #[command(flatten)]
+        args: crate::verify_distfiles::Args,
+    },
+    /// Checks that the trees pinned by CROS_WORKON_TREE of stable cros-workon
+    /// ebuilds match the local git checkouts.
+    VerifyWorkonTrees {
+        #[command(flatten)]
+        args: crate::verify_workon_trees::Args,
+    },
+    /// Reports packages affected by a hypothetical uprev or USE flag change.
+    WhatIf {
+        #[command(flatten)]
+        args: crate::what_if::Args,
+    },
+    /// Prints the shortest dependency path from one package to another.
+    WhyDepends {
+        #[command(flatten)]
+        args: crate::why_depends::Args,
+    },
 }
 
 fn default_source_dir() -> Result<PathBuf> {
@@ -274,7 +380,7 @@ fn setup_tools() -> Result<TempDir> {
     let hermetic_dir = alchemist.parent().unwrap();
     // Check if we're using the hermetic launcher.
     // The hermetic launcher requires mktemp, tail, and tar, none of which
-    // exist in the environment which ver_rs and ver_test run.
+    // exist in the environment which ver_cut, ver_rs and ver_test run.
     if hermetic_dir.join("_real_binary").is_file() {
         // Instead of running the self-extracting binary, run the already
         // extracted binary. This means we don't need those tools to exist.
@@ -299,12 +405,29 @@ fn setup_tools() -> Result<TempDir> {
 
     let tools_dir = tempfile::tempdir()?;
 
+    fs::symlink(&alchemist, tools_dir.path().join("ver_cut"))?;
     fs::symlink(&alchemist, tools_dir.path().join("ver_test"))?;
     fs::symlink(&alchemist, tools_dir.path().join("ver_rs"))?;
 
     Ok(tools_dir)
 }
 
+/// Converts the `--use-flags` value into USE flag updates applied to all
+/// packages.
+fn use_flags_to_updates(use_flags: Option<&String>) -> Vec<UseUpdate> {
+    use_flags
+        .map(|flags| UseUpdate {
+            kind: UseUpdateKind::Set,
+            filter: UseUpdateFilter {
+                atom: None,
+                stable_only: false,
+            },
+            use_tokens: flags.clone(),
+        })
+        .into_iter()
+        .collect()
+}
+
 /// Container that contains all the data structures for a specific board.
 pub struct TargetData {
     pub sysroot: PathBuf,
@@ -324,7 +447,7 @@ fn load_board(
     board: &str,
     profile_name: &str,
     root_dir: &Path,
-    use_flags: Option<String>,
+    use_updates: Vec<UseUpdate>,
     use_portage_site_configs: bool,
     force_accept_9999_ebuilds: bool,
 ) -> Result<TargetData> {
@@ -344,17 +467,10 @@ fn load_board(
             Box::new(site_settings) as Box<dyn ConfigSource>,
             Box::new(override_source) as Box<dyn ConfigSource>,
         ];
-        if let Some(flags) = use_flags {
+        if !use_updates.is_empty() {
             let use_override = SimpleConfigSource::new(vec![ConfigNode {
                 sources: vec![],
-                value: ConfigNodeValue::Uses(vec![UseUpdate {
-                    kind: UseUpdateKind::Set,
-                    filter: UseUpdateFilter {
-                        atom: None,
-                        stable_only: false,
-                    },
-                    use_tokens: flags,
-                }]),
+                value: ConfigNodeValue::Uses(use_updates),
             }]);
             config_sources.push(Box::new(use_override) as Box<dyn ConfigSource>);
         }
@@ -389,12 +505,26 @@ fn load_board(
 }
 
 pub fn alchemist_main(args: Args) -> Result<()> {
-    if args.board.is_none() && !args.host {
+    if args.board.is_empty() && !args.host {
         bail!("Either --board or --host should be specified.")
     }
-    if args.board.is_some() && args.host {
+    if !args.board.is_empty() && args.host {
         bail!("--board and --host shouldn't be specified together.");
     }
+    if args.board.len() > 1
+        && !matches!(
+            args.command,
+            Commands::DumpToolchains { .. }
+                | Commands::GenerateRepo { .. }
+                | Commands::KeywordMatrix { .. }
+                | Commands::SdkStages { .. }
+        )
+    {
+        bail!(
+            "--board can be specified multiple times only for dump-toolchains, generate-repo, \
+             keyword-matrix and sdk-stages."
+        );
+    }
 
     let source_dir = match args.source_dir {
         Some(s) => PathBuf::from(s),
@@ -407,19 +537,39 @@ pub fn alchemist_main(args: Args) -> Result<()> {
         profile: &args.host_profile,
     };
 
-    let board_target = if let Some(board) = args.board.as_ref() {
-        let profile = &args.profile;
+    let board_targets = args
+        .board
+        .iter()
+        .map(|value| {
+            let (board, profile) = value
+                .split_once(':')
+                .unwrap_or((value.as_str(), args.profile.as_str()));
+
+            // We don't support a board ROOT with two different profiles.
+            if board == host_target.board && profile != host_target.profile {
+                bail!(
+                    "--profile ({}) must match --host-profile ({})",
+                    profile,
+                    host_target.profile
+                );
+            }
 
-        // We don't support a board ROOT with two different profiles.
-        if board == host_target.board && profile != host_target.profile {
-            bail!(
-                "--profile ({}) must match --host-profile ({})",
-                profile,
-                host_target.profile
-            );
-        }
+            Ok(fakechroot::BoardTarget { board, profile })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if let Some(board) = board_targets
+        .iter()
+        .map(|board_target| board_target.board)
+        .duplicates()
+        .next()
+    {
+        bail!("--board {board} is specified more than once.");
+    }
 
-        Some(fakechroot::BoardTarget { board, profile })
+    // Read mounts before entering a fake chroot, which replaces them.
+    let bind_mounts = if args.translate_bind_mounts {
+        Some(PathTranslator::from_self_mountinfo()?)
     } else {
         None
     };
@@ -429,25 +579,41 @@ pub fn alchemist_main(args: Args) -> Result<()> {
         // TODO: What do we do here?
         PathTranslator::noop()
     } else {
-        let targets = if let Some(board_target) = board_target.as_ref() {
-            if board_target.board == host_target.board {
-                vec![&host_target]
-            } else {
-                vec![board_target, &host_target]
-            }
-        } else {
-            vec![&host_target]
-        };
+        let targets = board_targets
+            .iter()
+            .filter(|board_target| board_target.board != host_target.board)
+            .chain(std::iter::once(&host_target))
+            .collect_vec();
         enter_fake_chroot(&targets, &source_dir)?
     };
+    let translator = match bind_mounts {
+        Some(bind_mounts) => translator.then(&bind_mounts),
+        None => translator,
+    };
 
     let tools_dir = setup_tools()?;
 
-    let target_data = if let Some(board_target) = board_target {
-        let root_dir = Path::new("/build").join(board_target.board);
-        if is_inside_chroot()? && !root_dir.try_exists()? {
-            bail!(
-                "\n\
+    // The what-if subcommand loads a second copy of the boards with pretend
+    // overrides applied. The overridden ebuilds are written to a temporary
+    // directory that must outlive the evaluator.
+    let pretend_overrides = match &args.command {
+        Commands::WhatIf { args: local_args } => {
+            Some((local_args.parse_overrides()?, tempfile::tempdir()?))
+        }
+        _ => None,
+    };
+
+    // A single board is loaded under the "board" name so that the generated
+    // repository keeps its layout. Multiple boards are loaded under their own
+    // names so that their generated targets don't collide.
+    let multi_board = board_targets.len() > 1;
+    let target_data = board_targets
+        .into_iter()
+        .map(|board_target| -> Result<_> {
+            let root_dir = Path::new("/build").join(board_target.board);
+            if is_inside_chroot()? && !root_dir.try_exists()? {
+                bail!(
+                    "\n\
                 *****\n\
                 \t\tYou are running inside the CrOS SDK and `{}` doesn't exist.\n\
                 \n\
@@ -456,18 +622,22 @@ pub fn alchemist_main(args: Args) -> Result<()> {
                 \t\tWhen building public artifacts from an internal manifest, add --public.\n\
                 \n\
                 *****",
-                root_dir.display(),
-                board_target.board,
-                board_target.profile,
-            );
-        }
+                    root_dir.display(),
+                    board_target.board,
+                    board_target.profile,
+                );
+            }
 
-        let repos = RepositorySet::load("board", &root_dir)?;
+            let name = if multi_board {
+                board_target.board
+            } else {
+                "board"
+            };
+            let repos = RepositorySet::load(name, &root_dir)?;
 
-        Some((root_dir, repos, board_target))
-    } else {
-        None
-    };
+            Ok((root_dir, repos, board_target))
+        })
+        .collect::<Result<Vec<_>>>()?;
 
     let host_data = {
         let root_dir = Path::new("/build").join(host_target.board);
@@ -491,70 +661,226 @@ pub fn alchemist_main(args: Args) -> Result<()> {
         (root_dir, repos, host_target)
     };
 
+    let pretend_repos = match &pretend_overrides {
+        Some((overrides, pretend_dir)) => {
+            check_pretend_versions(
+                &target_data
+                    .iter()
+                    .map(|x| &x.1)
+                    .chain(std::iter::once(&host_data.1))
+                    .collect_vec(),
+                &overrides.versions,
+            )?;
+            let target_repos = target_data
+                .first()
+                .map(|(_, repos, _)| {
+                    create_pretend_repos(repos, &overrides.versions, pretend_dir.path())
+                })
+                .transpose()?;
+            let host_repos =
+                create_pretend_repos(&host_data.1, &overrides.versions, pretend_dir.path())?;
+            Some((target_repos, host_repos))
+        }
+        None => None,
+    };
+    let pretend_repo_sets = pretend_repos
+        .iter()
+        .flat_map(|(target_repos, host_repos)| [target_repos.as_ref(), Some(host_repos)]);
+
     // We share an evaluator between both config ROOTS so we only have to parse
     // the ebuilds once.
-    let evaluator = Arc::new(CachedEBuildEvaluator::new(
-        [target_data.as_ref().map(|x| &x.1), Some(&host_data.1)]
-            .into_iter()
-            .flatten()
-            .flat_map(|x| x.get_repos())
-            .cloned()
-            .collect(),
-        tools_dir.path(),
-    ));
-
-    let target = if let Some((root_dir, repos, board_target)) = target_data {
-        Some(load_board(
-            repos,
-            &evaluator,
-            board_target.board,
-            board_target.profile,
-            &root_dir,
-            args.use_flags.clone(),
-            args.use_portage_site_configs,
-            args.force_accept_9999_ebuilds,
-        )?)
-    } else {
-        None
+    let evaluator = Arc::new(
+        CachedEBuildEvaluator::new(
+            target_data
+                .iter()
+                .map(|x| Some(&x.1))
+                .chain([Some(&host_data.1)])
+                .chain(pretend_repo_sets)
+                .flatten()
+                .flat_map(|x| x.get_repos())
+                .cloned()
+                .collect(),
+            tools_dir.path(),
+        )
+        .with_timings(args.slow_report.is_some()),
+    );
+
+    let use_updates = use_flags_to_updates(args.use_flags.as_ref());
+
+    // Loads the boards again with the what-if overrides applied.
+    let pretend = match (pretend_repos, &pretend_overrides) {
+        (Some((pretend_target_repos, pretend_host_repos)), Some((overrides, _))) => {
+            let pretend_use_updates = use_updates
+                .iter()
+                .chain(&overrides.use_updates)
+                .cloned()
+                .collect_vec();
+            let pretend_target = match (pretend_target_repos, target_data.first()) {
+                (Some(repos), Some((root_dir, _, board_target))) => Some(load_board(
+                    repos,
+                    &evaluator,
+                    board_target.board,
+                    board_target.profile,
+                    root_dir,
+                    pretend_use_updates.clone(),
+                    args.use_portage_site_configs,
+                    args.force_accept_9999_ebuilds,
+                )?),
+                _ => None,
+            };
+            let pretend_host = load_board(
+                pretend_host_repos,
+                &evaluator,
+                host_data.2.board,
+                host_data.2.profile,
+                &host_data.0,
+                pretend_use_updates,
+                args.use_portage_site_configs,
+                args.force_accept_9999_ebuilds,
+            )?;
+            Some((pretend_host, pretend_target))
+        }
+        _ => None,
     };
 
-    #[allow(clippy::match_single_binding)]
-    let host = match host_data {
-        (root_dir, repos, host_target) => load_board(
+    // Loads the boards. The serve subcommand calls this again with
+    // `reload_repos` set to pick up config changes, including changes to
+    // metadata/layout.conf of repositories.
+    let load_targets = |reload_repos: bool| -> Result<(TargetData, Vec<TargetData>)> {
+        let targets = target_data
+            .iter()
+            .map(|(root_dir, repos, board_target)| {
+                let repos = if reload_repos {
+                    let name = if multi_board {
+                        board_target.board
+                    } else {
+                        "board"
+                    };
+                    RepositorySet::load(name, root_dir)?
+                } else {
+                    repos.clone()
+                };
+                load_board(
+                    repos,
+                    &evaluator,
+                    board_target.board,
+                    board_target.profile,
+                    root_dir,
+                    use_updates.clone(),
+                    args.use_portage_site_configs,
+                    args.force_accept_9999_ebuilds,
+                )
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let (root_dir, repos, host_target) = &host_data;
+        let repos = if reload_repos {
+            RepositorySet::load("host", root_dir)?
+        } else {
+            repos.clone()
+        };
+        let host = load_board(
             repos,
             &evaluator,
             host_target.board,
             host_target.profile,
-            &root_dir,
-            args.use_flags,
+            root_dir,
+            use_updates.clone(),
             args.use_portage_site_configs,
             args.force_accept_9999_ebuilds,
-        )?,
+        )?;
+
+        Ok((host, targets))
     };
+    let (host, targets) = load_targets(false)?;
+
+    // All subcommands except generate-repo and keyword-matrix handle at most
+    // one board.
+    let target = targets.first();
 
     match args.command {
         Commands::DumpPackage { args: local_args } => {
-            dump_package_main(&host, target.as_ref(), local_args)?;
+            dump_package_main(&host, target, local_args)?;
         }
         Commands::DumpProfile { args: local_args } => {
-            dump_profile_main(&target.unwrap_or(host), local_args)?;
+            dump_profile_main(target.unwrap_or(&host), local_args)?;
+        }
+        Commands::DumpToolchains { args: local_args } => {
+            dump_toolchains_main(&host, &targets, local_args)?;
+        }
+        Commands::FmtDeps { args: local_args } => {
+            fmt_deps_main(target.unwrap_or(&host), local_args)?;
         }
         Commands::GenerateRepo {
             output_dir,
             output_repos_json,
+            backend,
         } => {
             generate_repo_main(
                 &host,
-                target.as_ref(),
+                &targets,
                 &translator,
+                backend.create().as_ref(),
                 &src_dir,
                 &output_dir,
                 &output_repos_json,
             )?;
         }
         Commands::DigestRepo { args: local_args } => {
-            digest_repo_main(&host, target.as_ref(), local_args)?;
+            digest_repo_main(&host, target, local_args)?;
+        }
+        Commands::KeywordMatrix { args: local_args } => {
+            keyword_matrix_main(&host, &targets, local_args)?;
+        }
+        Commands::Lint { args: local_args } => {
+            lint_main(&host, target, &src_dir, local_args)?;
+        }
+        Commands::Query { args: local_args } => {
+            query_main(&host, target, local_args)?;
+        }
+        Commands::SdkStages { args: local_args } => {
+            sdk_stages_main(&host, &targets, &src_dir, local_args)?;
+        }
+        Commands::Serve { args: local_args } => {
+            let load_single_target =
+                || load_targets(true).map(|(host, targets)| (host, targets.into_iter().next()));
+            serve_main(
+                host,
+                targets.into_iter().next(),
+                &evaluator,
+                &load_single_target,
+                &translator,
+                &src_dir,
+                local_args,
+            )?;
+        }
+        Commands::SubslotImpact { args: local_args } => {
+            subslot_impact_main(&host, target, &src_dir, local_args)?;
+        }
+        Commands::VerifyDistfiles { args: local_args } => {
+            verify_distfiles_main(&host, target, &src_dir, local_args)?;
+        }
+        Commands::VerifyWorkonTrees { args: local_args } => {
+            verify_workon_trees_main(&host, target, &src_dir, local_args)?;
+        }
+        Commands::WhatIf { .. } => {
+            let (pretend_host, pretend_target) =
+                pretend.expect("pretend data is loaded for what-if");
+            what_if_main(
+                &host,
+                target,
+                &pretend_host,
+                pretend_target.as_ref(),
+                &src_dir,
+            )?;
         }
+        Commands::WhyDepends { args: local_args } => {
+            why_depends_main(&host, target, local_args)?;
+        }
+    }
+
+    if let Some(count) = args.slow_report {
+        print_slow_report(&evaluator.take_timings(), count);
     }
 
     Ok(())
@@ -2,18 +2,31 @@
 // Use of this source code is governed by a BSD-style license that can be
 // found in the LICENSE file.
 
+use alchemist::lint::lint_use_configs;
 use anyhow::{Context, Result};
 use itertools::Itertools;
 
-use crate::alchemist::TargetData;
+use crate::{alchemist::TargetData, lint::print_report};
 
 #[derive(clap::Args, Clone, Debug)]
 pub struct Args {
+    /// Instead of dumping variables, reports USE flags in package.use,
+    /// use.mask and such that are missing in IUSE or overridden by later
+    /// entries, and atoms matching no package.
+    #[arg(long)]
+    check_use: bool,
+
     /// Environment variables to dump.
     vars: Option<Vec<String>>,
 }
 
 pub fn dump_profile_main(target: &TargetData, args: Args) -> Result<()> {
+    if args.check_use {
+        let packages = target.resolver.find_all_packages()?;
+        print_report(&lint_use_configs(&target.config, &packages));
+        return Ok(());
+    }
+
     let map = target.config.env();
 
     let iter = if let Some(vars) = &args.vars {
@@ -0,0 +1,101 @@
+// Copyright 2024 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+use alchemist::toolchain::{Toolchain, ToolchainConfig, ToolchainIssue};
+use anyhow::{bail, Result};
+use itertools::Itertools;
+
+use crate::alchemist::TargetData;
+
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    /// Exits with a non-zero status if any toolchain configuration issue is
+    /// found.
+    #[arg(long)]
+    fail_on_issues: bool,
+}
+
+fn format_toolchain(toolchain: &Toolchain, is_default: bool) -> String {
+    let mut line = toolchain.name.clone();
+    line.push_str(&format!(
+        " (portage arch: {})",
+        toolchain.portage_arch().unwrap_or("unknown")
+    ));
+    if is_default {
+        line.push_str(" [default]");
+    }
+    if let Some(options) = &toolchain.options {
+        if let Some(sdk) = options.sdk {
+            line.push_str(&format!(" sdk={}", sdk));
+        }
+        if let Some(crossdev) = &options.crossdev {
+            line.push_str(&format!(" crossdev={}", crossdev));
+        }
+    }
+    line
+}
+
+fn print_toolchains(board: &str, config: &ToolchainConfig, issues: &[ToolchainIssue]) {
+    println!("{}:", board);
+    for (index, toolchain) in config.toolchains.iter().enumerate() {
+        println!(
+            "  {}",
+            format_toolchain(toolchain, config.default_index == Some(index))
+        );
+    }
+    for issue in issues {
+        match &issue.toolchain {
+            Some(toolchain) => println!("  ERROR: {}: {}", toolchain, issue.message),
+            None => println!("  ERROR: {}", issue.message),
+        }
+    }
+}
+
+/// The entry point of "dump-toolchains" subcommand.
+pub fn dump_toolchains_main(host: &TargetData, targets: &[TargetData], args: Args) -> Result<()> {
+    // Cross compilers are always built for the host, so resolve them there.
+    let native_triple = host.config.env().get("CHOST").cloned().unwrap_or_default();
+
+    let mut total_issues = 0;
+    for target in std::iter::once(host).chain(targets) {
+        let mut issues = target.toolchains.validate();
+        issues.extend(
+            target
+                .toolchains
+                .validate_cross_packages(&host.resolver, &native_triple)?,
+        );
+        let issues = issues.into_iter().sorted().collect_vec();
+
+        print_toolchains(&target.board, &target.toolchains, &issues);
+        total_issues += issues.len();
+    }
+
+    if args.fail_on_issues && total_issues > 0 {
+        bail!("Found {} toolchain configuration issues", total_issues);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn test_format_toolchain() -> Result<()> {
+        assert_eq!(
+            format_toolchain(&Toolchain::from_str("x86_64-cros-linux-gnu")?, true),
+            "x86_64-cros-linux-gnu (portage arch: amd64) [default]"
+        );
+        assert_eq!(
+            format_toolchain(
+                &Toolchain::from_str(r#"arm-none-eabi {"default": false, "sdk": false}"#)?,
+                false
+            ),
+            "arm-none-eabi (portage arch: arm) sdk=false"
+        );
+        Ok(())
+    }
+}
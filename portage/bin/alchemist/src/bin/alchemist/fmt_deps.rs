@@ -0,0 +1,222 @@
+// Copyright 2024 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+use std::str::FromStr;
+
+use alchemist::{
+    data::UseMap,
+    dependency::{
+        algorithm::{elide_use_conditions, simplify},
+        package::{PackageAtom, PackageDependencyMeta},
+        requse::RequiredUseDependency,
+        restrict::RestrictDependencyMeta,
+        uri::UriDependencyMeta,
+        Dependency, DependencyMeta,
+    },
+    ebuild::PackageDetails,
+};
+use anyhow::{bail, Context, Result};
+use itertools::Itertools;
+
+use crate::alchemist::TargetData;
+
+/// Ebuild variables holding dependency expressions.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Eq, PartialEq, strum_macros::AsRefStr)]
+#[value(rename_all = "SCREAMING_SNAKE_CASE")]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+enum Variable {
+    Depend,
+    Rdepend,
+    Bdepend,
+    Idepend,
+    Pdepend,
+    RequiredUse,
+    SrcUri,
+    Restrict,
+}
+
+impl Variable {
+    const ALL: [Self; 8] = [
+        Self::Depend,
+        Self::Rdepend,
+        Self::Bdepend,
+        Self::Idepend,
+        Self::Pdepend,
+        Self::RequiredUse,
+        Self::SrcUri,
+        Self::Restrict,
+    ];
+}
+
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    /// Variables to print, e.g. `--var RDEPEND`. Can be specified multiple
+    /// times. Defaults to all dependency variables.
+    #[arg(long = "var", value_enum, value_name = "VARIABLE")]
+    vars: Vec<Variable>,
+
+    /// Overrides USE flags of the packages, e.g. `foo,-bar`.
+    #[arg(long = "use", value_name = "FLAGS")]
+    use_flags: Option<String>,
+
+    /// Prints the expressions as written in ebuilds, without eliding USE
+    /// conditionals or simplifying.
+    #[arg(long)]
+    raw: bool,
+
+    /// Packages to print, e.g. `sys-apps/hello`. The best version is used.
+    #[arg(required = true)]
+    packages: Vec<String>,
+}
+
+/// Applies comma-separated USE flag overrides to `use_map`. Only flags in
+/// IUSE can be overridden.
+fn apply_use_flags(use_map: &mut UseMap, flags: &str) -> Result<()> {
+    for token in flags.split(',').filter(|token| !token.is_empty()) {
+        let (name, value) = match token.strip_prefix('-') {
+            Some(name) => (name, false),
+            None => (token.strip_prefix('+').unwrap_or(token), true),
+        };
+        match use_map.get_mut(name) {
+            Some(current) => *current = value,
+            None => bail!("USE flag {} is not in IUSE", name),
+        }
+    }
+    Ok(())
+}
+
+/// Formats a dependency expression in the canonical form. If `use_map` is
+/// given, USE conditionals are elided and the expression is simplified.
+fn format_dependency<M: DependencyMeta>(raw: &str, use_map: Option<&UseMap>) -> Result<String>
+where
+    Dependency<M>: FromStr<Err = anyhow::Error>,
+{
+    let deps: Dependency<M> = raw.parse()?;
+    let deps = match use_map {
+        Some(use_map) => simplify(elide_use_conditions(deps, use_map).unwrap_or_default()),
+        None => deps,
+    };
+    Ok(deps.to_pretty_string())
+}
+
+fn format_variable(raw: &str, variable: Variable, use_map: Option<&UseMap>) -> Result<String> {
+    match variable {
+        Variable::Depend
+        | Variable::Rdepend
+        | Variable::Bdepend
+        | Variable::Idepend
+        | Variable::Pdepend => format_dependency::<PackageDependencyMeta>(raw, use_map),
+        // USE flags are what REQUIRED_USE constrains, so print it as is.
+        Variable::RequiredUse => Ok(raw.parse::<RequiredUseDependency>()?.to_pretty_string()),
+        Variable::SrcUri => format_dependency::<UriDependencyMeta>(raw, use_map),
+        Variable::Restrict => format_dependency::<RestrictDependencyMeta>(raw, use_map),
+    }
+}
+
+fn format_package(
+    details: &PackageDetails,
+    variables: &[Variable],
+    use_map: Option<&UseMap>,
+) -> Result<String> {
+    let mut output = String::new();
+    for variable in variables {
+        let raw = details
+            .metadata
+            .vars
+            .get_scalar_or_default(variable.as_ref())?;
+        let formatted = format_variable(raw, *variable, use_map)
+            .with_context(|| format!("Failed to parse {}", variable.as_ref()))?;
+        output.push_str(&format!("{}:\n", variable.as_ref()));
+        for line in formatted.lines() {
+            output.push_str(&format!("  {}\n", line));
+        }
+    }
+    Ok(output)
+}
+
+/// The entry point of "fmt-deps" subcommand.
+pub fn fmt_deps_main(target: &TargetData, args: Args) -> Result<()> {
+    let variables = if args.vars.is_empty() {
+        Variable::ALL.to_vec()
+    } else {
+        args.vars
+    };
+
+    for raw_atom in &args.packages {
+        let atom: PackageAtom = raw_atom.parse()?;
+        let details = target
+            .resolver
+            .find_best_package(&atom)?
+            .with_context(|| format!("No package satisfies {}", atom))?;
+
+        let mut use_map = details.use_map.clone();
+        if let Some(flags) = &args.use_flags {
+            apply_use_flags(&mut use_map, flags)
+                .with_context(|| format!("Failed to apply --use to {}", atom))?;
+        }
+
+        println!(
+            "=======\t{}-{} (USE: {})",
+            details.as_basic_data().package_name,
+            details.as_basic_data().version,
+            use_map
+                .iter()
+                .sorted()
+                .map(|(name, value)| format!("{}{}", if *value { "+" } else { "-" }, name))
+                .join(" ")
+        );
+        print!(
+            "{}",
+            format_package(&details, &variables, (!args.raw).then_some(&use_map))?
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_use_flags() -> Result<()> {
+        let mut use_map = UseMap::from_iter([
+            ("foo".into(), false),
+            ("bar".into(), true),
+            ("baz".into(), false),
+        ]);
+        apply_use_flags(&mut use_map, "foo,-bar,+baz")?;
+        assert_eq!(
+            use_map,
+            UseMap::from_iter([
+                ("foo".into(), true),
+                ("bar".into(), false),
+                ("baz".into(), true),
+            ])
+        );
+
+        assert!(apply_use_flags(&mut use_map, "qux").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_variable() -> Result<()> {
+        let raw = "a/b foo? ( c/d ) !foo? ( || ( e/f g/h ) )";
+        let use_map = UseMap::from_iter([("foo".into(), false)]);
+
+        assert_eq!(
+            format_variable(raw, Variable::Depend, Some(&use_map))?,
+            "a/b\n|| (\n  e/f\n  g/h\n)"
+        );
+        assert_eq!(
+            format_variable(raw, Variable::Depend, None)?,
+            "a/b\nfoo? (\n  c/d\n)\n!foo? (\n  || (\n    e/f\n    g/h\n  )\n)"
+        );
+        assert_eq!(
+            format_variable("test? ( test ) strip", Variable::Restrict, Some(&use_map))?,
+            "strip"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+// Copyright 2024 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+use std::{fs::File, io::Write, path::Path};
+
+use anyhow::Result;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use tera::Tera;
+
+use super::{
+    common::{escape_starlark_string, AUTOGENERATE_NOTICE},
+    internal::packages::PackageBuildContext,
+    public::{ImagesBuildContext, PublicPackageBuildContext},
+};
+
+lazy_static! {
+    static ref TEMPLATES: Tera = {
+        let mut tera: Tera = Default::default();
+        tera.add_raw_template(
+            "internal/package.BUILD.bazel",
+            include_str!("internal/packages/templates/package.BUILD.bazel"),
+        )
+        .unwrap();
+        tera.add_raw_template(
+            "public/package.BUILD.bazel",
+            include_str!("public/templates/package.BUILD.bazel"),
+        )
+        .unwrap();
+        tera.add_raw_template(
+            "public/images.BUILD.bazel",
+            include_str!("public/templates/images.BUILD.bazel"),
+        )
+        .unwrap();
+        tera.autoescape_on(vec![".bazel"]);
+        tera.set_escape_fn(escape_starlark_string);
+        tera
+    };
+}
+
+/// Renders the build graph computed by generate-repo into files understood by
+/// a build executor.
+///
+/// Each method is called with the directory to render into, which already
+/// exists. Other outputs of generate-repo, e.g. overlays and SDKs, are always
+/// rendered for Bazel.
+pub trait OutputBackend: Sync {
+    /// Renders the ebuild targets of a package under `//internal/packages`.
+    fn render_package(&self, context: &PackageBuildContext, output_dir: &Path) -> Result<()>;
+
+    /// Renders the public aliases of a package, e.g. under `@portage//target`.
+    fn render_public_package(
+        &self,
+        context: &PublicPackageBuildContext,
+        output_dir: &Path,
+    ) -> Result<()>;
+
+    /// Renders the public image targets of a board.
+    fn render_images(&self, context: &ImagesBuildContext, output_dir: &Path) -> Result<()>;
+}
+
+/// Selects an [`OutputBackend`] on the command line.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default)]
+pub enum OutputBackendKind {
+    /// Generates `BUILD.bazel` files.
+    #[default]
+    Bazel,
+    /// Generates `build.json` files containing the build graph.
+    Json,
+}
+
+impl OutputBackendKind {
+    pub fn create(self) -> Box<dyn OutputBackend> {
+        match self {
+            Self::Bazel => Box::new(BazelBackend),
+            Self::Json => Box::new(JsonBackend),
+        }
+    }
+}
+
+/// Renders `BUILD.bazel` files from Tera templates.
+pub struct BazelBackend;
+
+impl BazelBackend {
+    fn render(template: &str, context: impl Serialize, output_dir: &Path) -> Result<()> {
+        let mut file = File::create(output_dir.join("BUILD.bazel"))?;
+        file.write_all(AUTOGENERATE_NOTICE.as_bytes())?;
+        TEMPLATES.render_to(template, &tera::Context::from_serialize(context)?, file)?;
+        Ok(())
+    }
+}
+
+impl OutputBackend for BazelBackend {
+    fn render_package(&self, context: &PackageBuildContext, output_dir: &Path) -> Result<()> {
+        Self::render("internal/package.BUILD.bazel", context, output_dir)
+    }
+
+    fn render_public_package(
+        &self,
+        context: &PublicPackageBuildContext,
+        output_dir: &Path,
+    ) -> Result<()> {
+        Self::render("public/package.BUILD.bazel", context, output_dir)
+    }
+
+    fn render_images(&self, context: &ImagesBuildContext, output_dir: &Path) -> Result<()> {
+        Self::render("public/images.BUILD.bazel", context, output_dir)
+    }
+}
+
+/// Writes the contexts passed to the backend as `build.json` files, for
+/// executors other than Bazel to consume.
+///
+/// Labels in the JSON files still follow the Bazel syntax, e.g.
+/// `//internal/packages/stage2/host/chromiumos/sys-libs/zlib:1.2.13`, which
+/// identify a target by its directory relative to the output directory and
+/// its name.
+pub struct JsonBackend;
+
+impl JsonBackend {
+    fn render(kind: &str, context: impl Serialize, output_dir: &Path) -> Result<()> {
+        #[derive(Serialize)]
+        struct Document<T> {
+            kind: String,
+            #[serde(flatten)]
+            context: T,
+        }
+
+        let mut file = File::create(output_dir.join("build.json"))?;
+        serde_json::to_writer_pretty(
+            &mut file,
+            &Document {
+                kind: kind.to_owned(),
+                context,
+            },
+        )?;
+        file.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+impl OutputBackend for JsonBackend {
+    fn render_package(&self, context: &PackageBuildContext, output_dir: &Path) -> Result<()> {
+        Self::render("package", context, output_dir)
+    }
+
+    fn render_public_package(
+        &self,
+        context: &PublicPackageBuildContext,
+        output_dir: &Path,
+    ) -> Result<()> {
+        Self::render("public_package", context, output_dir)
+    }
+
+    fn render_images(&self, context: &ImagesBuildContext, output_dir: &Path) -> Result<()> {
+        Self::render("images", context, output_dir)
+    }
+}
@@ -44,6 +44,12 @@ enum Repository {
         tag: String,
         internal: bool,
     },
+    #[allow(clippy::enum_variant_names)]
+    GitCommitRepository {
+        name: String,
+        remotes: Vec<String>,
+        commit: String,
+    },
 }
 
 pub fn generate_deps_file(all_sources: &[&PackageSources], out: &Path) -> Result<()> {
@@ -121,14 +127,29 @@ fn generate_deps(all_sources: &[&PackageSources]) -> Result<Vec<Repository>> {
             },
         });
 
-    Ok(unique_dists.chain(repos).chain(chrome).collect())
+    let git_repos = all_sources
+        .iter()
+        .flat_map(|sources| &sources.git_sources)
+        .unique_by(|source| &source.name)
+        .sorted_by(|a, b| a.name.cmp(&b.name))
+        .map(|source| Repository::GitCommitRepository {
+            name: source.name.clone(),
+            remotes: source.repo_uris.clone(),
+            commit: source.commit.clone(),
+        });
+
+    Ok(unique_dists
+        .chain(repos)
+        .chain(chrome)
+        .chain(git_repos)
+        .collect())
 }
 
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
 
-    use alchemist::analyze::source::{PackageDistSource, PackageSources};
+    use alchemist::analyze::source::{PackageDistSource, PackageGitSource, PackageSources};
     use pretty_assertions::assert_eq;
     use url::Url;
 
@@ -141,6 +162,7 @@ mod tests {
         let cipd_sources = PackageSources {
             local_sources: vec![],
             repo_sources: vec![],
+            git_sources: vec![],
             dist_sources: vec![PackageDistSource {
                 urls: vec![Url::parse("cipd://skia/tools/goldctl/linux-amd64:0ov3TU").unwrap()],
                 filename: "goldctl-2021.03.31-amd64.zip".to_owned(),
@@ -152,6 +174,7 @@ mod tests {
         let gs_sources = PackageSources {
             local_sources: vec![],
             repo_sources: vec![],
+            git_sources: vec![],
             dist_sources: vec![PackageDistSource {
                 urls: vec![Url::parse("gs://secret-bucket/secret-file.tar.gz").unwrap()],
                 filename: "secret-file.tar.gz".to_owned(),
@@ -163,6 +186,7 @@ mod tests {
         let https_sources = PackageSources {
             local_sources: vec![],
             repo_sources: vec![],
+            git_sources: vec![],
             dist_sources: vec![
                 PackageDistSource {
                     urls: vec![
@@ -209,4 +233,37 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn generate_deps_git_sources() -> Result<()> {
+        let git_source = PackageGitSource {
+            name: "git-github.com-foo-bar-0123456789abcdef0123456789abcdef01234567".to_owned(),
+            repo_uris: vec!["https://github.com/foo/bar.git".to_owned()],
+            commit: "0123456789abcdef0123456789abcdef01234567".to_owned(),
+        };
+        let sources = PackageSources {
+            local_sources: vec![],
+            repo_sources: vec![],
+            git_sources: vec![git_source.clone()],
+            dist_sources: vec![],
+        };
+
+        // Packages sharing the same pinned repository generate one repository.
+        let repos = generate_deps(&[&sources, &sources])?;
+        let actual = serde_json::to_string_pretty(&repos)?;
+        let expected = r#"[
+  {
+    "GitCommitRepository": {
+      "name": "git-github.com-foo-bar-0123456789abcdef0123456789abcdef01234567",
+      "remotes": [
+        "https://github.com/foo/bar.git"
+      ],
+      "commit": "0123456789abcdef0123456789abcdef01234567"
+    }
+  }
+]"#;
+        assert_eq!(actual, expected);
+
+        Ok(())
+    }
 }
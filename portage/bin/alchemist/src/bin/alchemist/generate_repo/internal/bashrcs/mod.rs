@@ -101,7 +101,7 @@ fn generate_bashrc_build_file(output_file: &Path, bashrcs: &[&Path]) -> Result<(
 pub fn generate_internal_bashrcs(
     translator: &PathTranslator,
     host: &TargetData,
-    target: Option<&TargetData>,
+    targets: &[TargetData],
     output_dir: &Path,
 ) -> Result<()> {
     let output_overlays_dir = output_dir.join("internal/bashrcs");
@@ -109,22 +109,24 @@ pub fn generate_internal_bashrcs(
         .repos
         .get_repos()
         .into_iter()
-        .chain(target.map_or(vec![], |data| data.repos.get_repos()))
+        .chain(targets.iter().flat_map(|data| data.repos.get_repos()))
         .cloned()
         .collect();
 
     let profile_bashrcs = merged_repo_set.group_paths_by_repos(
-        host.config
-            .all_profile_bashrcs()
-            .into_iter()
-            .chain(target.map_or(vec![], |data| data.config.all_profile_bashrcs())),
+        host.config.all_profile_bashrcs().into_iter().chain(
+            targets
+                .iter()
+                .flat_map(|data| data.config.all_profile_bashrcs()),
+        ),
     )?;
 
     let package_bashrcs = merged_repo_set.group_paths_by_repos(
-        host.config
-            .all_package_bashrcs()
-            .into_iter()
-            .chain(target.map_or(vec![], |data| data.config.all_package_bashrcs())),
+        host.config.all_package_bashrcs().into_iter().chain(
+            targets
+                .iter()
+                .flat_map(|data| data.config.all_package_bashrcs()),
+        ),
     )?;
 
     let output_dirs_to_bashrcs = profile_bashrcs
@@ -24,31 +24,19 @@ use alchemist::{
 };
 use anyhow::{anyhow, Context, Result};
 use itertools::Itertools;
-use lazy_static::lazy_static;
 use rayon::prelude::*;
 use serde::Serialize;
-use tera::Tera;
 use tracing::instrument;
 
-use crate::generate_repo::common::{
-    escape_starlark_string, package_details_to_target_path, repository_set_to_target_path,
-    DistFileEntry, AUTOGENERATE_NOTICE, PRIMORDIAL_PACKAGES,
+use crate::generate_repo::{
+    backend::OutputBackend,
+    common::{
+        package_details_to_target_path, repository_set_to_target_path, DistFileEntry,
+        PRIMORDIAL_PACKAGES,
+    },
+    internal::sources::is_covered_by_local_dir,
 };
 
-lazy_static! {
-    static ref TEMPLATES: Tera = {
-        let mut tera: Tera = Default::default();
-        tera.add_raw_template(
-            "package.BUILD.bazel",
-            include_str!("templates/package.BUILD.bazel"),
-        )
-        .unwrap();
-        tera.autoescape_on(vec![".bazel"]);
-        tera.set_escape_fn(escape_starlark_string);
-        tera
-    };
-}
-
 #[derive(Serialize)]
 pub struct EBuildEntry {
     ebuild_name: String,
@@ -211,12 +199,25 @@ impl EBuildEntry {
 
         let version = package.details.as_basic_data().version.to_string();
 
+        // Depend only on the outermost source packages to avoid redundant
+        // edges, e.g. on both src/platform2 and src/platform2/common-mk.
+        let local_dirs = package
+            .sources
+            .local_sources
+            .iter()
+            .filter_map(|source| match source {
+                PackageLocalSource::Src(src) => Some(src.as_path()),
+                _ => None,
+            })
+            .collect_vec();
+
         let mut sources: Vec<String> = package
             .sources
             .local_sources
             .iter()
             .filter_map(|source| match source {
                 PackageLocalSource::BazelTarget(target) => Some(target.clone()),
+                PackageLocalSource::Src(src) if is_covered_by_local_dir(src, &local_dirs) => None,
                 PackageLocalSource::Src(src) => Some(format!(
                     "//internal/sources/{}:__tarballs__",
                     src.to_string_lossy()
@@ -255,7 +256,9 @@ impl EBuildEntry {
             .local_sources
             .iter()
             .filter_map(|source| match source {
-                PackageLocalSource::SrcFile(src_file) => {
+                PackageLocalSource::SrcFile(src_file)
+                    if !is_covered_by_local_dir(src_file, &local_dirs) =>
+                {
                     Some(src_file.parent().unwrap().to_owned())
                 }
                 _ => None,
@@ -301,7 +304,15 @@ impl EBuildEntry {
             .sources
             .repo_sources
             .iter()
-            .map(|source| format!("@portage_deps//:{}_src", source.name.to_owned()))
+            .map(|source| &source.name)
+            .chain(
+                package
+                    .sources
+                    .git_sources
+                    .iter()
+                    .map(|source| &source.name),
+            )
+            .map(|name| format!("@portage_deps//:{}_src", name))
             .collect();
 
         let dists = package
@@ -506,7 +517,7 @@ impl EBuildEntry {
             package.details.as_basic_data().repo_name
         );
 
-        let bashrcs = package
+        let mut bashrcs: Vec<String> = package
             .bashrcs
             .iter()
             .map(|bashrc| {
@@ -533,6 +544,12 @@ impl EBuildEntry {
             })
             .collect::<Result<_>>()?;
 
+        // Let git-r3.eclass use the trees of pinned commits listed in
+        // `git_trees` instead of cloning the repository.
+        if !package.sources.git_sources.is_empty() {
+            bashrcs.push("@//bazel/portage/build_defs:git_r3_bashrc".to_owned());
+        }
+
         let expressions = &package.dependencies.expressions;
 
         // We omit PDEPEND since it's never supposed to have a sub-slot rebuild
@@ -629,20 +646,128 @@ impl EBuildFailure {
     }
 }
 
+/// The context to render the ebuild targets of a package directory.
 #[derive(Serialize)]
-struct BuildTemplateContext<'a> {
+pub struct PackageBuildContext<'a> {
     target_board: Option<&'a str>,
     target_portage_config: Option<&'a str>,
     host_overlay_set: Option<String>,
     target_overlay_set: String,
-    ebuilds: Vec<EBuildEntry>,
-    failures: Vec<EBuildFailure>,
+    ebuilds: &'a [EBuildEntry],
+    failures: &'a [EBuildFailure],
+}
+
+/// Sources of an ebuild in [`EBuildMetadataEntry`].
+#[derive(Serialize)]
+struct SourcesMetadata<'a> {
+    local: &'a [String],
+    extra: &'a [String],
+    repo: &'a [String],
+    dist: &'a [DistFileEntry],
+}
+
+/// Resolved dependencies of an ebuild in [`EBuildMetadataEntry`], keyed the
+/// same way as the attributes of the generated `ebuild` targets.
+#[derive(Serialize)]
+struct DependenciesMetadata<'a> {
+    host_build_deps: &'a [String],
+    reusable_host_build_deps: &'a [String],
+    provided_host_build_deps: &'a [String],
+    host_install_deps: &'a [String],
+    target_build_deps: &'a [String],
+    reusable_target_build_deps: &'a [String],
+    target_test_deps: &'a [String],
+    runtime_deps: &'a [String],
+    provided_runtime_deps: &'a [String],
+    install_set: &'a [String],
+}
+
+#[derive(Serialize)]
+struct InterfaceLibrariesMetadata<'a> {
+    use_interface_libraries: bool,
+    generate_interface_libraries: bool,
+    allowlist: &'a [PathBuf],
+}
+
+/// Machine-readable description of an ebuild written to `metadata.json` next
+/// to the package's `BUILD.bazel`, so that tools can access what alchemist
+/// resolved without parsing Starlark.
+#[derive(Serialize)]
+struct EBuildMetadataEntry<'a> {
+    cpv: String,
+    repository: &'a str,
+    slot: &'a str,
+    target: String,
+    ebuild_path: &'a Path,
+    uses: &'a [String],
+    sources: SourcesMetadata<'a>,
+    deps: DependenciesMetadata<'a>,
+    interface_libraries: InterfaceLibrariesMetadata<'a>,
+}
+
+impl<'a> EBuildMetadataEntry<'a> {
+    fn new(entry: &'a EBuildEntry, package: &'a Package, package_label: &str) -> Self {
+        Self {
+            cpv: format!(
+                "{}/{}-{}",
+                entry.category, entry.package_name, entry.version
+            ),
+            repository: &package.details.as_basic_data().repo_name,
+            slot: &entry.slot,
+            target: format!("{}:{}", package_label, entry.version),
+            ebuild_path: &package.details.as_basic_data().ebuild_path,
+            uses: &entry.uses,
+            sources: SourcesMetadata {
+                local: &entry.sources,
+                extra: &entry.extra_sources,
+                repo: &entry.git_trees,
+                dist: &entry.dists,
+            },
+            deps: DependenciesMetadata {
+                host_build_deps: &entry.host_build_deps,
+                reusable_host_build_deps: &entry.reusable_host_build_deps,
+                provided_host_build_deps: &entry.provided_host_build_deps,
+                host_install_deps: &entry.host_install_deps,
+                target_build_deps: &entry.target_build_deps,
+                reusable_target_build_deps: &entry.reusable_target_build_deps,
+                target_test_deps: &entry.target_test_deps,
+                runtime_deps: &entry.runtime_deps,
+                provided_runtime_deps: &entry.provided_runtime_deps,
+                install_set: &entry.install_set,
+            },
+            interface_libraries: InterfaceLibrariesMetadata {
+                use_interface_libraries: entry.use_interface_libraries,
+                generate_interface_libraries: entry.generate_interface_libraries,
+                allowlist: &entry.interface_library_allowlist,
+            },
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct PackageMetadata<'a> {
+    ebuilds: Vec<EBuildMetadataEntry<'a>>,
+    failures: &'a [EBuildFailure],
+}
+
+/// An entry of `index.json` listing all packages generated in the repository.
+#[derive(Serialize)]
+pub struct PackageIndexEntry {
+    cpv: String,
+    repository: String,
+    /// The prefix the package was generated under, e.g. `stage2/host` or
+    /// `stage2/target/board`.
+    prefix: String,
+    target: String,
+    metadata: PathBuf,
 }
 
 fn generate_package_build_file(
     target: &PackageType,
-    packages_in_dir: &[&MaybePackage],
-    out: &Path,
+    backend: &dyn OutputBackend,
+    ebuilds: &[EBuildEntry],
+    failures: &[EBuildFailure],
+    output_dir: &Path,
 ) -> Result<()> {
     let (target_board, target_portage_config) = match target {
         PackageType::Host { .. } => (None, None),
@@ -671,45 +796,29 @@ fn generate_package_build_file(
         PackageType::CrossRoot { target, .. } => target.repo_set,
     });
 
-    let context = BuildTemplateContext {
+    let context = PackageBuildContext {
         target_board,
         target_portage_config: target_portage_config.as_deref(),
         host_overlay_set,
         target_overlay_set,
-        ebuilds: packages_in_dir
-            .iter()
-            .flat_map(|package| match package {
-                MaybePackage::Ok(package) => Some(package),
-                _ => None,
-            })
-            .map(|package| EBuildEntry::try_new(target, package))
-            .collect::<Result<_>>()?,
-        failures: packages_in_dir
-            .iter()
-            .flat_map(|package| match package {
-                MaybePackage::Err(error) => Some(error),
-                _ => None,
-            })
-            .map(|error| EBuildFailure::new(error))
-            .collect(),
+        ebuilds,
+        failures,
     };
 
-    let mut file = File::create(out)?;
-    file.write_all(AUTOGENERATE_NOTICE.as_bytes())?;
-    TEMPLATES.render_to(
-        "package.BUILD.bazel",
-        &tera::Context::from_serialize(context)?,
-        file,
-    )?;
-    Ok(())
+    backend.render_package(&context, output_dir)
 }
 
+/// Generates a package directory and returns the entries to add to
+/// `index.json` for it.
 fn generate_package(
     target: &PackageType,
+    backend: &dyn OutputBackend,
     translator: &PathTranslator,
     packages_in_dir: &[&MaybePackage],
+    prefix: &str,
+    package_label: &str,
     output_dir: &Path,
-) -> Result<()> {
+) -> Result<Vec<PackageIndexEntry>> {
     create_dir_all(output_dir)?;
 
     let ebuilds = packages_in_dir
@@ -736,9 +845,51 @@ fn generate_package(
         }
     }
 
-    generate_package_build_file(target, packages_in_dir, &output_dir.join("BUILD.bazel"))?;
+    let packages = packages_in_dir
+        .iter()
+        .flat_map(|package| match package {
+            MaybePackage::Ok(package) => Some(package),
+            _ => None,
+        })
+        .collect_vec();
+    let ebuilds = packages
+        .iter()
+        .map(|package| EBuildEntry::try_new(target, package))
+        .collect::<Result<Vec<_>>>()?;
+    let failures = packages_in_dir
+        .iter()
+        .flat_map(|package| match package {
+            MaybePackage::Err(error) => Some(error),
+            _ => None,
+        })
+        .map(|error| EBuildFailure::new(error))
+        .collect_vec();
+
+    generate_package_build_file(target, backend, &ebuilds, &failures, output_dir)?;
 
-    Ok(())
+    let metadata = PackageMetadata {
+        ebuilds: ebuilds
+            .iter()
+            .zip(&packages)
+            .map(|(entry, package)| EBuildMetadataEntry::new(entry, package, package_label))
+            .collect(),
+        failures: &failures,
+    };
+    let mut file = File::create(output_dir.join("metadata.json"))?;
+    serde_json::to_writer_pretty(&mut file, &metadata)?;
+    file.write_all(b"\n")?;
+
+    Ok(metadata
+        .ebuilds
+        .into_iter()
+        .map(|entry| PackageIndexEntry {
+            cpv: entry.cpv,
+            repository: entry.repository.to_owned(),
+            prefix: prefix.to_owned(),
+            target: entry.target,
+            metadata: PathBuf::from(package_label.trim_start_matches("//")).join("metadata.json"),
+        })
+        .collect())
 }
 
 /// Groups ebuilds into `<repo_name>/<category>/<package>` groups.
@@ -758,48 +909,113 @@ fn join_by_package_dir(all_packages: &[MaybePackage]) -> HashMap<PathBuf, Vec<&M
     packages_by_dir
 }
 
+/// Generates the packages of `target` and returns the entries to add to
+/// `index.json` for them.
 #[instrument(skip_all)]
 pub fn generate_internal_packages(
     target: &PackageType,
+    backend: &dyn OutputBackend,
     translator: &PathTranslator,
     all_packages: &[MaybePackage],
     output_dir: &Path,
-) -> Result<()> {
-    let output_packages_dir = output_dir.join("internal/packages").join(match &target {
+) -> Result<Vec<PackageIndexEntry>> {
+    let prefix = match &target {
         PackageType::Host(host) => host.prefix,
         PackageType::CrossRoot { target, .. } => target.prefix,
-    });
+    };
+    let output_packages_dir = output_dir.join("internal/packages").join(prefix);
 
     // Generate packages in parallel.
     let packages_by_dir = join_by_package_dir(all_packages);
-    packages_by_dir
+    let index = packages_by_dir
         .into_par_iter()
-        .try_for_each(|(relative_package_dir, packages_in_dir)| {
-            let output_package_dir = output_packages_dir.join(relative_package_dir);
-            generate_package(target, translator, &packages_in_dir, &output_package_dir)
+        .map(|(relative_package_dir, packages_in_dir)| {
+            let output_package_dir = output_packages_dir.join(&relative_package_dir);
+            let package_label = format!(
+                "//internal/packages/{}/{}",
+                prefix,
+                relative_package_dir.display()
+            );
+            generate_package(
+                target,
+                backend,
+                translator,
+                &packages_in_dir,
+                prefix,
+                &package_label,
+                &output_package_dir,
+            )
         })
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .flatten()
+        .collect_vec();
+
+    Ok(index)
+}
+
+/// Writes `internal/packages/index.json` listing the packages generated for
+/// all prefixes, so that tools can find the metadata of every package in the
+/// repository in one place.
+pub fn generate_package_index(index: Vec<PackageIndexEntry>, output_dir: &Path) -> Result<()> {
+    let index = index
+        .into_iter()
+        .sorted_by(|a, b| {
+            a.cpv
+                .cmp(&b.cpv)
+                .then_with(|| a.prefix.cmp(&b.prefix))
+                .then_with(|| a.target.cmp(&b.target))
+        })
+        .collect_vec();
+
+    let output_packages_dir = output_dir.join("internal/packages");
+    create_dir_all(&output_packages_dir)?;
+    let mut file = File::create(output_packages_dir.join("index.json"))?;
+    serde_json::to_writer_pretty(&mut file, &index)?;
+    file.write_all(b"\n")?;
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::generate_repo::backend::{BazelBackend, JsonBackend};
+
     use super::*;
 
-    // TODO: test more than just the syntax and remove this test
-    #[test]
-    fn template_syntax_valid() -> Result<()> {
-        let context = BuildTemplateContext {
+    fn new_empty_context() -> PackageBuildContext<'static> {
+        PackageBuildContext {
             target_board: None,
             target_portage_config: None,
             host_overlay_set: None,
             target_overlay_set: "target_overlay_set_for_testing".to_string(),
-            ebuilds: Vec::new(),
-            failures: Vec::new(),
-        };
+            ebuilds: &[],
+            failures: &[],
+        }
+    }
 
-        let _ = TEMPLATES.render(
-            "package.BUILD.bazel",
-            &tera::Context::from_serialize(context)?,
+    // TODO: test more than just the syntax and remove this test
+    #[test]
+    fn template_syntax_valid() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        BazelBackend.render_package(&new_empty_context(), dir.path())?;
+        Ok(())
+    }
+
+    #[test]
+    fn json_backend_writes_context() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        JsonBackend.render_package(&new_empty_context(), dir.path())?;
+
+        let content = std::fs::read_to_string(dir.path().join("build.json"))?;
+        let value: serde_json::Value = serde_json::from_str(&content)?;
+        assert_eq!(value["kind"], "package");
+        assert_eq!(
+            value["target_overlay_set"],
+            "target_overlay_set_for_testing"
         );
+        assert_eq!(value["ebuilds"], serde_json::json!([]));
+        assert!(!dir.path().join("BUILD.bazel").exists());
 
         Ok(())
     }
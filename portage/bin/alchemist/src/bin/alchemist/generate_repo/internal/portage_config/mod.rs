@@ -16,6 +16,7 @@ use std::io::Write;
 use std::path::PathBuf;
 use std::{borrow::Cow, fs::create_dir_all, path::Path};
 use tera::Tera;
+use tracing::instrument;
 
 use crate::generate_repo::{common::AUTOGENERATE_NOTICE, TargetData};
 
@@ -223,17 +224,21 @@ pub fn generate_target_portage_config(target: &TargetData, prefix: &str, out: &P
 ///       * target
 ///           * board
 ///           * host
+///
+/// The config of each target is named after its repository set, i.e. `board`
+/// when generating a single board, and the board name otherwise.
+#[instrument(skip_all)]
 pub fn generate_portage_config(
     host: &TargetData,
-    target: Option<&TargetData>,
+    targets: &[TargetData],
     out: &Path,
 ) -> Result<()> {
     let out = out.join("internal/portage-config");
 
     generate_host_portage_config(host, &out)?;
     generate_target_host_portage_config(host, &out)?;
-    if let Some(target) = target {
-        generate_target_portage_config(target, "target/board", &out)?;
+    for target in targets {
+        generate_target_portage_config(target, &format!("target/{}", target.repos.name()), &out)?;
     }
 
     Ok(())
@@ -22,6 +22,7 @@ use std::{
 };
 use std::{str::FromStr, sync::Arc};
 use tera::Tera;
+use tracing::instrument;
 
 use crate::generate_repo::common::{
     package_details_to_target_path, repository_set_to_target_path, PRIMORDIAL_PACKAGES,
@@ -152,7 +153,9 @@ struct SdkTemplateContext<'a> {
     target_deps: Vec<String>,
 }
 
-fn get_primordial_packages(resolver: &PackageResolver) -> Result<Vec<Arc<PackageDetails>>> {
+/// Returns the [`PRIMORDIAL_PACKAGES`] to install into a target SDK's sysroot,
+/// except those listed in `package.provided`.
+pub fn get_primordial_packages(resolver: &PackageResolver) -> Result<Vec<Arc<PackageDetails>>> {
     let mut packages = Vec::with_capacity(PRIMORDIAL_PACKAGES.len());
     for package_name in PRIMORDIAL_PACKAGES {
         let atom = PackageDependencyAtom::from_str(package_name)?;
@@ -172,7 +175,9 @@ fn get_primordial_packages(resolver: &PackageResolver) -> Result<Vec<Arc<Package
     Ok(packages)
 }
 
-fn get_cross_glibc(
+/// Returns the glibc built for `toolchain` that `sdk_install_glibc` installs
+/// into the sysroot.
+pub fn get_cross_glibc(
     toolchain: &Toolchain,
     resolver: &PackageResolver,
 ) -> Result<Arc<PackageDetails>> {
@@ -185,7 +190,9 @@ fn get_cross_glibc(
         .with_context(|| format!("Failed to find {}", package_name))
 }
 
-fn get_toolchain_packages(
+/// Returns the [`TOOLCHAIN_PACKAGE_NAMES`] packages that make up the cross
+/// compiler layer for `primary_toolchain`.
+pub fn get_toolchain_packages(
     primary_toolchain: &Toolchain,
     resolver: &PackageResolver,
 ) -> Result<Vec<Arc<PackageDetails>>> {
@@ -252,6 +259,7 @@ struct SdkBaseContext<'a> {
     sdk: &'a str,
 }
 
+#[instrument(skip_all, fields(name = config.name))]
 pub fn generate_base_sdk(config: &SdkBaseConfig, out: &Path) -> Result<()> {
     let (dir, target) = match config.name.split_once(':') {
         None => (config.name, config.name),
@@ -312,6 +320,7 @@ struct SdkHostContext<'a> {
     base: &'a str,
 }
 
+#[instrument(skip_all, fields(name = config.name))]
 pub fn generate_host_sdk(config: &SdkHostConfig, out: &Path) -> Result<()> {
     let out = out.join("internal/sdk").join(config.name);
 
@@ -392,6 +401,7 @@ struct SdkTargetCrossCompileContext<'a> {
     toolchain_deps: Vec<String>,
 }
 
+#[instrument(skip_all, fields(name = config.name))]
 pub fn generate_target_sdk(config: &SdkTargetConfig, out: &Path) -> Result<()> {
     let wrappers = WRAPPER_DEFS.iter().map(|def| def.name).collect();
 
@@ -80,6 +80,15 @@ fn file_path_needs_renaming(path: &Path) -> bool {
     BAZEL_SPECIAL_FILE_NAMES.contains(&file_name)
 }
 
+/// Checks if a local source is included in the `__tarballs__` target of
+/// another local source directory, which also contains the tarballs of all
+/// source packages under it.
+pub fn is_covered_by_local_dir(path: &Path, local_dirs: &[&Path]) -> bool {
+    local_dirs
+        .iter()
+        .any(|dir| path != *dir && path.starts_with(dir))
+}
+
 /// Describes the layout of a local source package.
 ///
 /// A local source package corresponds to a directory in ChromeOS source code
@@ -103,20 +112,53 @@ struct SourcePackageLayout {
     /// Relative directory paths of child local source packages.
     /// e.g. "src/platform2/debugd/dbus_bindings".
     child_prefixes: Vec<PathBuf>,
+
+    /// Whether only `single_files` are needed from the directory.
+    ///
+    /// This is the case when no package depends on the directory or any of
+    /// its ancestors as a whole, e.g. `src/platform2` when packages only
+    /// require `src/platform2/.gn` and some subtrees of it. We then skip
+    /// replicating the directory tree, which is otherwise duplicated by its
+    /// child packages for subtrees.
+    single_files_only: bool,
 }
 
 impl SourcePackageLayout {
-    /// Computes a list of [`SourcePackageLayout`] from a list of [`PackageLocalSource`].
+    /// Computes a list of [`SourcePackageLayout`] from the lists of
+    /// [`PackageLocalSource`] of all packages.
+    ///
+    /// Every directory appears in exactly one source package, and a parent
+    /// package includes the tarballs of its children, so a subtree shared by
+    /// several packages is generated once and referenced from all of them.
+    /// Single files already covered by a directory required by the same
+    /// package are skipped since the package never refers to them.
     fn compute<'a>(
-        all_local_sources: impl IntoIterator<Item = &'a PackageLocalSource>,
+        all_local_sources: impl IntoIterator<Item = &'a [PackageLocalSource]>,
     ) -> Result<Vec<Self>> {
         let mut required_single_files: HashMap<PathBuf, HashSet<PathBuf>> = HashMap::new();
+        let mut required_dirs: HashSet<PathBuf> = HashSet::new();
 
         // Deduplicate all local source prefixes.
         let sorted_prefixes: Vec<PathBuf> = all_local_sources
             .into_iter()
+            .flat_map(|local_sources| {
+                let local_dirs = local_sources
+                    .iter()
+                    .filter_map(|source| match source {
+                        PackageLocalSource::Src(src) => Some(src.as_path()),
+                        _ => None,
+                    })
+                    .collect_vec();
+                local_sources.iter().filter(move |source| match source {
+                    PackageLocalSource::SrcFile(src) => !is_covered_by_local_dir(src, &local_dirs),
+                    _ => true,
+                })
+            })
             .filter_map(|origin| match origin {
-                PackageLocalSource::Src(src) => Some(PathBuf::from(src)),
+                PackageLocalSource::Src(src) => {
+                    required_dirs.insert(PathBuf::from(src));
+                    Some(PathBuf::from(src))
+                }
                 PackageLocalSource::SrcFile(src) => {
                     let dir_name = PathBuf::from(src.parent().unwrap());
                     let file_name = PathBuf::from(src.file_name().unwrap());
@@ -146,21 +188,25 @@ impl SourcePackageLayout {
                             .into_iter()
                             .collect(),
                         child_prefixes: Vec::new(),
+                        single_files_only: !required_dirs.contains(prefix),
                     },
                 )
             }));
 
-        // Compute children of each local source package.
+        // Compute children of each local source package. Parents come before
+        // their children in `sorted_prefixes`, so a parent's
+        // `single_files_only` is final when its children are visited.
         let mut stack: Vec<&Path> = Vec::new();
         for prefix in sorted_prefixes.iter() {
             while let Some(parent_prefix) = stack.pop() {
                 if prefix.starts_with(parent_prefix) {
                     // `prefix` is a child of `parent_prefix`.
-                    layout_map
-                        .get_mut(parent_prefix)
-                        .unwrap()
-                        .child_prefixes
-                        .push(prefix.to_owned());
+                    let parent = layout_map.get_mut(parent_prefix).unwrap();
+                    parent.child_prefixes.push(prefix.to_owned());
+                    // The whole directory is needed if its parent is needed.
+                    let parent_single_files_only = parent.single_files_only;
+                    layout_map.get_mut(prefix).unwrap().single_files_only &=
+                        parent_single_files_only;
                     stack.push(parent_prefix);
                     break;
                 }
@@ -239,6 +285,21 @@ impl SourcePackage {
             .join("internal/sources")
             .join(&layout.prefix);
 
+        // No need to scan the directory if we don't replicate it.
+        if layout.single_files_only {
+            let single_files = layout.single_files.clone();
+            return Ok(Self {
+                layout,
+                source_dir,
+                output_dir,
+                dirs: Vec::new(),
+                symlinks: Vec::new(),
+                renames: Vec::new(),
+                excludes: Vec::new(),
+                single_files,
+            });
+        }
+
         // Pre-compute child package paths for fast lookup.
         let child_paths: HashSet<PathBuf> = layout
             .child_prefixes
@@ -349,6 +410,7 @@ struct RenameEntry {
 #[derive(Serialize)]
 struct BuildTemplateContext {
     prefix: String,
+    single_files_only: bool,
     children: Vec<String>,
     dirs: Vec<String>,
     symlinks: Vec<SymlinkEntry>,
@@ -361,6 +423,7 @@ struct BuildTemplateContext {
 fn generate_build_file(package: &SourcePackage) -> Result<()> {
     let context = BuildTemplateContext {
         prefix: package.layout.prefix.to_string_lossy().into_owned(),
+        single_files_only: package.layout.single_files_only,
         children: package
             .layout
             .child_prefixes
@@ -450,6 +513,13 @@ fn generate_renaming_symlinks(package: &SourcePackage) -> Result<()> {
 /// to generate `BUILD.bazel`), we create a symlink to the whole directory,
 /// rather than creating a directory and a bunch of symlinks underneath it.
 fn generate_general_symlinks(package: &SourcePackage) -> Result<()> {
+    if package.layout.single_files_only {
+        for file in package.single_files.iter() {
+            symlink(package.source_dir.join(file), package.output_dir.join(file))?;
+        }
+        return Ok(());
+    }
+
     // Create child source package directories in case they have not been
     // created yet.
     for child_prefix in package.layout.child_prefixes.iter() {
@@ -571,7 +641,7 @@ fn generate_package(package: &SourcePackage) -> Result<()> {
 /// Generates source packages under `@portage//internal/sources/`.
 #[instrument(skip_all)]
 pub fn generate_internal_sources<'a>(
-    all_local_sources: impl IntoIterator<Item = &'a PackageLocalSource>,
+    all_local_sources: impl IntoIterator<Item = &'a [PackageLocalSource]>,
     repo_dir: &Path,
     repository_output_dir: &Path,
 ) -> Result<()> {
@@ -626,6 +696,31 @@ mod tests {
         assert_eq!("%F0%9F%90%88", escape_file_name_for_bazel_target_name("🐈"));
     }
 
+    #[test]
+    fn test_is_covered_by_local_dir() {
+        let local_dirs = [Path::new("src/platform2"), Path::new("src/aosp")];
+        assert!(is_covered_by_local_dir(
+            Path::new("src/platform2/common-mk"),
+            &local_dirs
+        ));
+        assert!(is_covered_by_local_dir(
+            Path::new("src/platform2/.gn"),
+            &local_dirs
+        ));
+        assert!(!is_covered_by_local_dir(
+            Path::new("src/platform2"),
+            &local_dirs
+        ));
+        assert!(!is_covered_by_local_dir(
+            Path::new("src/platform"),
+            &local_dirs
+        ));
+        assert!(!is_covered_by_local_dir(
+            Path::new("src/platform2-extra"),
+            &local_dirs
+        ));
+    }
+
     #[test]
     fn test_file_path_needs_renaming() {
         assert!(!file_path_needs_renaming(&PathBuf::from("foo/bar/baz")));
@@ -651,7 +746,8 @@ mod tests {
     /// A directory under `testdata` corresponds to a test case, and it must
     /// contain the following files:
     ///
-    /// - `sources.json`: JSON-serialized `Vec<PackageLocalSource>`.
+    /// - `sources.json`: JSON-serialized `Vec<Vec<PackageLocalSource>>`, one
+    ///   list per package.
     /// - `source`: Input source directory.
     /// - `golden`: Expected output directory.
     ///
@@ -677,7 +773,7 @@ mod tests {
                 .join(&entry.file_name())
                 .join("golden");
 
-            let local_sources: Vec<PackageLocalSource> = {
+            let local_sources: Vec<Vec<PackageLocalSource>> = {
                 let file = File::open(&case_input_path)?;
                 serde_json::from_reader(&file)?
             };
@@ -685,7 +781,11 @@ mod tests {
             let temp_dir = tempdir()?;
             let output_dir = temp_dir.path();
 
-            generate_internal_sources(&local_sources, case_source_dir.path(), output_dir)?;
+            generate_internal_sources(
+                local_sources.iter().map(Vec::as_slice),
+                case_source_dir.path(),
+                output_dir,
+            )?;
 
             let inner_output_dir = output_dir.join("internal/sources");
             compare_with_golden_data(&inner_output_dir, &case_golden_dir)?;
@@ -717,7 +817,7 @@ mod tests {
         let output_dir = output_dir.path();
 
         generate_internal_sources(
-            &[PackageLocalSource::Src(PathBuf::from(""))],
+            [[PackageLocalSource::Src(PathBuf::from(""))].as_slice()],
             source_dir,
             output_dir,
         )?;
@@ -765,10 +865,11 @@ mod tests {
         let output_dir = output_dir.path();
 
         generate_internal_sources(
-            &[
+            [[
                 PackageLocalSource::Src(PathBuf::from("src/third_party/llvm-project")),
                 PackageLocalSource::Src(PathBuf::from("src/platform2")),
-            ],
+            ]
+            .as_slice()],
             source_dir,
             output_dir,
         )?;
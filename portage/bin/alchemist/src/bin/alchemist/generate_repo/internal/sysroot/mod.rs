@@ -14,6 +14,7 @@ use anyhow::Result;
 use lazy_static::lazy_static;
 use serde::Serialize;
 use tera::Tera;
+use tracing::instrument;
 
 lazy_static! {
     static ref TEMPLATE: Tera = {
@@ -31,16 +32,18 @@ lazy_static! {
 
 #[derive(Serialize)]
 struct BuildTemplateContext<'a> {
-    target_board: &'a str,
+    target_boards: Vec<&'a str>,
 }
 
-pub fn generate_sysroot_build_file(target: &TargetData, out: &Path) -> Result<()> {
+/// Generates `//internal/sysroot` containing a sysroot target for each board.
+#[instrument(skip_all)]
+pub fn generate_sysroot_build_file(targets: &[TargetData], out: &Path) -> Result<()> {
     let output_dir = out.join("internal/sysroot");
     create_dir_all(&output_dir)?;
     let output_file = output_dir.join("BUILD.bazel");
 
     let context = BuildTemplateContext {
-        target_board: &target.board,
+        target_boards: targets.iter().map(|target| target.board.as_str()).collect(),
     };
 
     let mut file = File::create(&output_file)?;
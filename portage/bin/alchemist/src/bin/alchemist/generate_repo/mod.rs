@@ -2,6 +2,7 @@
 // Use of this source code is governed by a BSD-style license that can be
 // found in the LICENSE file.
 
+pub mod backend;
 mod common;
 mod deps;
 pub mod internal;
@@ -11,7 +12,7 @@ use std::{
     collections::HashMap,
     fs::{create_dir_all, remove_dir_all, File},
     io::{ErrorKind, Write},
-    path::Path,
+    path::{Path, PathBuf},
     str::FromStr,
     sync::Arc,
 };
@@ -30,16 +31,19 @@ use alchemist::{
 };
 use anyhow::{anyhow, bail, Context, Result};
 use itertools::Itertools;
+use tracing::instrument;
 
 use crate::alchemist::TargetData;
 
 use self::{
+    backend::OutputBackend,
     deps::generate_deps_file,
     internal::{
         bashrcs::generate_internal_bashrcs,
         overlays::generate_internal_overlays,
         packages::{
-            generate_internal_packages, PackageHostConfig, PackageTargetConfig, PackageType,
+            generate_internal_packages, generate_package_index, PackageHostConfig,
+            PackageTargetConfig, PackageType,
         },
         portage_config::generate_portage_config,
         sdk::{
@@ -52,7 +56,9 @@ use self::{
     public::{generate_public_images, generate_public_packages},
 };
 
-fn load_packages(
+/// Loads and analyzes all packages visible to `target`.
+#[instrument(skip_all, fields(board = %target.board))]
+pub fn load_packages(
     host: &TargetData,
     target: &TargetData,
     src_dir: &Path,
@@ -89,7 +95,7 @@ fn load_packages(
     Ok(packages)
 }
 
-fn get_sdk_implicit_system_package(host_packages: &[MaybePackage]) -> Result<Arc<Package>> {
+pub fn get_sdk_implicit_system_package(host_packages: &[MaybePackage]) -> Result<Arc<Package>> {
     // TODO: Add a parameter to pass this along
     let sdk_atom = PackageAtom::from_str("virtual/target-sdk-implicit-system")?;
 
@@ -112,7 +118,7 @@ fn get_sdk_implicit_system_package(host_packages: &[MaybePackage]) -> Result<Arc
     }
 }
 
-fn compute_provided_packages(
+pub fn compute_provided_packages(
     packages_by_path: &HashMap<&Path, Result<&Package, &PackageAnalysisError>>,
     root: &Package,
 ) -> Result<Vec<ProvidedPackage>> {
@@ -138,6 +144,21 @@ fn compute_bootstrap_packages<'a>(
     packages_by_path: &'a HashMap<&Path, Result<&Package, &PackageAnalysisError>>,
     root: &Package,
 ) -> Result<Vec<&'a Package>> {
+    Ok(
+        compute_bootstrap_packages_with_dependents(packages_by_path, root)?
+            .into_iter()
+            .map(|(package, _)| package)
+            .collect(),
+    )
+}
+
+/// Same as [`compute_bootstrap_packages`], but also returns the packages in
+/// the transitive DEPEND and RDEPEND of `root` that BDEPEND or IDEPEND on each
+/// bootstrap package.
+pub fn compute_bootstrap_packages_with_dependents<'a>(
+    packages_by_path: &'a HashMap<&Path, Result<&Package, &PackageAnalysisError>>,
+    root: &Package,
+) -> Result<Vec<(&'a Package, Vec<&'a Package>)>> {
     // We collect the DEPEND in addition to the RDEPEND because there might be
     // packages that only declare a dependency as a DEPEND. If we only collected
     // the RDEPEND then we might not be able to build the DEPEND and thus fail
@@ -149,7 +170,7 @@ fn compute_bootstrap_packages<'a>(
             &[DependencyKind::BuildTarget, DependencyKind::RunTarget],
         )?;
 
-    let mut bdepends = vec![];
+    let mut bdepends: HashMap<&Path, (&Package, Vec<&Package>)> = HashMap::new();
 
     let get_package = |details: &PackageDetails| -> Result<_> {
         let maybe_package = packages_by_path
@@ -177,26 +198,152 @@ fn compute_bootstrap_packages<'a>(
             .iter()
             .chain(&package.dependencies.direct.install_host)
         {
-            bdepends.push(get_package(dep)?);
+            let dep = get_package(dep)?;
+            bdepends
+                .entry(dep.as_basic_data().ebuild_path.as_path())
+                .or_insert_with(|| (dep, Vec::new()))
+                .1
+                .push(package);
         }
     }
 
     Ok(bdepends
-        .into_iter()
-        .unique_by(|package| &package.as_basic_data().ebuild_path)
-        .sorted_by_key(|package| &package.as_basic_data().ebuild_path)
+        .into_values()
+        .sorted_by_key(|(package, _)| &package.as_basic_data().ebuild_path)
         .collect())
 }
 
+/// The packages a base SDK installs.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SdkBasePackages {
+    /// The implicit system set.
+    ImplicitSystem,
+    /// The implicit system set and the BDEPENDs needed to rebuild it.
+    Bootstrap,
+}
+
+/// How an SDK stage is generated.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SdkStageKind {
+    /// A base SDK bundling packages built with `source_sdk`.
+    Base {
+        source_package_prefix: &'static str,
+        source_sdk: &'static str,
+        packages: SdkBasePackages,
+    },
+    /// A host SDK on top of `base`. Host packages are built with it under the
+    /// stage name.
+    Host { base: &'static str },
+    /// A target SDK on top of `base` with the primordial packages of a
+    /// sysroot. Cross-root packages are built with it under the stage name.
+    Target { base: &'static str },
+}
+
+/// An SDK layer generated under `//internal/sdk/<name>`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SdkStage {
+    pub name: &'static str,
+    pub kind: SdkStageKind,
+}
+
+/// The SDK stages generated for the host, in order.
+pub const HOST_SDK_STAGES: [SdkStage; 6] = [
+    // The SDK used to build the stage1/target/host packages on top of the
+    // downloaded SDK tarball. These packages are used to generate the Stage 2
+    // SDK.
+    //
+    // i.e., An unknown version of LLVM will be used to cross-root build the
+    // latest version of LLVM.
+    //
+    // We don't know which packages are installed in the Stage 1 SDK, so we
+    // can't specify a host. In order to build an SDK with known versions, we
+    // need to cross-root compile a new SDK with the latest config and
+    // packages. We assume that the Stage 1 SDK contains all the BDEPENDs
+    // required to build the packages of the Stage 2 SDK.
+    SdkStage {
+        name: "stage1/target/host",
+        kind: SdkStageKind::Target {
+            base: "@//bazel/portage/sdk:stage1",
+        },
+    },
+    // The base for the host and board SDKs.
+    SdkStage {
+        name: "stage2",
+        kind: SdkStageKind::Base {
+            source_package_prefix: "stage1/target/host",
+            source_sdk: "stage1/target/host",
+            packages: SdkBasePackages::ImplicitSystem,
+        },
+    },
+    // The SDK used to build all the host packages. We no longer need to
+    // cross-root build since we know exactly what is contained in the Stage 2
+    // SDK, so BDEPENDs are properly supported.
+    SdkStage {
+        name: "stage2/host",
+        kind: SdkStageKind::Host { base: "stage2" },
+    },
+    // The stage 3 Bootstrap SDK is composed of packages built using the Stage
+    // 2 SDK. It is used to update the Stage 1 SDK when necessary. It only
+    // contains the packages necessary to build the implicit system set.
+    SdkStage {
+        name: "stage3:bootstrap",
+        kind: SdkStageKind::Base {
+            source_package_prefix: "stage2/host",
+            // TODO: THIS IS WRONG, we should be using the stage2 SDK, but
+            // we don't have a target/host SDK right now.
+            source_sdk: "stage1/target/host:base",
+            packages: SdkBasePackages::Bootstrap,
+        },
+    },
+    // The stage3/target/host packages are used to test that the stage 3
+    // bootstrap SDK can correctly build the implicit system set.
+    SdkStage {
+        name: "stage3/target/host",
+        kind: SdkStageKind::Target {
+            base: "stage3:bootstrap",
+        },
+    },
+    // This SDK is only used to verify that the Stage 3 Bootstrap SDK can
+    // actually bootstrap the implicit system.
+    //
+    // The Stage 2 SDK and Stage 4 SDK should in theory be bit-for-bit
+    // identical.
+    SdkStage {
+        name: "stage4",
+        kind: SdkStageKind::Base {
+            source_package_prefix: "stage3/target/host",
+            source_sdk: "stage3/target/host",
+            packages: SdkBasePackages::ImplicitSystem,
+        },
+    },
+];
+
+/// The stage the board SDKs are built on.
+pub const BOARD_SDK_BASE: &str = "stage2";
+
+/// The host stage whose packages satisfy BDEPEND and IDEPEND of board
+/// packages. Its packages are also exposed as the public host packages.
+pub const BOARD_SDK_HOST: &str = "stage2/host";
+
+/// Returns the name of the board SDK stage for a repository set.
+pub fn board_sdk_stage_name(repo_set_name: &str) -> String {
+    format!("{BOARD_SDK_BASE}/target/{repo_set_name}")
+}
+
 /// Generates the stage1, stage2, etc packages and SDKs.
+///
+/// The host packages are analyzed once and shared by all `targets`.
+#[instrument(skip_all)]
 pub fn generate_stages(
     host: &TargetData,
-    target: Option<&TargetData>,
+    targets: &[TargetData],
     translator: &PathTranslator,
+    backend: &dyn OutputBackend,
     src_dir: &Path,
     output_dir: &Path,
 ) -> Result<Vec<MaybePackage>> {
     let mut all_packages = vec![];
+    let mut package_index = vec![];
 
     let host_packages = load_packages(host, host, src_dir)?;
 
@@ -221,202 +368,144 @@ pub fn generate_stages(
     let implicit_system_packages =
         compute_provided_packages(&packages_by_path, &implicit_system_package)?;
 
-    // Generate the SDK used by the stage1/target/host packages.
-    generate_target_sdk(
-        &SdkTargetConfig {
-            base: "@//bazel/portage/sdk:stage1",
-            name: "stage1/target/host",
-            board: &host.board,
-            target_repo_set: &host.repos,
-            target_resolver: &host.resolver,
-            target_primary_toolchain: host
-                .toolchains
-                .primary()
-                .context("Host is missing primary toolchain")?,
-            host: None,
-        },
-        output_dir,
-    )?;
-
-    // Generate the packages that will be built using the Stage 1 SDK.
-    // These packages will be used to generate the Stage 2 SDK.
-    //
-    // i.e., An unknown version of LLVM will be used to cross-root build
-    // the latest version of LLVM.
-    //
-    // We assume that the Stage 1 SDK contains all the BDEPENDs required
-    // to build all the packages required to build the Stage 2 SDK.
-    generate_internal_packages(
-        // We don't know which packages are installed in the Stage 1 SDK
-        // (downloaded SDK tarball), so we can't specify a host. In order
-        // to build an SDK with known versions, we need to cross-root
-        // compile a new SDK with the latest config and packages. This
-        // guarantees that we can correctly track all the dependencies so
-        // we can ensure proper package rebuilds.
-        &PackageType::CrossRoot {
-            host: None,
-            target: PackageTargetConfig {
-                board: &host.board,
-                prefix: "stage1/target/host",
-                repo_set: &host.repos,
-            },
-        },
-        translator,
-        // TODO: Do we want to pass in sdk_packages? This would mean we
-        // can't manually build other host packages using the Stage 1 SDK,
-        // but it saves us on generating symlinks for packages we probably
-        // won't use.
-        &host_packages,
-        output_dir,
-    )?;
-
-    // Generate the stage 2 SDK
-    //
-    // This SDK will be used as the base for the host and target SDKs.
-    generate_base_sdk(
-        &SdkBaseConfig {
-            name: "stage2",
-            source_package_prefix: "stage1/target/host",
-            source_sdk: "stage1/target/host",
-            source_repo_set: &host.repos,
-            packages: vec![&implicit_system_package],
-            package_suffix: None,
-        },
-        output_dir,
-    )?;
-
-    // Generate the stage 2 host SDK. This will be used to build all the
-    // host packages.
-    generate_host_sdk(
-        &SdkHostConfig {
-            base: "stage2",
-            name: "stage2/host",
-        },
-        output_dir,
-    )?;
-
-    // Generate the host packages that will be built using the Stage 2 SDK.
-    let stage2_host = PackageHostConfig {
+    let host_config = |prefix| PackageHostConfig {
         repo_set: &host.repos,
-        prefix: "stage2/host",
+        prefix,
         sdk_provided_packages: &implicit_system_packages,
     };
-    generate_internal_packages(
-        // We no longer need to cross-root build since we know exactly what
-        // is contained in the Stage 2 SDK. This means we can properly
-        // support BDEPENDs. All the packages listed in `sdk_packages` are
-        // considered implicit system dependencies for any of these
-        // packages.
-        &PackageType::Host(stage2_host),
-        translator,
-        &host_packages,
-        output_dir,
-    )?;
 
-    // Generate the Stage 3 Bootstrap SDK
-    //
-    // The stage 3 Bootstrap SDK is composed of packages built using the Stage
-    // 2 SDK. It is used to update the Stage 1 SDK when necessary. It only
-    // contains the packages necessary to build the implicit system set.
-    generate_base_sdk(
-        &SdkBaseConfig {
-            name: "stage3:bootstrap",
-            source_package_prefix: "stage2/host",
-            // TODO: THIS IS WRONG, we should be using the stage2 SDK, but
-            // we don't have a target/host SDK right now.
-            source_sdk: "stage1/target/host:base",
-            source_repo_set: &host.repos,
-            packages: std::iter::once(implicit_system_package.as_ref())
-                .chain(compute_bootstrap_packages(
-                    &packages_by_path,
-                    &implicit_system_package,
-                )?)
-                .collect(),
-            // We use the _including_provided suffix so we can get ALL the
-            // RDEPENDs. The regular ebuild target has its RDEPENDs filtered
-            // by what the SDK already provides.
-            package_suffix: Some("_including_provided"),
-        },
-        output_dir,
-    )?;
-
-    // Generate the SDK used by the stage3/target/host packages.
-    generate_target_sdk(
-        &SdkTargetConfig {
-            base: "stage3:bootstrap",
-            name: "stage3/target/host",
-            board: &host.board,
-            target_repo_set: &host.repos,
-            target_resolver: &host.resolver,
-            target_primary_toolchain: host
-                .toolchains
-                .primary()
-                .context("Host is missing primary toolchain")?,
-            host: None,
-        },
-        output_dir,
-    )?;
+    for stage in &HOST_SDK_STAGES {
+        match stage.kind {
+            SdkStageKind::Target { base } => {
+                generate_target_sdk(
+                    &SdkTargetConfig {
+                        base,
+                        name: stage.name,
+                        board: &host.board,
+                        target_repo_set: &host.repos,
+                        target_resolver: &host.resolver,
+                        target_primary_toolchain: host
+                            .toolchains
+                            .primary()
+                            .context("Host is missing primary toolchain")?,
+                        host: None,
+                    },
+                    output_dir,
+                )?;
+
+                package_index.extend(generate_internal_packages(
+                    &PackageType::CrossRoot {
+                        host: None,
+                        target: PackageTargetConfig {
+                            board: &host.board,
+                            prefix: stage.name,
+                            repo_set: &host.repos,
+                        },
+                    },
+                    backend,
+                    translator,
+                    // TODO: Do we want to pass in only the packages needed to
+                    // build the next stage? This would mean we can't manually
+                    // build other host packages using these SDKs, but it
+                    // saves us on generating symlinks for packages we
+                    // probably won't use.
+                    &host_packages,
+                    output_dir,
+                )?);
+            }
+            SdkStageKind::Base {
+                source_package_prefix,
+                source_sdk,
+                packages,
+            } => {
+                let (packages, package_suffix) = match packages {
+                    SdkBasePackages::ImplicitSystem => (vec![&*implicit_system_package], None),
+                    SdkBasePackages::Bootstrap => (
+                        std::iter::once(implicit_system_package.as_ref())
+                            .chain(compute_bootstrap_packages(
+                                &packages_by_path,
+                                &implicit_system_package,
+                            )?)
+                            .collect(),
+                        // We use the _including_provided suffix so we can get
+                        // ALL the RDEPENDs. The regular ebuild target has its
+                        // RDEPENDs filtered by what the SDK already provides.
+                        Some("_including_provided"),
+                    ),
+                };
+
+                generate_base_sdk(
+                    &SdkBaseConfig {
+                        name: stage.name,
+                        source_package_prefix,
+                        source_sdk,
+                        source_repo_set: &host.repos,
+                        packages,
+                        package_suffix,
+                    },
+                    output_dir,
+                )?;
+            }
+            SdkStageKind::Host { base } => {
+                generate_host_sdk(
+                    &SdkHostConfig {
+                        base,
+                        name: stage.name,
+                    },
+                    output_dir,
+                )?;
+
+                // All the packages listed in `sdk_provided_packages` are
+                // considered implicit system dependencies for any of these
+                // packages.
+                package_index.extend(generate_internal_packages(
+                    &PackageType::Host(host_config(stage.name)),
+                    backend,
+                    translator,
+                    &host_packages,
+                    output_dir,
+                )?);
+            }
+        }
+    }
 
-    // These packages will be used to test that the stage 3 bootstrap SDK can
-    // correctly build the implicit system set.
-    generate_internal_packages(
-        &PackageType::CrossRoot {
-            host: None,
-            target: PackageTargetConfig {
-                board: &host.board,
-                prefix: "stage3/target/host",
-                repo_set: &host.repos,
-            },
-        },
-        translator,
-        // TODO: Do we want to pass in only the DEPEND + RDEPEND of the implicit
-        // system?
+    // Generate public aliases
+    generate_public_packages(
+        backend,
         &host_packages,
-        output_dir,
+        BOARD_SDK_HOST,
+        &output_dir.join("host"),
     )?;
 
-    // Generate the stage 4 SDK
-    //
-    // This SDK is only used to verify that the Stage 3 Bootstrap SDK can
-    // actually bootstrap the implicit system.
-    //
-    // The Stage 2 SDK and Stage 4 SDK should in theory be bit-for-bit
-    // identical.
-    generate_base_sdk(
-        &SdkBaseConfig {
-            name: "stage4",
-            source_package_prefix: "stage3/target/host",
-            source_sdk: "stage3/target/host",
-            source_repo_set: &host.repos,
-            packages: vec![&implicit_system_package],
-            package_suffix: None,
-        },
-        output_dir,
-    )?;
-
-    // Generate public aliases
-    generate_public_packages(&host_packages, "stage2/host", &output_dir.join("host"))?;
-
     all_packages.extend(host_packages);
 
-    if let Some(target) = target {
+    // With multiple boards, each board gets its own directory for public
+    // targets so that they don't collide.
+    let multi_board = targets.len() > 1;
+
+    for target in targets {
         let target_packages = load_packages(host, target, src_dir)?;
 
+        // The repository set is named "board" when generating a single board,
+        // and after the board otherwise.
+        let name = target.repos.name();
+        let prefix = board_sdk_stage_name(name);
+
         // Generate the stage 2 target board SDK. This will be used to build
         // all the target's packages.
         generate_target_sdk(
             &SdkTargetConfig {
-                base: "stage2",
-                name: "stage2/target/board",
+                base: BOARD_SDK_BASE,
+                name: &prefix,
                 board: &target.board,
                 target_repo_set: &target.repos,
                 target_resolver: &target.resolver,
                 target_primary_toolchain: target
                     .toolchains
                     .primary()
-                    .context("Target is missing primary toolchain")?,
+                    .with_context(|| format!("{} is missing primary toolchain", target.board))?,
                 host: Some(SdkTargetHostConfig {
-                    prefix: "stage2/host",
+                    prefix: BOARD_SDK_HOST,
                     resolver: &host.resolver,
                 }),
             },
@@ -425,29 +514,46 @@ pub fn generate_stages(
 
         // Generate the target packages that will be cross-root /
         // cross-compiled using the Stage 2 SDK.
-        generate_internal_packages(
+        package_index.extend(generate_internal_packages(
             &PackageType::CrossRoot {
                 // We want to use the stage2/host packages to satisfy
                 // our BDEPEND/IDEPEND dependencies.
-                host: Some(stage2_host),
+                host: Some(host_config(BOARD_SDK_HOST)),
                 target: PackageTargetConfig {
                     board: &target.board,
-                    prefix: "stage2/target/board",
+                    prefix: &prefix,
                     repo_set: &target.repos,
                 },
             },
+            backend,
             translator,
             &target_packages,
             output_dir,
-        )?;
+        )?);
+
+        let (public_packages_dir, public_images_dir) = if multi_board {
+            (
+                Path::new("target").join(&target.board),
+                Path::new("images").join(&target.board),
+            )
+        } else {
+            (PathBuf::from("target"), PathBuf::from("images"))
+        };
 
         generate_public_packages(
+            backend,
             &target_packages,
-            "stage2/target/board",
-            &output_dir.join("target"),
+            &prefix,
+            &output_dir.join(&public_packages_dir),
         )?;
 
-        generate_public_images(&target.board, &output_dir.join("images"))?;
+        generate_public_images(
+            backend,
+            &target.board,
+            name,
+            &format!("@portage//{}", public_packages_dir.display()),
+            &output_dir.join(public_images_dir),
+        )?;
 
         // TODO: Generate the Stage 3 target packages if we decide to build
         // targets against the stage 3 SDK.
@@ -455,14 +561,24 @@ pub fn generate_stages(
         all_packages.extend(target_packages);
     }
 
+    generate_package_index(package_index, output_dir)?;
+
     Ok(all_packages)
 }
 
 /// The entry point of "generate-repo" subcommand.
+///
+/// All `targets` are generated into the same repository. A single target is
+/// generated under the `board` name, e.g. `//internal/sdk/stage2/target/board`
+/// and `@portage//target`. When there are multiple targets, each one is
+/// generated under its board name instead, e.g.
+/// `//internal/sdk/stage2/target/amd64-generic` and
+/// `@portage//target/amd64-generic`.
 pub fn generate_repo_main(
     host: &TargetData,
-    target: Option<&TargetData>,
+    targets: &[TargetData],
     translator: &PathTranslator,
+    backend: &dyn OutputBackend,
     src_dir: &Path,
     output_dir: &Path,
     deps_file: &Path,
@@ -487,17 +603,17 @@ pub fn generate_repo_main(
 
     generate_internal_overlays(
         translator,
-        [Some(host), target]
-            .iter()
-            .filter_map(|x| x.map(|data| data.repos.as_ref()))
+        std::iter::once(host)
+            .chain(targets)
+            .map(|data| data.repos.as_ref())
             .collect_vec()
             .as_slice(),
         output_dir,
     )?;
 
-    generate_internal_bashrcs(translator, host, target, output_dir)?;
+    generate_internal_bashrcs(translator, host, targets, output_dir)?;
 
-    let all_packages = generate_stages(host, target, translator, src_dir, output_dir)?;
+    let all_packages = generate_stages(host, targets, translator, backend, src_dir, output_dir)?;
 
     generate_deps_file(
         &all_packages
@@ -510,7 +626,7 @@ pub fn generate_repo_main(
         deps_file,
     )?;
 
-    generate_portage_config(host, target, output_dir)?;
+    generate_portage_config(host, targets, output_dir)?;
 
     File::create(output_dir.join("BUILD.bazel"))?
         .write_all(include_bytes!("templates/root.BUILD.bazel"))?;
@@ -518,7 +634,7 @@ pub fn generate_repo_main(
 
     eprintln!("Generating sources...");
     generate_internal_sources(
-        all_packages.iter().flat_map(|package| match package {
+        all_packages.iter().map(|package| match package {
             MaybePackage::Ok(package) => package.sources.local_sources.as_slice(),
             _ => &[],
         }),
@@ -528,8 +644,8 @@ pub fn generate_repo_main(
         output_dir,
     )?;
 
-    if let Some(target) = target {
-        generate_sysroot_build_file(target, output_dir)?;
+    if !targets.is_empty() {
+        generate_sysroot_build_file(targets, output_dir)?;
     }
 
     eprintln!("Generated @portage.");
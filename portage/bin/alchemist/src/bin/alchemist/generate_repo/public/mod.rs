@@ -5,8 +5,7 @@
 use std::{
     borrow::Cow,
     collections::{BTreeMap, HashMap},
-    fs::{create_dir_all, File},
-    io::Write,
+    fs::create_dir_all,
     path::Path,
 };
 use tracing::instrument;
@@ -15,34 +14,11 @@ use alchemist::{
     analyze::MaybePackage, dependency::package::AsPackageRef, resolver::select_best_version,
 };
 use anyhow::Result;
-use lazy_static::lazy_static;
 use rayon::prelude::*;
 use serde::Serialize;
-use tera::Tera;
 use version::Version;
 
-use crate::generate_repo::common::escape_starlark_string;
-
-use super::common::AUTOGENERATE_NOTICE;
-
-lazy_static! {
-    static ref TEMPLATES: Tera = {
-        let mut tera: Tera = Default::default();
-        tera.add_raw_template(
-            "images.BUILD.bazel",
-            include_str!("templates/images.BUILD.bazel"),
-        )
-        .unwrap();
-        tera.add_raw_template(
-            "package.BUILD.bazel",
-            include_str!("templates/package.BUILD.bazel"),
-        )
-        .unwrap();
-        tera.autoescape_on(vec![".bazel"]);
-        tera.set_escape_fn(escape_starlark_string);
-        tera
-    };
-}
+use super::backend::OutputBackend;
 
 #[derive(Serialize)]
 struct AliasEntry<'a> {
@@ -62,14 +38,16 @@ struct EbuildFailureEntry<'a> {
     error: Cow<'a, str>,
 }
 
+/// The context to render the public aliases of a package.
 #[derive(Serialize)]
-struct BuildTemplateContext<'a> {
+pub struct PublicPackageBuildContext<'a> {
     aliases: Vec<AliasEntry<'a>>,
     test_suites: Vec<TestSuiteEntry<'a>>,
     best_version_selection_failure: Option<&'a str>,
 }
 
 fn generate_public_package(
+    backend: &dyn OutputBackend,
     maybe_packages: &[&MaybePackage],
     package_prefix: &str,
     package_output_dir: &Path,
@@ -183,21 +161,13 @@ fn generate_public_package(
         Err(reason) => Some(reason.as_str()),
     };
 
-    let context = BuildTemplateContext {
+    let context = PublicPackageBuildContext {
         aliases,
         test_suites,
         best_version_selection_failure,
     };
 
-    let mut file = File::create(package_output_dir.join("BUILD.bazel"))?;
-    file.write_all(AUTOGENERATE_NOTICE.as_bytes())?;
-    TEMPLATES.render_to(
-        "package.BUILD.bazel",
-        &tera::Context::from_serialize(context)?,
-        file,
-    )?;
-
-    Ok(())
+    backend.render_public_package(&context, package_output_dir)
 }
 
 fn join_by_package_name(all_packages: &[MaybePackage]) -> HashMap<String, Vec<&MaybePackage>> {
@@ -222,6 +192,7 @@ pub struct TargetConfig<'a> {
 /// Generates the public aliases for packages.
 #[instrument(skip_all)]
 pub fn generate_public_packages(
+    backend: &dyn OutputBackend,
     all_packages: &[MaybePackage],
     package_prefix: &str,
     output_dir: &Path,
@@ -233,49 +204,114 @@ pub fn generate_public_packages(
         .into_par_iter()
         .try_for_each(|(package_name, maybe_packages)| {
             let package_output_dir = output_dir.join(package_name);
-            generate_public_package(&maybe_packages, package_prefix, &package_output_dir)
+            generate_public_package(
+                backend,
+                &maybe_packages,
+                package_prefix,
+                &package_output_dir,
+            )
         })
 }
 
+/// The context to render the public image targets of a board.
 #[derive(Serialize)]
-struct ImagesTemplateContext<'a> {
+pub struct ImagesBuildContext<'a> {
     board: &'a str,
+    target: &'a str,
+    target_packages: &'a str,
 }
 
 /// Generates the public targets for images.
+///
+/// `target` is the name the board's SDK, overlay set and Portage config are
+/// generated under, e.g. `board` for `//internal/sdk/stage2/target/board`.
+/// `target_packages` is the Bazel package the board's public package targets
+/// are generated in, e.g. `@portage//target`.
 #[instrument(skip_all)]
-pub fn generate_public_images(board: &str, output_dir: &Path) -> Result<()> {
+pub fn generate_public_images(
+    backend: &dyn OutputBackend,
+    board: &str,
+    target: &str,
+    target_packages: &str,
+    output_dir: &Path,
+) -> Result<()> {
     create_dir_all(output_dir)?;
 
-    let context = ImagesTemplateContext { board };
+    let context = ImagesBuildContext {
+        board,
+        target,
+        target_packages,
+    };
 
-    let mut file = File::create(output_dir.join("BUILD.bazel"))?;
-    file.write_all(AUTOGENERATE_NOTICE.as_bytes())?;
-    TEMPLATES.render_to(
-        "images.BUILD.bazel",
-        &tera::Context::from_serialize(context)?,
-        file,
-    )?;
-    Ok(())
+    backend.render_images(&context, output_dir)
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::generate_repo::backend::{BazelBackend, JsonBackend};
+
     use super::*;
 
     // TODO: test more than just the syntax and remove this test
     #[test]
     fn template_syntax_valid() -> Result<()> {
-        let context = BuildTemplateContext {
+        let context = PublicPackageBuildContext {
             aliases: Vec::new(),
             test_suites: Vec::new(),
             best_version_selection_failure: None,
         };
 
-        let _ = TEMPLATES.render(
-            "package.BUILD.bazel",
-            &tera::Context::from_serialize(context)?,
+        let dir = tempfile::tempdir()?;
+        BazelBackend.render_public_package(&context, dir.path())?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_public_images_uses_target_labels() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        generate_public_images(
+            &BazelBackend,
+            "arm64-generic",
+            "arm64-generic",
+            "@portage//target/arm64-generic",
+            dir.path(),
+        )?;
+
+        let content = std::fs::read_to_string(dir.path().join("BUILD.bazel"))?;
+        assert!(content.contains(r#"board = "arm64-generic""#));
+        assert!(content.contains(r#"sdk = "//internal/sdk/stage2/target/arm64-generic""#));
+        assert!(content.contains(r#""//internal/portage-config/target/arm64-generic""#));
+        assert!(content.contains(r#"overlays = "//internal/overlays:arm64-generic""#));
+        assert!(
+            content.contains(r#""@portage//target/arm64-generic/virtual/target-os:package_set""#)
+        );
+        assert!(!content.contains("target/board"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn json_backend_writes_best_version_selection_failure() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let package_dir = dir.path().join("sys-apps/foo");
+        generate_public_package(&JsonBackend, &[], "stage2/host", &package_dir)?;
+
+        let content = std::fs::read_to_string(package_dir.join("build.json"))?;
+        let value: serde_json::Value = serde_json::from_str(&content)?;
+        assert_eq!(value["kind"], "public_package");
+        assert_eq!(
+            value["best_version_selection_failure"],
+            "All packages are masked"
         );
+        assert!(value["aliases"]
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::json!({
+                "name": "foo",
+                "actual": ":best_version_selection_failure",
+            })));
+        assert!(!package_dir.join("BUILD.bazel").exists());
 
         Ok(())
     }
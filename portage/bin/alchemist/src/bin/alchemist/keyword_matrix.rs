@@ -0,0 +1,447 @@
+// Copyright 2024 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+use std::{
+    collections::{hash_map::Entry, HashMap, VecDeque},
+    path::PathBuf,
+    str::FromStr,
+    sync::Arc,
+};
+
+use alchemist::{
+    analyze::dependency::direct::{analyze_direct_dependencies, DependencyKind},
+    config::bundle::{ConfigBundle, IsPackageAcceptedResult},
+    dependency::package::{AsPackageRef, PackageAtom},
+    ebuild::{MaybePackageDetails, PackageDetails},
+    resolver::select_best_version,
+};
+use anyhow::Result;
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::alchemist::TargetData;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default)]
+enum OutputFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    /// Output format.
+    #[arg(long, value_enum, default_value_t)]
+    format: OutputFormat,
+
+    /// Only reports packages whose selected version is accepted only because
+    /// of --force-accept-9999-ebuilds.
+    #[arg(long)]
+    forced_only: bool,
+
+    /// Packages to report, together with the packages they depend on.
+    /// Defaults to virtual/target-os, virtual/target-os-dev and
+    /// virtual/target-os-test for boards, and virtual/target-sdk for the host.
+    packages: Vec<String>,
+}
+
+/// How the selected version of a package is accepted by a board.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum KeywordStatus {
+    /// Accepted by a stable keyword, e.g. `amd64`.
+    Stable,
+    /// Accepted by a testing keyword, e.g. `~amd64`.
+    Testing,
+    /// Not accepted by ACCEPT_KEYWORDS, but accepted because it is a 9999
+    /// cros-workon ebuild and --force-accept-9999-ebuilds is set.
+    #[serde(rename = "forced-9999")]
+    Forced9999,
+}
+
+impl KeywordStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Stable => "stable",
+            Self::Testing => "testing",
+            Self::Forced9999 => "forced-9999",
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Serialize)]
+struct MatrixRow {
+    board: String,
+    arch: String,
+    package_name: String,
+    version: String,
+    status: KeywordStatus,
+    /// The version Portage selects, i.e. ignoring versions accepted only by
+    /// --force-accept-9999-ebuilds. `None` if there is no such version.
+    portage_version: Option<String>,
+}
+
+/// Checks if KEYWORDS and ACCEPT_KEYWORDS accept the package, ignoring
+/// --force-accept-9999-ebuilds.
+fn is_accepted_by_config(config: &ConfigBundle, details: &PackageDetails) -> Result<bool> {
+    Ok(matches!(
+        config.is_package_accepted(&details.metadata.vars, &details.as_package_ref())?,
+        IsPackageAcceptedResult::Accepted { .. }
+    ))
+}
+
+/// Determines how the selected version of a package is accepted by `config`.
+fn classify(config: &ConfigBundle, details: &PackageDetails) -> Result<KeywordStatus> {
+    Ok(if details.stable {
+        KeywordStatus::Stable
+    } else if is_accepted_by_config(config, details)? {
+        KeywordStatus::Testing
+    } else {
+        KeywordStatus::Forced9999
+    })
+}
+
+/// Computes the packages reachable from `roots` in the world of `target`, or
+/// of `host` if `target` is `None`.
+///
+/// For boards, only DEPEND, RDEPEND and PDEPEND are followed, since
+/// BDEPEND and IDEPEND are installed to the host with the host's keywords.
+///
+/// Returns the packages sorted by name and version, and the number of
+/// packages whose dependencies could not be analyzed.
+fn compute_closure(
+    host: &TargetData,
+    target: Option<&TargetData>,
+    roots: &[PackageAtom],
+) -> Result<(Vec<Arc<PackageDetails>>, usize)> {
+    let world = target.unwrap_or(host);
+    let cross_compile = match target {
+        Some(target) => host.config.env().get("CHOST") != target.config.env().get("CHOST"),
+        None => false,
+    };
+
+    let mut visited: HashMap<PathBuf, Arc<PackageDetails>> = HashMap::new();
+    let mut queue: VecDeque<Arc<PackageDetails>> = VecDeque::new();
+    for atom in roots {
+        match world.resolver.find_best_package(atom)? {
+            Some(details) => {
+                if visited
+                    .insert(details.as_basic_data().ebuild_path.clone(), details.clone())
+                    .is_none()
+                {
+                    queue.push_back(details);
+                }
+            }
+            None => eprintln!("WARNING: No package matches {atom} for {}", world.board),
+        }
+    }
+
+    let mut failed = 0;
+    while let Some(details) = queue.pop_front() {
+        let deps = match analyze_direct_dependencies(
+            &details,
+            cross_compile,
+            &host.resolver,
+            &world.resolver,
+        ) {
+            Ok((deps, _expressions)) => deps,
+            Err(_) => {
+                failed += 1;
+                continue;
+            }
+        };
+
+        for kind in DependencyKind::ALL {
+            if target.is_some()
+                && matches!(
+                    kind,
+                    DependencyKind::BuildHost | DependencyKind::InstallHost
+                )
+            {
+                continue;
+            }
+            for dep in deps.get(kind) {
+                if let Entry::Vacant(entry) = visited.entry(dep.as_basic_data().ebuild_path.clone())
+                {
+                    entry.insert(dep.clone());
+                    queue.push_back(dep.clone());
+                }
+            }
+        }
+    }
+
+    let packages = visited
+        .into_values()
+        .sorted_by(|a, b| {
+            let a = a.as_basic_data();
+            let b = b.as_basic_data();
+            a.package_name
+                .cmp(&b.package_name)
+                .then_with(|| a.version.cmp(&b.version))
+        })
+        .collect();
+    Ok((packages, failed))
+}
+
+/// Computes the matrix rows of `packages` as seen by `target`.
+fn compute_rows(target: &TargetData, packages: &[Arc<PackageDetails>]) -> Result<Vec<MatrixRow>> {
+    let arch = target.config.env().get("ARCH").cloned().unwrap_or_default();
+
+    let mut rows = Vec::new();
+    for selected in packages {
+        let package_name = selected.as_basic_data().package_name.clone();
+
+        // Portage does not know about --force-accept-9999-ebuilds, so exclude
+        // versions accepted only by it.
+        let atom = PackageAtom::from_str(&package_name)?;
+        let portage_candidates = target
+            .resolver
+            .find_packages(&atom)?
+            .iter()
+            .filter_map(|candidate| match candidate {
+                MaybePackageDetails::Ok(details) if details.readiness.ok() => {
+                    match is_accepted_by_config(&target.config, details) {
+                        Ok(true) => Some(Ok(details.clone())),
+                        Ok(false) => None,
+                        Err(err) => Some(Err(err)),
+                    }
+                }
+                _ => None,
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let portage_version = select_best_version(&portage_candidates)
+            .map(|details| details.as_basic_data().version.to_string());
+
+        rows.push(MatrixRow {
+            board: target.board.clone(),
+            arch: arch.clone(),
+            package_name,
+            version: selected.as_basic_data().version.to_string(),
+            status: classify(&target.config, selected)?,
+            portage_version,
+        });
+    }
+
+    Ok(rows)
+}
+
+/// Quotes a CSV field if needed.
+fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+fn format_csv(rows: &[MatrixRow]) -> String {
+    let mut output = String::from("board,arch,package,version,status,portage_version\n");
+    for row in rows {
+        let fields = [
+            row.board.as_str(),
+            row.arch.as_str(),
+            row.package_name.as_str(),
+            row.version.as_str(),
+            row.status.as_str(),
+            row.portage_version.as_deref().unwrap_or_default(),
+        ];
+        output.push_str(&fields.into_iter().map(escape_csv).join(","));
+        output.push('\n');
+    }
+    output
+}
+
+/// The entry point of "keyword-matrix" subcommand.
+pub fn keyword_matrix_main(host: &TargetData, targets: &[TargetData], args: Args) -> Result<()> {
+    let targets = if targets.is_empty() {
+        vec![None]
+    } else {
+        targets.iter().map(Some).collect_vec()
+    };
+
+    let mut rows = Vec::new();
+    for target in targets {
+        let roots = if !args.packages.is_empty() {
+            args.packages.iter().map(String::as_str).collect_vec()
+        } else if target.is_some() {
+            vec![
+                "virtual/target-os",
+                "virtual/target-os-dev",
+                "virtual/target-os-test",
+            ]
+        } else {
+            vec!["virtual/target-sdk"]
+        };
+        let roots = roots
+            .into_iter()
+            .map(PackageAtom::from_str)
+            .collect::<Result<Vec<_>>>()?;
+
+        let world = target.unwrap_or(host);
+        let (packages, failed) = compute_closure(host, target, &roots)?;
+        if failed > 0 {
+            eprintln!(
+                "WARNING: {failed} packages failed to analyze for {} and their \
+                 dependencies were skipped",
+                world.board
+            );
+        }
+        rows.extend(
+            compute_rows(world, &packages)?
+                .into_iter()
+                .filter(|row| !args.forced_only || row.status == KeywordStatus::Forced9999),
+        );
+    }
+
+    match args.format {
+        OutputFormat::Csv => {
+            print!("{}", format_csv(&rows));
+        }
+        OutputFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(&rows)?);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use alchemist::{
+        bash::vars::{BashValue, BashVars},
+        config::{ConfigNode, ConfigNodeValue, SimpleConfigSource},
+        data::{Slot, UseMap},
+        ebuild::{
+            metadata::{EBuildBasicData, EBuildMetadata},
+            PackageReadiness,
+        },
+    };
+
+    use super::*;
+
+    fn new_config(accept_keywords: &str) -> ConfigBundle {
+        ConfigBundle::from_sources([SimpleConfigSource::new(vec![ConfigNode {
+            sources: vec![PathBuf::from("<fake>")],
+            value: ConfigNodeValue::Vars(HashMap::from_iter([
+                ("ARCH".into(), "amd64".into()),
+                ("ACCEPT_KEYWORDS".into(), accept_keywords.into()),
+            ])),
+        }])])
+    }
+
+    fn new_package(version: &str, keywords: &str, stable: bool) -> PackageDetails {
+        PackageDetails {
+            metadata: Arc::new(EBuildMetadata {
+                basic_data: EBuildBasicData {
+                    repo_name: "baz".to_owned(),
+                    ebuild_path: PathBuf::from(format!("/path/to/bar-{version}.ebuild")),
+                    package_name: "foo/bar".to_owned(),
+                    short_package_name: "bar".to_owned(),
+                    category_name: "foo".to_owned(),
+                    version: version.parse().unwrap(),
+                },
+                vars: BashVars::new(HashMap::from([(
+                    "KEYWORDS".to_owned(),
+                    BashValue::Scalar(keywords.to_owned()),
+                )])),
+            }),
+            slot: Slot::new("0"),
+            use_map: UseMap::new(),
+            stable,
+            readiness: PackageReadiness::Ok,
+            inherited: HashSet::new(),
+            inherit_paths: vec![],
+            direct_build_target: None,
+            bazel_metadata: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_is_accepted_by_config() -> Result<()> {
+        let stable_config = new_config("amd64");
+        let testing_config = new_config("amd64 ~amd64");
+
+        let stable = new_package("1.0", "*", true);
+        assert!(is_accepted_by_config(&stable_config, &stable)?);
+        assert!(is_accepted_by_config(&testing_config, &stable)?);
+
+        let testing = new_package("1.0", "~*", false);
+        assert!(!is_accepted_by_config(&stable_config, &testing)?);
+        assert!(is_accepted_by_config(&testing_config, &testing)?);
+
+        let unkeyworded = new_package("9999", "-* ~arm64", false);
+        assert!(!is_accepted_by_config(&stable_config, &unkeyworded)?);
+        assert!(!is_accepted_by_config(&testing_config, &unkeyworded)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_classify() -> Result<()> {
+        let config = new_config("amd64 ~amd64");
+
+        assert_eq!(
+            classify(&config, &new_package("1.0", "*", true))?,
+            KeywordStatus::Stable
+        );
+        assert_eq!(
+            classify(&config, &new_package("1.0", "~*", false))?,
+            KeywordStatus::Testing
+        );
+        assert_eq!(
+            classify(&config, &new_package("9999", "~*", false))?,
+            KeywordStatus::Testing
+        );
+        assert_eq!(
+            classify(&new_config("amd64"), &new_package("9999", "~*", false))?,
+            KeywordStatus::Forced9999
+        );
+        assert_eq!(
+            classify(&config, &new_package("9999", "-*", false))?,
+            KeywordStatus::Forced9999
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_csv() {
+        let rows = [
+            MatrixRow {
+                board: "betty".to_owned(),
+                arch: "amd64".to_owned(),
+                package_name: "chromeos-base/foo".to_owned(),
+                version: "9999".to_owned(),
+                status: KeywordStatus::Forced9999,
+                portage_version: Some("0.0.1-r100".to_owned()),
+            },
+            MatrixRow {
+                board: "arm,\"64\"".to_owned(),
+                arch: "arm64".to_owned(),
+                package_name: "sys-libs/bar".to_owned(),
+                version: "1.0".to_owned(),
+                status: KeywordStatus::Testing,
+                portage_version: None,
+            },
+        ];
+
+        assert_eq!(
+            format_csv(&rows),
+            "board,arch,package,version,status,portage_version\n\
+             betty,amd64,chromeos-base/foo,9999,forced-9999,0.0.1-r100\n\
+             \"arm,\"\"64\"\"\",arm64,sys-libs/bar,1.0,testing,\n"
+        );
+    }
+
+    #[test]
+    fn test_status_json() -> Result<()> {
+        assert_eq!(
+            serde_json::to_string(&KeywordStatus::Forced9999)?,
+            "\"forced-9999\""
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+// Copyright 2024 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+use std::path::Path;
+
+use alchemist::{
+    analyze::MaybePackage,
+    ebuild::MaybePackageDetails,
+    lint::{lint_config, lint_packages, lint_toml_metadata, LintIssue, LintSeverity},
+};
+use anyhow::{bail, Result};
+use itertools::Itertools;
+
+use crate::{alchemist::TargetData, generate_repo::load_packages};
+
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    /// Exits with a non-zero status on warnings as well as errors.
+    #[arg(long)]
+    fail_on_warnings: bool,
+}
+
+/// Prints issues grouped by their kinds, errors first.
+pub fn print_report(issues: &[LintIssue]) {
+    for (kind, group) in &issues.iter().sorted().group_by(|issue| issue.kind) {
+        let group = group.collect_vec();
+        let severity = match kind.severity() {
+            LintSeverity::Error => "ERROR",
+            LintSeverity::Warning => "WARNING",
+        };
+        println!("{}: {} ({})", severity, kind.description(), group.len());
+        for issue in group {
+            println!("  {}", issue.location.display());
+            for line in issue.message.lines() {
+                println!("    {}", line);
+            }
+        }
+        println!();
+    }
+}
+
+/// The entry point of "lint" subcommand.
+pub fn lint_main(
+    host: &TargetData,
+    target: Option<&TargetData>,
+    src_dir: &Path,
+    args: Args,
+) -> Result<()> {
+    let target = target.unwrap_or(host);
+
+    let packages = load_packages(host, target, src_dir)?;
+    let all_details = packages
+        .iter()
+        .map(|package| match package {
+            MaybePackage::Ok(package) => MaybePackageDetails::Ok(package.details.clone()),
+            MaybePackage::Err(error) => error.details.clone(),
+        })
+        .collect_vec();
+
+    let mut issues = lint_packages(&packages);
+    issues.extend(lint_toml_metadata(&target.repos)?);
+    issues.extend(lint_config(&target.config, &all_details));
+
+    print_report(&issues);
+
+    let (errors, warnings): (Vec<_>, Vec<_>) = issues
+        .iter()
+        .partition(|issue| issue.kind.severity() == LintSeverity::Error);
+    eprintln!(
+        "Found {} errors and {} warnings in {}:{}",
+        errors.len(),
+        warnings.len(),
+        target.board,
+        target.profile
+    );
+
+    if !errors.is_empty() || (args.fail_on_warnings && !warnings.is_empty()) {
+        bail!("lint failed");
+    }
+    Ok(())
+}
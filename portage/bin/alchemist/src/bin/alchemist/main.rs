@@ -6,14 +6,29 @@ mod alchemist;
 mod digest_repo;
 mod dump_package;
 mod dump_profile;
+mod dump_toolchains;
+mod fmt_deps;
 mod generate_repo;
+mod keyword_matrix;
+mod lint;
+mod query;
+mod sdk_stages;
+mod serve;
+mod slow_report;
+mod subslot_impact;
+mod ver_cut;
 mod ver_rs;
 mod ver_test;
+mod verify_distfiles;
+mod verify_workon_trees;
+mod what_if;
+mod why_depends;
 
 use std::process::ExitCode;
 
 use crate::alchemist::alchemist_main;
 use clap::{Parser, Subcommand};
+use ver_cut::ver_cut_main;
 use ver_rs::ver_rs_main;
 use ver_test::ver_test_main;
 
@@ -25,9 +40,13 @@ struct Cli {
 }
 
 #[derive(Subcommand, Debug)]
+#[allow(clippy::large_enum_variant)] // Parsed only once at startup.
 enum Executables {
     Alchemist(alchemist::Args),
 
+    #[command(name = "ver_cut")] // Otherwise we get ver-cut
+    VerCut(ver_cut::Args),
+
     #[command(name = "ver_rs")] // Otherwise we get ver-rs
     VerRs(ver_rs::Args),
 
@@ -38,6 +57,7 @@ enum Executables {
 fn main() -> ExitCode {
     let result = match Cli::parse().executables {
         Executables::Alchemist(args) => alchemist_main(args),
+        Executables::VerCut(args) => ver_cut_main(args),
         Executables::VerRs(args) => ver_rs_main(args),
         Executables::VerTest(args) => ver_test_main(args),
     };
@@ -0,0 +1,120 @@
+// Copyright 2024 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+use std::path::PathBuf;
+
+use alchemist::{analyze::query::PackageQuery, ebuild::MaybePackageDetails};
+use anyhow::Result;
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::alchemist::TargetData;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default)]
+enum OutputFormat {
+    #[default]
+    Table,
+    Json,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    /// Output format.
+    #[arg(long, value_enum, default_value_t)]
+    format: OutputFormat,
+
+    /// Query to evaluate against packages, e.g.
+    /// `inherit:cros-rust and var:CROS_RUST_FEATURES=z`. Multiple arguments
+    /// are joined with spaces.
+    ///
+    /// Predicates: inherit:ECLASS, iuse:FLAG, use:FLAG, category:NAME,
+    /// repo:NAME, var:NAME, var:NAME=VALUE, metallurgy-target,
+    /// interface-libraries, stable, masked. Combine them with and, or, not
+    /// and parentheses.
+    #[arg(required = true)]
+    query: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct QueryResult {
+    package_name: String,
+    version: String,
+    repo_name: String,
+    slot: String,
+    stable: bool,
+    masked: bool,
+    ebuild_path: PathBuf,
+}
+
+/// The entry point of "query" subcommand.
+pub fn query_main(host: &TargetData, target: Option<&TargetData>, args: Args) -> Result<()> {
+    let query: PackageQuery = args.query.join(" ").parse()?;
+
+    let resolver = &target.unwrap_or(host).resolver;
+
+    let mut matched = Vec::new();
+    let mut failed = 0;
+    for package in resolver.find_all_packages()? {
+        match package {
+            MaybePackageDetails::Ok(details) => {
+                if query.matches(&details)? {
+                    matched.push(details);
+                }
+            }
+            MaybePackageDetails::Err(_) => failed += 1,
+        }
+    }
+    matched.sort_by(|a, b| {
+        let a = a.as_basic_data();
+        let b = b.as_basic_data();
+        a.package_name
+            .cmp(&b.package_name)
+            .then(a.version.cmp(&b.version))
+            .then(a.repo_name.cmp(&b.repo_name))
+    });
+
+    let results = matched
+        .into_iter()
+        .map(|details| QueryResult {
+            package_name: details.as_basic_data().package_name.clone(),
+            version: details.as_basic_data().version.to_string(),
+            repo_name: details.as_basic_data().repo_name.clone(),
+            slot: details.slot.to_string(),
+            stable: details.stable,
+            masked: !details.readiness.ok(),
+            ebuild_path: details.as_basic_data().ebuild_path.clone(),
+        })
+        .collect_vec();
+
+    match args.format {
+        OutputFormat::Table => {
+            for result in &results {
+                println!(
+                    "{}-{}::{}\t{}\t{}",
+                    result.package_name,
+                    result.version,
+                    result.repo_name,
+                    result.slot,
+                    [
+                        result.stable.then_some("stable"),
+                        result.masked.then_some("masked"),
+                    ]
+                    .into_iter()
+                    .flatten()
+                    .join(",")
+                );
+            }
+        }
+        OutputFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(&results)?);
+        }
+    }
+
+    eprintln!("{} packages matched", results.len());
+    if failed > 0 {
+        eprintln!("WARNING: {failed} packages failed to load and were skipped");
+    }
+
+    Ok(())
+}
@@ -15,6 +15,9 @@ ALCHEMIST_REPO_RULE_SRCS = [
     "@cros//bazel/portage/bin/alchemist/src/bin/alchemist:digest_repo.rs",
     "@cros//bazel/portage/bin/alchemist/src/bin/alchemist:dump_package.rs",
     "@cros//bazel/portage/bin/alchemist/src/bin/alchemist:dump_profile.rs",
+    "@cros//bazel/portage/bin/alchemist/src/bin/alchemist:dump_toolchains.rs",
+    "@cros//bazel/portage/bin/alchemist/src/bin/alchemist:fmt_deps.rs",
+    "@cros//bazel/portage/bin/alchemist/src/bin/alchemist:generate_repo/backend.rs",
     "@cros//bazel/portage/bin/alchemist/src/bin/alchemist:generate_repo/common.rs",
     "@cros//bazel/portage/bin/alchemist/src/bin/alchemist:generate_repo/deps.rs",
     "@cros//bazel/portage/bin/alchemist/src/bin/alchemist:generate_repo/internal/bashrcs/mod.rs",
@@ -45,9 +48,21 @@ ALCHEMIST_REPO_RULE_SRCS = [
     "@cros//bazel/portage/bin/alchemist/src/bin/alchemist:generate_repo/public/templates/images.BUILD.bazel",
     "@cros//bazel/portage/bin/alchemist/src/bin/alchemist:generate_repo/public/templates/package.BUILD.bazel",
     "@cros//bazel/portage/bin/alchemist/src/bin/alchemist:generate_repo/templates/root.BUILD.bazel",
+    "@cros//bazel/portage/bin/alchemist/src/bin/alchemist:keyword_matrix.rs",
+    "@cros//bazel/portage/bin/alchemist/src/bin/alchemist:lint.rs",
     "@cros//bazel/portage/bin/alchemist/src/bin/alchemist:main.rs",
+    "@cros//bazel/portage/bin/alchemist/src/bin/alchemist:query.rs",
+    "@cros//bazel/portage/bin/alchemist/src/bin/alchemist:sdk_stages.rs",
+    "@cros//bazel/portage/bin/alchemist/src/bin/alchemist:serve.rs",
+    "@cros//bazel/portage/bin/alchemist/src/bin/alchemist:slow_report.rs",
+    "@cros//bazel/portage/bin/alchemist/src/bin/alchemist:subslot_impact.rs",
+    "@cros//bazel/portage/bin/alchemist/src/bin/alchemist:ver_cut.rs",
     "@cros//bazel/portage/bin/alchemist/src/bin/alchemist:ver_rs.rs",
     "@cros//bazel/portage/bin/alchemist/src/bin/alchemist:ver_test.rs",
+    "@cros//bazel/portage/bin/alchemist/src/bin/alchemist:verify_distfiles.rs",
+    "@cros//bazel/portage/bin/alchemist/src/bin/alchemist:verify_workon_trees.rs",
+    "@cros//bazel/portage/bin/alchemist/src/bin/alchemist:what_if.rs",
+    "@cros//bazel/portage/bin/alchemist/src/bin/alchemist:why_depends.rs",
     "@cros//bazel/portage/bin/alchemist:BUILD.bazel",
     "@cros//bazel/portage/bin/alchemist:src/analyze/dependency/direct/flatten.rs",
     "@cros//bazel/portage/bin/alchemist:src/analyze/dependency/direct/hacks.rs",
@@ -55,7 +70,9 @@ ALCHEMIST_REPO_RULE_SRCS = [
     "@cros//bazel/portage/bin/alchemist:src/analyze/dependency/direct/slot.rs",
     "@cros//bazel/portage/bin/alchemist:src/analyze/dependency/indirect.rs",
     "@cros//bazel/portage/bin/alchemist:src/analyze/dependency/mod.rs",
+    "@cros//bazel/portage/bin/alchemist:src/analyze/dependency/subslot.rs",
     "@cros//bazel/portage/bin/alchemist:src/analyze/mod.rs",
+    "@cros//bazel/portage/bin/alchemist:src/analyze/query.rs",
     "@cros//bazel/portage/bin/alchemist:src/analyze/restrict.rs",
     "@cros//bazel/portage/bin/alchemist:src/analyze/source.rs",
     "@cros//bazel/portage/bin/alchemist:src/analyze/tests.rs",
@@ -92,16 +109,17 @@ ALCHEMIST_REPO_RULE_SRCS = [
     "@cros//bazel/portage/bin/alchemist:src/dependency/restrict/parser.rs",
     "@cros//bazel/portage/bin/alchemist:src/dependency/uri/mod.rs",
     "@cros//bazel/portage/bin/alchemist:src/dependency/uri/parser.rs",
+    "@cros//bazel/portage/bin/alchemist:src/ebuild/eapi.rs",
     "@cros//bazel/portage/bin/alchemist:src/ebuild/ebuild_prelude.sh",
     "@cros//bazel/portage/bin/alchemist:src/ebuild/metadata.rs",
     "@cros//bazel/portage/bin/alchemist:src/ebuild/mod.rs",
     "@cros//bazel/portage/bin/alchemist:src/fakechroot.rs",
     "@cros//bazel/portage/bin/alchemist:src/fileops.rs",
     "@cros//bazel/portage/bin/alchemist:src/lib.rs",
+    "@cros//bazel/portage/bin/alchemist:src/lint.rs",
     "@cros//bazel/portage/bin/alchemist:src/path.rs",
     "@cros//bazel/portage/bin/alchemist:src/repository.rs",
     "@cros//bazel/portage/bin/alchemist:src/resolver.rs",
-    "@cros//bazel/portage/bin/alchemist:src/simpleversion.rs",
     "@cros//bazel/portage/bin/alchemist:src/testutils.rs",
     "@cros//bazel/portage/bin/alchemist:src/toolchain/mod.rs",
     "@cros//bazel/portage/common/chrome_trace:BUILD.bazel",
@@ -122,6 +140,7 @@ ALCHEMIST_REPO_RULE_SRCS = [
     "@cros//bazel/portage/common/fileutil:src/xattr.rs",
     "@cros//bazel/portage/common/portage/version:BUILD.bazel",
     "@cros//bazel/portage/common/portage/version:src/lib.rs",
+    "@cros//bazel/portage/common/portage/version:src/manip.rs",
     "@cros//bazel/portage/common/portage/version:src/version.rs",
     "@cros//bazel/portage/common/tracing_chrome_trace:BUILD.bazel",
     "@cros//bazel/portage/common/tracing_chrome_trace:src/lib.rs",
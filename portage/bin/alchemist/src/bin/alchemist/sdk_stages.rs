@@ -0,0 +1,547 @@
+// Copyright 2024 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+use std::{collections::BTreeMap, path::Path};
+
+use alchemist::{dependency::package::AsPackageRef, resolver::PackageResolver};
+use anyhow::{Context, Result};
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::{
+    alchemist::TargetData,
+    generate_repo::{
+        board_sdk_stage_name, compute_bootstrap_packages_with_dependents,
+        compute_provided_packages, get_sdk_implicit_system_package,
+        internal::sdk::{get_cross_glibc, get_primordial_packages, get_toolchain_packages},
+        load_packages, SdkBasePackages, SdkStage, SdkStageKind, HOST_SDK_STAGES,
+    },
+};
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default)]
+enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    /// Output format.
+    #[arg(long, value_enum, default_value_t)]
+    format: OutputFormat,
+}
+
+/// Why a package is installed by an SDK stage.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+enum InclusionReason {
+    /// The package defining the implicit system set, i.e.
+    /// virtual/target-sdk-implicit-system.
+    ImplicitSystem,
+    /// In the transitive RDEPEND of the implicit system set, so the SDK
+    /// provides it to all packages built with it.
+    Provided,
+    /// A BDEPEND or IDEPEND of packages in the transitive DEPEND and RDEPEND
+    /// of the implicit system set, needed to rebuild the set.
+    BootstrapBdepend { required_by: Vec<String> },
+    /// One of the primordial packages installed into the sysroot.
+    Primordial,
+    /// Part of the cross compiler installed for the board's toolchain.
+    Toolchain,
+}
+
+impl InclusionReason {
+    fn describe(&self) -> String {
+        match self {
+            Self::ImplicitSystem => "implicit system".to_owned(),
+            Self::Provided => "provided".to_owned(),
+            Self::BootstrapBdepend { required_by } => {
+                format!("bootstrap BDEPEND of {}", required_by.join(", "))
+            }
+            Self::Primordial => "primordial".to_owned(),
+            Self::Toolchain => "toolchain".to_owned(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+struct StagePackage {
+    package_name: String,
+    version: String,
+    reason: InclusionReason,
+}
+
+impl StagePackage {
+    fn new(package: &impl AsPackageRef, reason: InclusionReason) -> Self {
+        let package = package.as_package_ref();
+        Self {
+            package_name: package.package_name.to_owned(),
+            version: package.version.to_string(),
+            reason,
+        }
+    }
+
+    fn cpv(&self) -> String {
+        format!("{}-{}", self.package_name, self.version)
+    }
+}
+
+/// The difference between the packages of a stage and its previous stage.
+#[derive(Debug, Default, Eq, PartialEq, Serialize)]
+struct StageDiff {
+    added: Vec<String>,
+    removed: Vec<String>,
+    /// Packages whose versions differ, e.g. `sys-libs/zlib: 1.2.11 -> 1.2.13`.
+    changed: Vec<String>,
+}
+
+impl StageDiff {
+    fn compute(previous: &[StagePackage], current: &[StagePackage]) -> Self {
+        let previous: BTreeMap<_, _> = previous
+            .iter()
+            .map(|package| (&package.package_name, package))
+            .collect();
+        let current: BTreeMap<_, _> = current
+            .iter()
+            .map(|package| (&package.package_name, package))
+            .collect();
+
+        let mut diff = Self::default();
+        for (name, package) in &current {
+            match previous.get(name) {
+                None => diff.added.push(package.cpv()),
+                Some(old) if old.version != package.version => diff
+                    .changed
+                    .push(format!("{}: {} -> {}", name, old.version, package.version)),
+                Some(_) => {}
+            }
+        }
+        for (name, package) in &previous {
+            if !current.contains_key(name) {
+                diff.removed.push(package.cpv());
+            }
+        }
+        diff
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct StageReport {
+    name: String,
+    /// The stage the packages are compared with.
+    previous: Option<String>,
+    /// Whether the stage is a target SDK, as opposed to a base SDK.
+    #[serde(skip)]
+    is_target: bool,
+    packages: Vec<StagePackage>,
+    diff: Option<StageDiff>,
+}
+
+/// Appends `new` to `packages` unless a package of the same name is already
+/// listed, in which case the earlier reason wins.
+fn add_packages(packages: &mut Vec<StagePackage>, new: impl IntoIterator<Item = StagePackage>) {
+    for package in new {
+        if !packages
+            .iter()
+            .any(|existing| existing.package_name == package.package_name)
+        {
+            packages.push(package);
+        }
+    }
+}
+
+fn primordial_packages(resolver: &PackageResolver) -> Result<Vec<StagePackage>> {
+    Ok(get_primordial_packages(resolver)?
+        .iter()
+        .map(|details| StagePackage::new(details.as_ref(), InclusionReason::Primordial))
+        .collect())
+}
+
+/// Computes the packages installed by each SDK stage in
+/// [`HOST_SDK_STAGES`] and by the board SDKs.
+///
+/// Host SDKs, e.g. stage2/host, install no packages on top of their base
+/// stage and are not listed.
+fn compute_stages(
+    host: &TargetData,
+    targets: &[TargetData],
+    src_dir: &Path,
+) -> Result<Vec<StageReport>> {
+    let host_packages = load_packages(host, host, src_dir)?;
+    let packages_by_path = host_packages
+        .iter()
+        .map(|package| {
+            (
+                package.as_basic_data().ebuild_path.as_path(),
+                package.into(),
+            )
+        })
+        .collect();
+
+    let implicit_system_package = get_sdk_implicit_system_package(&host_packages)?;
+
+    let mut implicit_system = vec![StagePackage::new(
+        &implicit_system_package.details,
+        InclusionReason::ImplicitSystem,
+    )];
+    add_packages(
+        &mut implicit_system,
+        compute_provided_packages(&packages_by_path, &implicit_system_package)?
+            .iter()
+            .map(|package| StagePackage {
+                package_name: package.package_name.clone(),
+                version: package.version.to_string(),
+                reason: InclusionReason::Provided,
+            }),
+    );
+
+    let mut bootstrap = implicit_system.clone();
+    add_packages(
+        &mut bootstrap,
+        compute_bootstrap_packages_with_dependents(&packages_by_path, &implicit_system_package)?
+            .into_iter()
+            .map(|(package, dependents)| {
+                StagePackage::new(
+                    &package.details,
+                    InclusionReason::BootstrapBdepend {
+                        required_by: dependents
+                            .iter()
+                            .map(|dependent| {
+                                let dependent = dependent.as_basic_data();
+                                format!("{}-{}", dependent.package_name, dependent.version)
+                            })
+                            .sorted()
+                            .dedup()
+                            .collect(),
+                    },
+                )
+            }),
+    );
+
+    let host_primordial = primordial_packages(&host.resolver)?;
+
+    let boards = targets
+        .iter()
+        .map(|target| {
+            let toolchain = target
+                .toolchains
+                .primary()
+                .with_context(|| format!("{} is missing primary toolchain", target.board))?;
+
+            let mut packages = primordial_packages(&target.resolver)?;
+            add_packages(
+                &mut packages,
+                std::iter::once(get_cross_glibc(toolchain, &host.resolver)?)
+                    .chain(get_toolchain_packages(toolchain, &host.resolver)?)
+                    .map(|details| StagePackage::new(details.as_ref(), InclusionReason::Toolchain)),
+            );
+            Ok((board_sdk_stage_name(target.repos.name()), packages))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(assemble_stages(
+        &HOST_SDK_STAGES,
+        &StageContents {
+            host_primordial,
+            implicit_system,
+            bootstrap,
+        },
+        boards,
+    ))
+}
+
+/// The package sets installed by the host SDK stages.
+struct StageContents {
+    host_primordial: Vec<StagePackage>,
+    implicit_system: Vec<StagePackage>,
+    bootstrap: Vec<StagePackage>,
+}
+
+/// Lays out `stages` and the board stages as reports, comparing each stage
+/// with the latest earlier stage of the same kind.
+fn assemble_stages(
+    stages: &[SdkStage],
+    contents: &StageContents,
+    boards: Vec<(String, Vec<StagePackage>)>,
+) -> Vec<StageReport> {
+    let mut reports: Vec<StageReport> = Vec::new();
+    let mut add_report = |name: String, is_target: bool, packages: Vec<StagePackage>| {
+        let previous = reports
+            .iter()
+            .rev()
+            .find(|report| report.is_target == is_target);
+        let diff = previous.map(|previous| StageDiff::compute(&previous.packages, &packages));
+        reports.push(StageReport {
+            name,
+            previous: previous.map(|previous| previous.name.clone()),
+            is_target,
+            packages,
+            diff,
+        });
+    };
+
+    for stage in stages {
+        let (is_target, packages) = match stage.kind {
+            SdkStageKind::Target { .. } => (true, &contents.host_primordial),
+            SdkStageKind::Base {
+                packages: SdkBasePackages::ImplicitSystem,
+                ..
+            } => (false, &contents.implicit_system),
+            SdkStageKind::Base {
+                packages: SdkBasePackages::Bootstrap,
+                ..
+            } => (false, &contents.bootstrap),
+            SdkStageKind::Host { .. } => continue,
+        };
+        add_report(stage.name.to_owned(), is_target, packages.clone());
+    }
+
+    for (name, packages) in boards {
+        add_report(name, true, packages);
+    }
+
+    reports
+}
+
+fn format_text(stages: &[StageReport]) -> String {
+    let mut output = String::new();
+    for stage in stages {
+        match &stage.previous {
+            Some(previous) => {
+                output.push_str(&format!("{} (previous: {})\n", stage.name, previous))
+            }
+            None => output.push_str(&format!("{}\n", stage.name)),
+        }
+
+        output.push_str(&format!("  Packages ({}):\n", stage.packages.len()));
+        for package in stage.packages.iter().sorted_by_key(|p| &p.package_name) {
+            output.push_str(&format!(
+                "    {}: {}\n",
+                package.cpv(),
+                package.reason.describe()
+            ));
+        }
+
+        if let Some(diff) = &stage.diff {
+            for (title, entries) in [
+                ("Added", &diff.added),
+                ("Removed", &diff.removed),
+                ("Changed", &diff.changed),
+            ] {
+                if entries.is_empty() {
+                    continue;
+                }
+                output.push_str(&format!("  {} ({}):\n", title, entries.len()));
+                for entry in entries {
+                    output.push_str(&format!("    {}\n", entry));
+                }
+            }
+        }
+        output.push('\n');
+    }
+    output
+}
+
+/// The entry point of "sdk-stages" subcommand.
+pub fn sdk_stages_main(
+    host: &TargetData,
+    targets: &[TargetData],
+    src_dir: &Path,
+    args: Args,
+) -> Result<()> {
+    let stages = compute_stages(host, targets, src_dir)?;
+
+    match args.format {
+        OutputFormat::Text => print!("{}", format_text(&stages)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&stages)?),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(package_name: &str, version: &str, reason: InclusionReason) -> StagePackage {
+        StagePackage {
+            package_name: package_name.to_owned(),
+            version: version.to_owned(),
+            reason,
+        }
+    }
+
+    #[test]
+    fn test_stage_diff() {
+        let previous = [
+            package("sys-libs/zlib", "1.2.11", InclusionReason::Provided),
+            package("sys-devel/make", "4.4", InclusionReason::Provided),
+        ];
+        let current = [
+            package("sys-libs/zlib", "1.2.13", InclusionReason::Provided),
+            package(
+                "sys-devel/bison",
+                "3.8.2",
+                InclusionReason::BootstrapBdepend {
+                    required_by: vec!["sys-libs/glibc-2.35".to_owned()],
+                },
+            ),
+        ];
+
+        assert_eq!(
+            StageDiff::compute(&previous, &current),
+            StageDiff {
+                added: vec!["sys-devel/bison-3.8.2".to_owned()],
+                removed: vec!["sys-devel/make-4.4".to_owned()],
+                changed: vec!["sys-libs/zlib: 1.2.11 -> 1.2.13".to_owned()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_assemble_stages() {
+        let contents = StageContents {
+            host_primordial: vec![package(
+                "sys-libs/glibc",
+                "2.35",
+                InclusionReason::Primordial,
+            )],
+            implicit_system: vec![package(
+                "virtual/target-sdk-implicit-system",
+                "1",
+                InclusionReason::ImplicitSystem,
+            )],
+            bootstrap: vec![
+                package(
+                    "virtual/target-sdk-implicit-system",
+                    "1",
+                    InclusionReason::ImplicitSystem,
+                ),
+                package(
+                    "sys-devel/bison",
+                    "3.8.2",
+                    InclusionReason::BootstrapBdepend {
+                        required_by: vec!["sys-libs/glibc-2.35".to_owned()],
+                    },
+                ),
+            ],
+        };
+        let boards = vec![(
+            board_sdk_stage_name("board"),
+            vec![
+                package("sys-libs/glibc", "2.37", InclusionReason::Primordial),
+                package(
+                    "cross-x86_64-cros-linux-gnu/gcc",
+                    "10.2.0",
+                    InclusionReason::Toolchain,
+                ),
+            ],
+        )];
+
+        let reports = assemble_stages(&HOST_SDK_STAGES, &contents, boards);
+
+        assert_eq!(
+            reports
+                .iter()
+                .map(|report| (report.name.as_str(), report.previous.as_deref()))
+                .collect_vec(),
+            vec![
+                ("stage1/target/host", None),
+                ("stage2", None),
+                ("stage3:bootstrap", Some("stage2")),
+                ("stage3/target/host", Some("stage1/target/host")),
+                ("stage4", Some("stage3:bootstrap")),
+                ("stage2/target/board", Some("stage3/target/host")),
+            ]
+        );
+
+        assert_eq!(reports[0].diff, None);
+        assert_eq!(
+            reports[2].diff,
+            Some(StageDiff {
+                added: vec!["sys-devel/bison-3.8.2".to_owned()],
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            reports[4].diff,
+            Some(StageDiff {
+                removed: vec!["sys-devel/bison-3.8.2".to_owned()],
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            reports[5].diff,
+            Some(StageDiff {
+                added: vec!["cross-x86_64-cros-linux-gnu/gcc-10.2.0".to_owned()],
+                changed: vec!["sys-libs/glibc: 2.35 -> 2.37".to_owned()],
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_assemble_stages_skips_host_sdks() {
+        let stages = [
+            SdkStage {
+                name: "base",
+                kind: SdkStageKind::Base {
+                    source_package_prefix: "target",
+                    source_sdk: "target",
+                    packages: SdkBasePackages::ImplicitSystem,
+                },
+            },
+            SdkStage {
+                name: "host",
+                kind: SdkStageKind::Host { base: "base" },
+            },
+        ];
+        let contents = StageContents {
+            host_primordial: vec![],
+            implicit_system: vec![],
+            bootstrap: vec![],
+        };
+
+        let reports = assemble_stages(&stages, &contents, vec![]);
+
+        assert_eq!(
+            reports.iter().map(|report| &report.name).collect_vec(),
+            vec!["base"]
+        );
+    }
+
+    #[test]
+    fn test_format_text() {
+        let stages = [StageReport {
+            name: "stage3:bootstrap".to_owned(),
+            previous: Some("stage2".to_owned()),
+            is_target: false,
+            packages: vec![
+                package("sys-libs/zlib", "1.2.13", InclusionReason::Provided),
+                package(
+                    "sys-devel/bison",
+                    "3.8.2",
+                    InclusionReason::BootstrapBdepend {
+                        required_by: vec!["sys-libs/glibc-2.35".to_owned()],
+                    },
+                ),
+            ],
+            diff: Some(StageDiff {
+                added: vec!["sys-devel/bison-3.8.2".to_owned()],
+                ..Default::default()
+            }),
+        }];
+
+        assert_eq!(
+            format_text(&stages),
+            "stage3:bootstrap (previous: stage2)\n  \
+             Packages (2):\n    \
+             sys-devel/bison-3.8.2: bootstrap BDEPEND of sys-libs/glibc-2.35\n    \
+             sys-libs/zlib-1.2.13: provided\n  \
+             Added (1):\n    \
+             sys-devel/bison-3.8.2\n\n"
+        );
+    }
+}
@@ -0,0 +1,411 @@
+// Copyright 2024 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsStr,
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    os::unix::{
+        io::{AsFd, AsRawFd, RawFd},
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver, Sender},
+    time::{Duration, Instant},
+};
+
+use alchemist::{ebuild::metadata::CachedEBuildEvaluator, fakechroot::PathTranslator};
+use anyhow::{Context, Result};
+use clap::Parser;
+use itertools::Itertools;
+use nix::{
+    sys::inotify::{AddWatchFlags, InitFlags, Inotify, WatchDescriptor},
+    unistd::{close, dup, dup2},
+};
+use walkdir::WalkDir;
+
+use crate::{
+    alchemist::TargetData,
+    dump_package::dump_package_main,
+    generate_repo::{backend::OutputBackendKind, generate_repo_main},
+    why_depends::why_depends_main,
+};
+
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    /// Path of the Unix domain socket to listen on.
+    #[arg(long, value_name = "PATH")]
+    socket: PathBuf,
+}
+
+/// A request sent by a client as a single line of whitespace-separated
+/// arguments, e.g. `dump-package sys-apps/attr`.
+#[derive(Parser, Debug)]
+#[command(no_binary_name = true)]
+enum Request {
+    /// Dumps information of packages.
+    DumpPackage {
+        #[command(flatten)]
+        args: crate::dump_package::Args,
+    },
+    /// Prints the shortest dependency path from one package to another.
+    WhyDepends {
+        #[command(flatten)]
+        args: crate::why_depends::Args,
+    },
+    /// Generates a Bazel repository containing overlays and packages.
+    GenerateRepo {
+        /// Output directory path.
+        #[arg(short = 'o', long, value_name = "PATH")]
+        output_dir: PathBuf,
+
+        #[arg(long)]
+        /// An output path for a json-encoded Vec<deps::Repository>.
+        output_repos_json: PathBuf,
+
+        /// The format to generate package and image build files in.
+        #[arg(long, value_enum, default_value_t)]
+        backend: OutputBackendKind,
+    },
+}
+
+/// How a change to a file affects the in-memory state.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+enum ChangeKind {
+    /// An ebuild was changed, added or removed.
+    Ebuild,
+    /// An eclass was changed, added or removed.
+    Eclass,
+    /// A profile or a config file was changed, so configs must be reloaded.
+    Config,
+    /// The change doesn't affect the cached state, e.g. files under `files/`
+    /// which are inspected on every analysis.
+    Ignored,
+}
+
+fn classify_change(path: &Path, repo_dirs: &[PathBuf]) -> ChangeKind {
+    match path.extension().and_then(OsStr::to_str) {
+        Some("ebuild") => return ChangeKind::Ebuild,
+        Some("eclass") => return ChangeKind::Eclass,
+        _ => {}
+    }
+    for repo_dir in repo_dirs {
+        if let Ok(relative_path) = path.strip_prefix(repo_dir) {
+            return match relative_path.components().next() {
+                Some(component)
+                    if component.as_os_str() == "profiles"
+                        || component.as_os_str() == "metadata" =>
+                {
+                    ChangeKind::Config
+                }
+                _ => ChangeKind::Ignored,
+            };
+        }
+    }
+    // Paths outside of repositories are config directories of sysroots.
+    ChangeKind::Config
+}
+
+/// Watches directory trees recursively with inotify.
+struct Watcher {
+    inotify: Inotify,
+    dirs: HashMap<WatchDescriptor, PathBuf>,
+}
+
+impl Watcher {
+    const WATCH_FLAGS: AddWatchFlags = AddWatchFlags::IN_CLOSE_WRITE
+        .union(AddWatchFlags::IN_CREATE)
+        .union(AddWatchFlags::IN_DELETE)
+        .union(AddWatchFlags::IN_MOVED_FROM)
+        .union(AddWatchFlags::IN_MOVED_TO);
+
+    fn new() -> Result<Self> {
+        Ok(Self {
+            inotify: Inotify::init(InitFlags::IN_CLOEXEC)?,
+            dirs: HashMap::new(),
+        })
+    }
+
+    /// Starts watching all directories under `root`.
+    fn add_tree(&mut self, root: &Path) -> Result<()> {
+        for entry in WalkDir::new(root) {
+            let entry = entry?;
+            if !entry.file_type().is_dir() {
+                continue;
+            }
+            let wd = self
+                .inotify
+                .add_watch(entry.path(), Self::WATCH_FLAGS)
+                .with_context(|| format!("Failed to watch {}", entry.path().display()))?;
+            self.dirs.insert(wd, entry.path().to_owned());
+        }
+        Ok(())
+    }
+
+    /// Blocks until files are changed and returns their paths.
+    fn read_changes(&mut self) -> Result<Vec<PathBuf>> {
+        let mut changed_paths = Vec::new();
+        for event in self.inotify.read_events()? {
+            let (Some(dir), Some(name)) = (self.dirs.get(&event.wd), &event.name) else {
+                continue;
+            };
+            let path = dir.join(name);
+            if event.mask.contains(AddWatchFlags::IN_ISDIR) {
+                if event
+                    .mask
+                    .intersects(AddWatchFlags::IN_CREATE | AddWatchFlags::IN_MOVED_TO)
+                {
+                    self.add_tree(&path)?;
+                }
+                continue;
+            }
+            changed_paths.push(path);
+        }
+        Ok(changed_paths)
+    }
+
+    /// Keeps sending changed paths until the receiver is dropped.
+    ///
+    /// Errors are written to `log` rather than to stderr, which is redirected
+    /// to the client socket while a request is being served.
+    fn run(mut self, sender: Sender<Vec<PathBuf>>, mut log: File) {
+        loop {
+            match self.read_changes() {
+                Ok(changed_paths) if changed_paths.is_empty() => {}
+                Ok(changed_paths) => {
+                    if sender.send(changed_paths).is_err() {
+                        return;
+                    }
+                }
+                Err(err) => {
+                    let _ = writeln!(log, "WARNING: Failed to watch files: {err:#}");
+                }
+            }
+        }
+    }
+}
+
+/// Holds the in-memory state kept warm between requests.
+struct ServeState<'a> {
+    host: TargetData,
+    target: Option<TargetData>,
+    evaluator: &'a CachedEBuildEvaluator,
+    load_targets: &'a dyn Fn() -> Result<(TargetData, Option<TargetData>)>,
+    repo_dirs: Vec<PathBuf>,
+}
+
+impl ServeState<'_> {
+    /// Drops the cached state affected by changes to `changed_paths`.
+    fn apply_changes(&mut self, changed_paths: HashSet<PathBuf>) -> Result<()> {
+        let kinds: HashSet<ChangeKind> = changed_paths
+            .iter()
+            .map(|path| classify_change(path, &self.repo_dirs))
+            .collect();
+
+        if kinds.contains(&ChangeKind::Config) {
+            eprintln!("Configs changed; reloading...");
+            let (host, target) = (self.load_targets)()?;
+            self.host = host;
+            self.target = target;
+        }
+
+        if kinds.contains(&ChangeKind::Ebuild) || kinds.contains(&ChangeKind::Eclass) {
+            let invalidated = self.evaluator.invalidate(&changed_paths);
+            for data in [Some(&self.host), self.target.as_ref()]
+                .into_iter()
+                .flatten()
+            {
+                data.loader.invalidate(&invalidated);
+            }
+            eprintln!("Invalidated {} ebuilds", invalidated.len());
+        }
+
+        Ok(())
+    }
+
+    fn handle_request(
+        &self,
+        request: Request,
+        translator: &PathTranslator,
+        src_dir: &Path,
+    ) -> Result<()> {
+        match request {
+            Request::DumpPackage { args } => {
+                dump_package_main(&self.host, self.target.as_ref(), args)
+            }
+            Request::WhyDepends { args } => {
+                why_depends_main(&self.host, self.target.as_ref(), args)
+            }
+            Request::GenerateRepo {
+                output_dir,
+                output_repos_json,
+                backend,
+            } => generate_repo_main(
+                &self.host,
+                self.target.as_slice(),
+                translator,
+                backend.create().as_ref(),
+                src_dir,
+                &output_dir,
+                &output_repos_json,
+            ),
+        }
+    }
+}
+
+/// Runs `f` with stdout and stderr redirected to `fd`.
+///
+/// Requests are served one at a time, so redirecting the standard streams of
+/// the whole process is safe.
+fn with_redirected_output<T>(fd: RawFd, f: impl FnOnce() -> T) -> Result<T> {
+    let stdout_fd = std::io::stdout().as_raw_fd();
+    let stderr_fd = std::io::stderr().as_raw_fd();
+
+    std::io::stdout().flush()?;
+    std::io::stderr().flush()?;
+    let saved_stdout = dup(stdout_fd)?;
+    let saved_stderr = dup(stderr_fd)?;
+    dup2(fd, stdout_fd)?;
+    dup2(fd, stderr_fd)?;
+
+    let result = f();
+
+    std::io::stdout().flush()?;
+    std::io::stderr().flush()?;
+    dup2(saved_stdout, stdout_fd)?;
+    dup2(saved_stderr, stderr_fd)?;
+    close(saved_stdout)?;
+    close(saved_stderr)?;
+    Ok(result)
+}
+
+/// How long to wait for a client to send its request line.
+const REQUEST_READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn serve_connection(
+    state: &ServeState,
+    stream: UnixStream,
+    translator: &PathTranslator,
+    src_dir: &Path,
+) -> Result<()> {
+    // Requests are served one at a time, so don't let a stuck client block
+    // everyone else.
+    stream.set_read_timeout(Some(REQUEST_READ_TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let start_time = Instant::now();
+
+    let result = with_redirected_output(stream.as_raw_fd(), || -> Result<()> {
+        let request = Request::try_parse_from(line.split_whitespace())?;
+        state.handle_request(request, translator, src_dir)
+    })?;
+    if let Err(err) = &result {
+        writeln!(&stream, "ERROR: {err:#}")?;
+    }
+
+    eprintln!(
+        "Served `{}` in {:.1}s",
+        line.trim(),
+        start_time.elapsed().as_secs_f32()
+    );
+    Ok(())
+}
+
+/// Keeps packages and configs in memory and answers requests over a Unix
+/// domain socket, re-evaluating only ebuilds affected by file changes.
+pub fn serve_main(
+    host: TargetData,
+    target: Option<TargetData>,
+    evaluator: &CachedEBuildEvaluator,
+    load_targets: &dyn Fn() -> Result<(TargetData, Option<TargetData>)>,
+    translator: &PathTranslator,
+    src_dir: &Path,
+    args: Args,
+) -> Result<()> {
+    let repo_dirs = [Some(&host), target.as_ref()]
+        .into_iter()
+        .flatten()
+        .flat_map(|data| data.repos.get_repos())
+        .map(|repo| repo.base_dir().to_owned())
+        .unique()
+        .collect_vec();
+    let config_dirs = [Some(&host), target.as_ref()]
+        .into_iter()
+        .flatten()
+        .map(|data| data.sysroot.join("etc/portage"))
+        .filter(|dir| dir.is_dir())
+        .collect_vec();
+
+    let mut watcher = Watcher::new()?;
+    for dir in repo_dirs.iter().chain(&config_dirs) {
+        watcher.add_tree(dir)?;
+    }
+    let (sender, receiver): (Sender<Vec<PathBuf>>, Receiver<Vec<PathBuf>>) = channel();
+    let watcher_log = File::from(std::io::stderr().as_fd().try_clone_to_owned()?);
+    std::thread::spawn(move || watcher.run(sender, watcher_log));
+
+    let mut state = ServeState {
+        host,
+        target,
+        evaluator,
+        load_targets,
+        repo_dirs,
+    };
+
+    if args.socket.exists() {
+        std::fs::remove_file(&args.socket)
+            .with_context(|| format!("Failed to remove {}", args.socket.display()))?;
+    }
+    let listener = UnixListener::bind(&args.socket)
+        .with_context(|| format!("Failed to listen on {}", args.socket.display()))?;
+    eprintln!("Listening on {}", args.socket.display());
+
+    for stream in listener.incoming() {
+        let stream = stream?;
+
+        let changed_paths: HashSet<PathBuf> = receiver.try_iter().flatten().collect();
+        if !changed_paths.is_empty() {
+            if let Err(err) = state.apply_changes(changed_paths) {
+                writeln!(&stream, "ERROR: Failed to reload: {err:#}")?;
+                continue;
+            }
+        }
+
+        if let Err(err) = serve_connection(&state, stream, translator, src_dir) {
+            eprintln!("WARNING: Failed to serve a request: {err:#}");
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_change() {
+        let repo_dirs = vec![PathBuf::from("/overlays/a"), PathBuf::from("/overlays/b")];
+
+        for (path, expected) in [
+            ("/overlays/a/sys-apps/foo/foo-1.ebuild", ChangeKind::Ebuild),
+            ("/overlays/b/eclass/foo.eclass", ChangeKind::Eclass),
+            ("/overlays/a/profiles/base/package.use", ChangeKind::Config),
+            ("/overlays/b/metadata/layout.conf", ChangeKind::Config),
+            (
+                "/overlays/a/sys-apps/foo/files/foo.patch",
+                ChangeKind::Ignored,
+            ),
+            ("/overlays/a/sys-apps/foo/Manifest", ChangeKind::Ignored),
+            ("/build/board/etc/portage/make.conf", ChangeKind::Config),
+        ] {
+            assert_eq!(
+                classify_change(Path::new(path), &repo_dirs),
+                expected,
+                "{path}"
+            );
+        }
+    }
+}
@@ -0,0 +1,118 @@
+// Copyright 2024 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+use std::{collections::HashMap, time::Duration};
+
+use alchemist::ebuild::metadata::EBuildTiming;
+use itertools::Itertools;
+
+/// The accumulated time spent sourcing an eclass across all ebuilds.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct EclassTiming {
+    name: String,
+    count: usize,
+    total: Duration,
+}
+
+/// Accumulates the eclass timings of all ebuilds, slowest first.
+fn summarize_eclasses(timings: &[EBuildTiming]) -> Vec<EclassTiming> {
+    let mut eclasses: HashMap<&str, EclassTiming> = HashMap::new();
+    for (name, elapsed) in timings.iter().flat_map(|timing| &timing.eclasses) {
+        let eclass = eclasses.entry(name).or_insert_with(|| EclassTiming {
+            name: name.clone(),
+            count: 0,
+            total: Duration::ZERO,
+        });
+        eclass.count += 1;
+        eclass.total += *elapsed;
+    }
+    eclasses
+        .into_values()
+        .sorted_by(|a, b| b.total.cmp(&a.total).then_with(|| a.name.cmp(&b.name)))
+        .collect()
+}
+
+/// Prints the `count` slowest ebuilds and eclasses to stderr.
+///
+/// Eclass times include the time spent sourcing the eclasses they inherit,
+/// and are summed over all ebuilds inheriting them.
+pub fn print_slow_report(timings: &[EBuildTiming], count: usize) {
+    let total: Duration = timings.iter().map(|timing| timing.elapsed).sum();
+    eprintln!(
+        "Evaluated {} ebuilds in {:.3}s of total bash time",
+        timings.len(),
+        total.as_secs_f64()
+    );
+
+    eprintln!("Slowest ebuilds:");
+    for timing in timings
+        .iter()
+        .sorted_by(|a, b| b.elapsed.cmp(&a.elapsed))
+        .take(count)
+    {
+        eprintln!(
+            "  {:>10.3}ms  {}",
+            timing.elapsed.as_secs_f64() * 1000.0,
+            timing.ebuild_path.display()
+        );
+    }
+
+    eprintln!("Slowest eclasses:");
+    for eclass in summarize_eclasses(timings).into_iter().take(count) {
+        eprintln!(
+            "  {:>10.3}ms  {} ({} times, {:.3}ms on average)",
+            eclass.total.as_secs_f64() * 1000.0,
+            eclass.name,
+            eclass.count,
+            eclass.total.as_secs_f64() * 1000.0 / eclass.count as f64
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn new_timing(path: &str, eclasses: &[(&str, u64)]) -> EBuildTiming {
+        EBuildTiming {
+            ebuild_path: PathBuf::from(path),
+            elapsed: Duration::from_millis(100),
+            eclasses: eclasses
+                .iter()
+                .map(|(name, millis)| (name.to_string(), Duration::from_millis(*millis)))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_summarize_eclasses() {
+        let timings = [
+            new_timing(
+                "a/a/a-1.ebuild",
+                &[("flag-o-matic", 5), ("cros-workon", 20)],
+            ),
+            new_timing("b/b/b-1.ebuild", &[("flag-o-matic", 30)]),
+            new_timing("c/c/c-1.ebuild", &[("cros-workon", 15)]),
+            new_timing("d/d/d-1.ebuild", &[]),
+        ];
+
+        assert_eq!(
+            summarize_eclasses(&timings),
+            vec![
+                EclassTiming {
+                    name: "cros-workon".to_owned(),
+                    count: 2,
+                    total: Duration::from_millis(35),
+                },
+                EclassTiming {
+                    name: "flag-o-matic".to_owned(),
+                    count: 2,
+                    total: Duration::from_millis(35),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,117 @@
+// Copyright 2024 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+use std::path::Path;
+
+use alchemist::{
+    analyze::dependency::subslot::{analyze_subslot_impact, RebuildReason, SubslotRebuild, World},
+    data::Slot,
+    dependency::package::PackageAtom,
+    ebuild::PackageDetails,
+};
+use anyhow::{bail, Context, Result};
+use itertools::Itertools;
+
+use crate::{alchemist::TargetData, generate_repo::load_packages};
+
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    /// The SLOT of the package before the change, e.g. `0/1.2`. Defaults to
+    /// the SLOT of the best version of the package.
+    #[arg(long)]
+    old_slot: Option<String>,
+
+    /// The SLOT of the package after the change, e.g. `0/1.3`.
+    #[arg(long)]
+    new_slot: String,
+
+    /// Package whose SLOT changes.
+    package: String,
+}
+
+fn world_name(world: World) -> &'static str {
+    match world {
+        World::Host => "host",
+        World::Target => "target",
+    }
+}
+
+fn format_package(details: &PackageDetails) -> String {
+    format!(
+        "{}-{}",
+        details.as_basic_data().package_name,
+        details.as_basic_data().version
+    )
+}
+
+fn print_rebuild(rebuild: &SubslotRebuild) {
+    let reason = match &rebuild.reason {
+        RebuildReason::SlotOperator { kinds } => format!(
+            "slot operator dependency in {}",
+            kinds.iter().map(|kind| kind.var_name()).join(", ")
+        ),
+        RebuildReason::BuildInput { world, package } => format!(
+            "builds with {} ({})",
+            format_package(package),
+            world_name(*world)
+        ),
+    };
+    println!(
+        "{}\t{}\t{}",
+        world_name(rebuild.world),
+        format_package(&rebuild.package),
+        reason
+    );
+}
+
+/// Lists packages that need to be rebuilt when the SLOT of a package changes.
+pub fn subslot_impact_main(
+    host: &TargetData,
+    target: Option<&TargetData>,
+    src_dir: &Path,
+    args: Args,
+) -> Result<()> {
+    let atom = args.package.parse::<PackageAtom>()?;
+
+    let old_slot = match args.old_slot {
+        Some(old_slot) => old_slot,
+        None => {
+            let resolver = &target.unwrap_or(host).resolver;
+            let package = resolver
+                .find_best_package(&atom)?
+                .with_context(|| format!("No package matches {atom}"))?;
+            package.slot.to_string()
+        }
+    };
+    let old_slot = Slot::<&str>::new(&old_slot);
+    let new_slot = Slot::<&str>::new(&args.new_slot);
+    if old_slot == new_slot {
+        bail!("The old and new SLOT are identical: {old_slot}");
+    }
+
+    let host_packages = load_packages(host, host, src_dir)?;
+    let target_packages = target
+        .map(|target| load_packages(host, target, src_dir))
+        .transpose()?;
+
+    let rebuilds = analyze_subslot_impact(
+        &host_packages,
+        target_packages.as_deref(),
+        atom.package_name(),
+        &old_slot,
+    )?;
+
+    for rebuild in &rebuilds {
+        print_rebuild(rebuild);
+    }
+    eprintln!(
+        "{} packages are rebuilt when {} changes SLOT from {} to {}",
+        rebuilds.len(),
+        atom.package_name(),
+        old_slot,
+        new_slot
+    );
+
+    Ok(())
+}
@@ -0,0 +1,53 @@
+// Copyright 2024 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+use std::process::exit;
+
+use anyhow::{Context, Result};
+use clap::Parser;
+
+#[derive(Parser, Debug, PartialEq, Eq)]
+#[command(name = "ver_cut")]
+#[command(author = "ChromiumOS Authors")]
+#[command(about = "Prints a substring of a version", long_about = None)]
+pub struct Args {
+    range: String,
+
+    /// Defaults to $PV.
+    version: Option<String>,
+}
+
+fn cut(args: Args) -> Result<String> {
+    let version = match args.version {
+        Some(version) => version,
+        None => std::env::var("PV").context("PV environment variable is not set")?,
+    };
+    version::ver_cut(&args.range, &version)
+}
+
+pub fn ver_cut_main(args: Args) -> Result<()> {
+    match cut(args) {
+        Ok(result) => {
+            println!("{}", result);
+            exit(0)
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cut() -> Result<()> {
+        let args = Args::try_parse_from(vec!["ver_cut", "1-2", "1.2.3"])?;
+        assert_eq!(cut(args)?, "1.2");
+
+        Ok(())
+    }
+}
@@ -2,12 +2,11 @@
 // Use of this source code is governed by a BSD-style license that can be
 // found in the LICENSE file.
 
-use std::{cmp::Ordering, process::exit};
+use std::process::exit;
 
-use anyhow::{anyhow, bail, Result};
+use anyhow::{anyhow, Result};
 use clap::{arg, command, Parser};
 use itertools::Itertools;
-use version::Version;
 
 #[derive(Parser, Debug, PartialEq, Eq)]
 #[command(name = "ver_test")]
@@ -31,21 +30,7 @@ fn compare(args: Args) -> Result<bool> {
         .collect_tuple()
         .ok_or_else(|| anyhow!("Needs 2 or 3 arguments"))?;
 
-    let lhs = Version::try_new(&lhs)?;
-    let rhs = Version::try_new(&rhs)?;
-    let ord = lhs.cmp(&rhs);
-
-    let ok = match op.as_str() {
-        "-eq" => ord == Ordering::Equal,
-        "-ne" => ord != Ordering::Equal,
-        "-gt" => ord == Ordering::Greater,
-        "-ge" => ord != Ordering::Less,
-        "-lt" => ord == Ordering::Less,
-        "-le" => ord != Ordering::Greater,
-        _ => bail!("Unsupported operator: {}", &op),
-    };
-
-    Ok(ok)
+    version::ver_test(&lhs, &op, &rhs)
 }
 
 pub fn ver_test_main(args: Args) -> Result<()> {
@@ -0,0 +1,469 @@
+// Copyright 2024 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    fs::File,
+    io::{ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use alchemist::{
+    analyze::{
+        restrict::analyze_restricts, source::PackageDistSource, MaybePackage, Package,
+        PackageAnalysisError,
+    },
+    dependency::{package::PackageAtom, restrict::RestrictAtom},
+    ebuild::{MaybePackageDetails, PackageReadiness},
+    resolver::PackageResolver,
+};
+use anyhow::{bail, Context, Result};
+use blake2::Blake2b512;
+use itertools::{Either, Itertools};
+use rayon::prelude::*;
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::{alchemist::TargetData, generate_repo::load_packages};
+
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    /// Directory containing the downloaded distfiles.
+    #[arg(long, value_name = "DIR")]
+    distdir: PathBuf,
+
+    /// Writes the distfiles that are missing or mismatched to the file, one
+    /// per line as the file name followed by its URLs.
+    #[arg(long, value_name = "PATH")]
+    fetch_list: Option<PathBuf>,
+
+    /// Packages to check the distfiles of, together with the packages needed
+    /// to build and install them. Defaults to virtual/target-os,
+    /// virtual/target-os-dev and virtual/target-os-test for boards, and to
+    /// virtual/target-sdk for the host.
+    packages: Vec<String>,
+}
+
+/// Packages whose closure is checked by default for boards.
+const DEFAULT_BOARD_ROOTS: &[&str] = &[
+    "virtual/target-os",
+    "virtual/target-os-dev",
+    "virtual/target-os-test",
+];
+
+/// Packages whose closure is checked by default for the host.
+const DEFAULT_HOST_ROOTS: &[&str] = &["virtual/target-sdk"];
+
+/// The result of checking a distfile in the distdir.
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum DistFileStatus {
+    Ok,
+    Missing,
+    SizeMismatch { actual: u64 },
+    HashMismatch { name: &'static str, actual: String },
+}
+
+/// Computes the hashes of a file with the algorithms supported by Manifest
+/// files.
+fn compute_hashes(path: &Path) -> Result<HashMap<&'static str, String>> {
+    let mut file = File::open(path).with_context(|| format!("open {}", path.display()))?;
+    let mut sha256 = Sha256::new();
+    let mut sha512 = Sha512::new();
+    let mut blake2b = Blake2b512::new();
+
+    let mut buffer = vec![0; 1 << 20];
+    loop {
+        let size = file
+            .read(&mut buffer)
+            .with_context(|| format!("read {}", path.display()))?;
+        if size == 0 {
+            break;
+        }
+        sha256.update(&buffer[..size]);
+        sha512.update(&buffer[..size]);
+        blake2b.update(&buffer[..size]);
+    }
+
+    Ok(HashMap::from([
+        ("SHA256", format!("{:x}", sha256.finalize())),
+        ("SHA512", format!("{:x}", sha512.finalize())),
+        ("BLAKE2B", format!("{:x}", blake2b.finalize())),
+    ]))
+}
+
+/// Checks that the distfile exists in `distdir` with the size and hashes
+/// recorded in the Manifest.
+fn verify_dist_file(distdir: &Path, source: &PackageDistSource) -> Result<DistFileStatus> {
+    let path = distdir.join(&source.filename);
+    let metadata = match std::fs::metadata(&path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(DistFileStatus::Missing),
+        Err(err) => return Err(err).with_context(|| format!("stat {}", path.display())),
+    };
+
+    if metadata.len() != source.size {
+        return Ok(DistFileStatus::SizeMismatch {
+            actual: metadata.len(),
+        });
+    }
+
+    let actual_hashes = compute_hashes(&path)?;
+    for name in ["SHA512", "BLAKE2B", "SHA256"] {
+        let (Some(expected), Some(actual)) = (source.hashes.get(name), actual_hashes.get(name))
+        else {
+            continue;
+        };
+        if !expected.eq_ignore_ascii_case(actual) {
+            return Ok(DistFileStatus::HashMismatch {
+                name,
+                actual: actual.clone(),
+            });
+        }
+    }
+
+    Ok(DistFileStatus::Ok)
+}
+
+/// Returns the label reported for a package that failed to analyze.
+///
+/// Fetch-restricted packages fail source analysis on purpose, so they are
+/// reported separately from other failures.
+fn failure_label(error: &PackageAnalysisError) -> &'static str {
+    match &error.details {
+        MaybePackageDetails::Ok(details)
+            if analyze_restricts(details)
+                .is_ok_and(|restricts| restricts.contains(&RestrictAtom::Fetch)) =>
+        {
+            "FETCH-RESTRICTED"
+        }
+        _ => "FAILED",
+    }
+}
+
+/// Identifies a package in the host or the target world.
+type PackageKey<'a> = (bool, &'a Path);
+
+/// Returns the packages needed to build and install `roots`.
+///
+/// The closure contains the packages themselves, their DEPENDs, the host
+/// packages installed to build them, and their transitive runtime
+/// dependencies. Packages that failed to analyze are included, but their
+/// dependencies are unknown and thus not followed.
+fn compute_closure<'a>(
+    host_packages: &'a [MaybePackage],
+    target_packages: Option<&'a [MaybePackage]>,
+    roots: &[&'a MaybePackage],
+) -> Vec<&'a MaybePackage> {
+    let start_is_host = target_packages.is_none();
+    let index: HashMap<PackageKey, &MaybePackage> = host_packages
+        .iter()
+        .map(|package| (true, package))
+        .chain(
+            target_packages
+                .into_iter()
+                .flatten()
+                .map(|package| (false, package)),
+        )
+        .map(|(is_host, package)| {
+            (
+                (is_host, package.as_basic_data().ebuild_path.as_path()),
+                package,
+            )
+        })
+        .collect();
+
+    let mut visited: HashSet<PackageKey> = HashSet::new();
+    let mut queue: VecDeque<PackageKey> = VecDeque::new();
+    for root in roots {
+        let key = (start_is_host, root.as_basic_data().ebuild_path.as_path());
+        if visited.insert(key) {
+            queue.push_back(key);
+        }
+    }
+
+    let mut closure = Vec::new();
+    while let Some(key @ (is_host, _)) = queue.pop_front() {
+        let Some(package) = index.get(&key) else {
+            continue;
+        };
+        closure.push(*package);
+        let MaybePackage::Ok(package) = package else {
+            continue;
+        };
+
+        let deps = &package.dependencies;
+        let neighbors = deps
+            .direct
+            .build_target
+            .iter()
+            .chain(&deps.indirect.install_set)
+            .map(|details| (is_host, details))
+            // BDEPEND and IDEPEND are always resolved in the host world.
+            .chain(
+                deps.indirect
+                    .build_host_set
+                    .iter()
+                    .map(|details| (true, details)),
+            );
+        for (is_host, details) in neighbors {
+            let key = (is_host, details.as_basic_data().ebuild_path.as_path());
+            if visited.insert(key) {
+                queue.push_back(key);
+            }
+        }
+    }
+    closure
+}
+
+fn find_roots<'a>(
+    resolver: &PackageResolver,
+    packages: &'a [MaybePackage],
+    atoms: &[String],
+) -> Result<Vec<&'a MaybePackage>> {
+    let by_path: HashMap<&Path, &MaybePackage> = packages
+        .iter()
+        .map(|package| (package.as_basic_data().ebuild_path.as_path(), package))
+        .collect();
+
+    atoms
+        .iter()
+        .map(|raw| {
+            let atom = raw.parse::<PackageAtom>()?;
+            let details = resolver
+                .find_best_package(&atom)?
+                .with_context(|| format!("No package matches {atom}"))?;
+            match by_path.get(details.as_basic_data().ebuild_path.as_path()) {
+                Some(package) => Ok(*package),
+                None => bail!("{atom} was not analyzed"),
+            }
+        })
+        .collect()
+}
+
+/// Returns the default root packages that exist for `world`.
+fn default_roots(world: &TargetData, is_board: bool) -> Result<Vec<String>> {
+    let atoms = if is_board {
+        DEFAULT_BOARD_ROOTS
+    } else {
+        DEFAULT_HOST_ROOTS
+    };
+    let mut roots = Vec::new();
+    for raw in atoms {
+        let atom = raw.parse::<PackageAtom>()?;
+        if world.resolver.find_best_package(&atom)?.is_some() {
+            roots.push(raw.to_string());
+        } else {
+            eprintln!("WARNING: No package matches {atom} for {}", world.board);
+        }
+    }
+    Ok(roots)
+}
+
+fn write_fetch_list(path: &Path, sources: &[&PackageDistSource]) -> Result<()> {
+    let mut file = File::create(path).with_context(|| format!("create {}", path.display()))?;
+    for source in sources {
+        writeln!(
+            file,
+            "{}\t{}",
+            source.filename,
+            source.urls.iter().map(|url| url.as_str()).join(" ")
+        )?;
+    }
+    Ok(())
+}
+
+/// The entry point of "verify-distfiles" subcommand.
+pub fn verify_distfiles_main(
+    host: &TargetData,
+    target: Option<&TargetData>,
+    src_dir: &Path,
+    args: Args,
+) -> Result<()> {
+    let host_packages = load_packages(host, host, src_dir)?;
+    let target_packages = target
+        .map(|target| load_packages(host, target, src_dir))
+        .transpose()?;
+
+    let (world, world_packages) = match (target, &target_packages) {
+        (Some(target), Some(target_packages)) => (target, target_packages),
+        _ => (host, &host_packages),
+    };
+    let atoms = if args.packages.is_empty() {
+        default_roots(world, target.is_some())?
+    } else {
+        args.packages.clone()
+    };
+    let roots = find_roots(&world.resolver, world_packages, &atoms)?;
+    let packages = compute_closure(&host_packages, target_packages.as_deref(), &roots);
+
+    let (packages, errors): (Vec<&Package>, Vec<&PackageAnalysisError>) =
+        packages.into_iter().partition_map(|package| match package {
+            MaybePackage::Ok(package) => Either::Left(package.as_ref()),
+            MaybePackage::Err(error) => Either::Right(error.as_ref()),
+        });
+
+    // The distfiles of packages that failed to analyze are unknown, so the
+    // distdir can't be verified for them. Masked packages are never built, so
+    // their distfiles are not needed.
+    let errors: BTreeMap<&Path, &PackageAnalysisError> = errors
+        .into_iter()
+        .filter(|error| {
+            !matches!(
+                &error.details,
+                MaybePackageDetails::Ok(details)
+                    if matches!(details.readiness, PackageReadiness::Masked { .. })
+            )
+        })
+        .map(|error| (error.as_basic_data().ebuild_path.as_path(), error))
+        .collect();
+    for error in errors.values() {
+        println!(
+            "{}\t{}-{}\t{}",
+            failure_label(error),
+            error.as_basic_data().package_name,
+            error.as_basic_data().version,
+            error.error.replace('\n', "\n\t")
+        );
+    }
+
+    let sources: BTreeMap<&str, &PackageDistSource> = packages
+        .iter()
+        .flat_map(|package| &package.sources.dist_sources)
+        .map(|source| (source.filename.as_str(), source))
+        .collect();
+
+    let results = sources
+        .par_iter()
+        .map(|(_, source)| Ok((*source, verify_dist_file(&args.distdir, source)?)))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut failed = Vec::new();
+    for (source, status) in &results {
+        match status {
+            DistFileStatus::Ok => continue,
+            DistFileStatus::Missing => println!("MISSING\t{}", source.filename),
+            DistFileStatus::SizeMismatch { actual } => println!(
+                "MISMATCH\t{}\tsize is {} bytes, expected {}",
+                source.filename, actual, source.size
+            ),
+            DistFileStatus::HashMismatch { name, actual } => println!(
+                "MISMATCH\t{}\t{} is {}, expected {}",
+                source.filename, name, actual, source.hashes[*name]
+            ),
+        }
+        failed.push(*source);
+    }
+
+    if let Some(fetch_list) = &args.fetch_list {
+        write_fetch_list(fetch_list, &failed)?;
+    }
+
+    let missing = results
+        .iter()
+        .filter(|(_, status)| *status == DistFileStatus::Missing)
+        .count();
+    eprintln!(
+        "Checked {} distfiles: {} missing, {} mismatched",
+        results.len(),
+        missing,
+        failed.len() - missing
+    );
+
+    if !failed.is_empty() {
+        bail!(
+            "{} is incomplete; {} distfiles need to be fetched",
+            args.distdir.display(),
+            failed.len()
+        );
+    }
+    if !errors.is_empty() {
+        bail!(
+            "{} packages failed to analyze; their distfiles were not checked",
+            errors.len()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use url::Url;
+
+    fn new_source(filename: &str, content: &[u8]) -> PackageDistSource {
+        PackageDistSource {
+            urls: vec![Url::parse(&format!("https://example/{filename}")).unwrap()],
+            filename: filename.to_owned(),
+            size: content.len() as u64,
+            hashes: HashMap::from([
+                (
+                    "SHA512".to_owned(),
+                    format!("{:x}", Sha512::digest(content)),
+                ),
+                (
+                    "BLAKE2B".to_owned(),
+                    format!("{:x}", Blake2b512::digest(content)),
+                ),
+            ]),
+        }
+    }
+
+    #[test]
+    fn test_verify_dist_file() -> Result<()> {
+        let distdir = tempfile::tempdir()?;
+        let distdir = distdir.path();
+        std::fs::write(distdir.join("good.tar.gz"), b"good")?;
+        std::fs::write(distdir.join("short.tar.gz"), b"bad")?;
+        std::fs::write(distdir.join("corrupt.tar.gz"), b"baad")?;
+
+        assert_eq!(
+            verify_dist_file(distdir, &new_source("good.tar.gz", b"good"))?,
+            DistFileStatus::Ok
+        );
+        assert_eq!(
+            verify_dist_file(distdir, &new_source("missing.tar.gz", b"good"))?,
+            DistFileStatus::Missing
+        );
+        assert_eq!(
+            verify_dist_file(distdir, &new_source("short.tar.gz", b"good"))?,
+            DistFileStatus::SizeMismatch { actual: 3 }
+        );
+        assert_eq!(
+            verify_dist_file(distdir, &new_source("corrupt.tar.gz", b"good"))?,
+            DistFileStatus::HashMismatch {
+                name: "SHA512",
+                actual: format!("{:x}", Sha512::digest(b"baad")),
+            }
+        );
+
+        // A BLAKE2B mismatch is detected even when SHA512 is not recorded.
+        let mut source = new_source("corrupt.tar.gz", b"good");
+        source.hashes.remove("SHA512");
+        assert_eq!(
+            verify_dist_file(distdir, &source)?,
+            DistFileStatus::HashMismatch {
+                name: "BLAKE2B",
+                actual: format!("{:x}", Blake2b512::digest(b"baad")),
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_fetch_list() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("fetch.list");
+        let a = new_source("a.tar.gz", b"a");
+        let b = new_source("b.tar.gz", b"b");
+
+        write_fetch_list(&path, &[&a, &b])?;
+
+        assert_eq!(
+            std::fs::read_to_string(&path)?,
+            "a.tar.gz\thttps://example/a.tar.gz\nb.tar.gz\thttps://example/b.tar.gz\n"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,303 @@
+// Copyright 2024 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use alchemist::{
+    analyze::source::{extract_repo_sources, PackageRepoSource},
+    dependency::package::PackageAtom,
+    ebuild::{MaybePackageDetails, PackageDetails},
+};
+use anyhow::{bail, Context, Result};
+use itertools::Itertools;
+use rayon::prelude::*;
+
+use crate::alchemist::TargetData;
+
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    /// The git ref of the local checkouts to compare the pinned trees with.
+    #[arg(long = "ref", value_name = "REF", default_value = "HEAD")]
+    git_ref: String,
+
+    /// Also fails if any pinned tree can't be checked, e.g. because its
+    /// project is not synced.
+    #[arg(long)]
+    fail_on_unavailable: bool,
+
+    /// Packages to check. Defaults to all packages.
+    packages: Vec<String>,
+}
+
+/// The result of comparing a pinned tree with the local checkout.
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum TreeStatus {
+    Ok,
+    Stale { actual: String },
+    Unavailable { reason: String },
+}
+
+/// Identifies a path in a local checkout: the project path and the subtree.
+type TreeKey = (PathBuf, Option<PathBuf>);
+
+/// Computes the hash of the git object at `subtree` of the project checked
+/// out at `project_dir` as of `git_ref`.
+///
+/// Returns the reason as the inner error if the project or the path doesn't
+/// exist, e.g. because the project is not synced. Fails if `git_ref` doesn't
+/// name a commit in the project, so that a mistyped ref isn't reported as
+/// unavailable trees.
+fn compute_tree_hash(
+    project_dir: &Path,
+    subtree: Option<&Path>,
+    git_ref: &str,
+) -> Result<Result<String, String>> {
+    if !project_dir.is_dir() {
+        return Ok(Err(format!("{} does not exist", project_dir.display())));
+    }
+
+    let status = Command::new("git")
+        .arg("-C")
+        .arg(project_dir)
+        .args(["rev-parse", "--verify", "--quiet"])
+        .arg(format!("{git_ref}^{{commit}}"))
+        .stdout(Stdio::null())
+        .status()
+        .context("Failed to run git")?;
+    if !status.success() {
+        bail!("{} is not a commit in {}", git_ref, project_dir.display());
+    }
+
+    // Use a path relative to the current directory as the project may not be
+    // at the top of the git repository.
+    let path = match subtree {
+        Some(subtree) => format!("./{}", subtree.display()),
+        None => "./".to_owned(),
+    };
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(project_dir)
+        .args(["rev-parse", "--verify", "--quiet"])
+        .arg(format!("{git_ref}:{path}"))
+        .output()
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        return Ok(Err(format!(
+            "{}:{} does not exist in {}",
+            git_ref,
+            path,
+            project_dir.display()
+        )));
+    }
+
+    Ok(Ok(String::from_utf8(output.stdout)?.trim().to_owned()))
+}
+
+fn verify_repo_source(actual: &Result<String, String>, source: &PackageRepoSource) -> TreeStatus {
+    match actual {
+        Ok(actual) if actual.eq_ignore_ascii_case(&source.tree_hash) => TreeStatus::Ok,
+        Ok(actual) => TreeStatus::Stale {
+            actual: actual.clone(),
+        },
+        Err(reason) => TreeStatus::Unavailable {
+            reason: reason.clone(),
+        },
+    }
+}
+
+fn format_package(details: &PackageDetails) -> String {
+    format!(
+        "{}-{}",
+        details.as_basic_data().package_name,
+        details.as_basic_data().version
+    )
+}
+
+/// The entry point of "verify-workon-trees" subcommand.
+///
+/// Compares the trees pinned by CROS_WORKON_TREE of stable cros-workon
+/// ebuilds with the trees in the local git checkouts, and fails if any pin is
+/// stale, or unavailable with --fail-on-unavailable.
+pub fn verify_workon_trees_main(
+    host: &TargetData,
+    target: Option<&TargetData>,
+    src_dir: &Path,
+    args: Args,
+) -> Result<()> {
+    let resolver = &target.unwrap_or(host).resolver;
+
+    let packages: Vec<MaybePackageDetails> = if args.packages.is_empty() {
+        resolver.find_all_packages()?
+    } else {
+        let mut packages = Vec::new();
+        for raw in &args.packages {
+            let atom = raw.parse::<PackageAtom>()?;
+            packages.extend(resolver.find_packages(&atom)?);
+        }
+        packages
+    };
+
+    let mut pins: Vec<(&PackageDetails, PackageRepoSource)> = Vec::new();
+    for package in &packages {
+        let MaybePackageDetails::Ok(details) = package else {
+            continue;
+        };
+        let repo_sources = extract_repo_sources(details, src_dir)
+            .with_context(|| format!("Failed to analyze {}", format_package(details)))?;
+        pins.extend(
+            repo_sources
+                .into_iter()
+                .map(|source| (details.as_ref(), source)),
+        );
+    }
+
+    // Many ebuilds pin the same trees, so compute each tree only once.
+    let keys: BTreeSet<TreeKey> = pins
+        .iter()
+        .map(|(_, source)| (source.project_path.clone(), source.subtree.clone()))
+        .collect();
+    let actual_hashes: BTreeMap<TreeKey, Result<String, String>> = keys
+        .into_par_iter()
+        .map(|(project_path, subtree)| {
+            let actual = compute_tree_hash(
+                &src_dir.join(&project_path),
+                subtree.as_deref(),
+                &args.git_ref,
+            )?;
+            Ok(((project_path, subtree), actual))
+        })
+        .collect::<Result<_>>()?;
+
+    let mut stale = 0;
+    let mut unavailable = 0;
+    for (details, source) in pins
+        .iter()
+        .sorted_by_key(|(details, _)| format_package(details))
+    {
+        let actual = &actual_hashes[&(source.project_path.clone(), source.subtree.clone())];
+        match verify_repo_source(actual, source) {
+            TreeStatus::Ok => {}
+            TreeStatus::Stale { actual } => {
+                println!(
+                    "STALE\t{}\t{}\tpinned {}, actual {}",
+                    format_package(details),
+                    source.full_path().display(),
+                    source.tree_hash,
+                    actual
+                );
+                stale += 1;
+            }
+            TreeStatus::Unavailable { reason } => {
+                println!(
+                    "UNAVAILABLE\t{}\t{}\t{}",
+                    format_package(details),
+                    source.full_path().display(),
+                    reason
+                );
+                unavailable += 1;
+            }
+        }
+    }
+
+    eprintln!(
+        "Checked {} pinned trees at {}: {} stale, {} unavailable",
+        pins.len(),
+        args.git_ref,
+        stale,
+        unavailable
+    );
+
+    if stale > 0 {
+        bail!(
+            "{} CROS_WORKON_TREE pins don't match the local checkouts at {}",
+            stale,
+            args.git_ref
+        );
+    }
+    if args.fail_on_unavailable && unavailable > 0 {
+        bail!(
+            "{} CROS_WORKON_TREE pins could not be checked at {}",
+            unavailable,
+            args.git_ref
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn git(dir: &Path, args: &[&str]) -> Result<String> {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .output()?;
+        assert!(output.status.success(), "git {:?} failed", args);
+        Ok(String::from_utf8(output.stdout)?.trim().to_owned())
+    }
+
+    fn new_source(tree_hash: &str, subtree: Option<&str>) -> PackageRepoSource {
+        PackageRepoSource {
+            name: format!("tree-project-{tree_hash}"),
+            project: "project".to_owned(),
+            tree_hash: tree_hash.to_owned(),
+            project_path: PathBuf::from("project"),
+            subtree: subtree.map(PathBuf::from),
+        }
+    }
+
+    #[test]
+    fn test_compute_tree_hash() -> Result<()> {
+        let src_dir = tempfile::tempdir()?;
+        let project_dir = src_dir.path().join("project");
+        std::fs::create_dir_all(project_dir.join("sub"))?;
+        std::fs::write(project_dir.join("sub/file"), "old")?;
+        git(&project_dir, &["init", "--quiet"])?;
+        git(&project_dir, &["add", "."])?;
+        git(&project_dir, &["commit", "--quiet", "-m", "old"])?;
+
+        let old_root = git(&project_dir, &["rev-parse", "HEAD^{tree}"])?;
+        let old_sub = git(&project_dir, &["rev-parse", "HEAD:sub"])?;
+
+        std::fs::write(project_dir.join("sub/file"), "new")?;
+        git(&project_dir, &["commit", "--quiet", "-a", "-m", "new"])?;
+
+        let actual = compute_tree_hash(&project_dir, None, "HEAD")?;
+        assert_eq!(
+            verify_repo_source(&actual, &new_source(&old_root, None)),
+            TreeStatus::Stale {
+                actual: git(&project_dir, &["rev-parse", "HEAD^{tree}"])?
+            }
+        );
+
+        let actual = compute_tree_hash(&project_dir, Some(Path::new("sub")), "HEAD~1")?;
+        assert_eq!(
+            verify_repo_source(&actual, &new_source(&old_sub, Some("sub"))),
+            TreeStatus::Ok
+        );
+
+        let actual = compute_tree_hash(&project_dir, Some(Path::new("missing")), "HEAD")?;
+        assert!(matches!(
+            verify_repo_source(&actual, &new_source(&old_sub, Some("missing"))),
+            TreeStatus::Unavailable { .. }
+        ));
+
+        assert!(compute_tree_hash(&project_dir, None, "no-such-ref").is_err());
+
+        let actual = compute_tree_hash(&src_dir.path().join("unsynced"), None, "HEAD")?;
+        assert!(matches!(
+            verify_repo_source(&actual, &new_source(&old_root, None)),
+            TreeStatus::Unavailable { .. }
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,605 @@
+// Copyright 2024 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{copy, create_dir_all, read_dir},
+    os::unix::fs::symlink,
+    path::{Path, PathBuf},
+};
+
+use alchemist::{
+    analyze::{dependency::direct::DependencyKind, MaybePackage, Package},
+    config::{UseUpdate, UseUpdateFilter, UseUpdateKind},
+    dependency::{
+        package::{PackageAtom, PackageDependency},
+        Dependency,
+    },
+    ebuild::{metadata::EBuildPathInfo, PackageDetails},
+    repository::{Repository, RepositorySet, RepositorySetOperations},
+    resolver::select_best_version,
+};
+use anyhow::{bail, Context, Result};
+use itertools::Itertools;
+use version::Version;
+
+use crate::{alchemist::TargetData, generate_repo::load_packages};
+
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    /// Pretends that a package has a new version, e.g. `sys-libs/foo=1.2.3`.
+    ///
+    /// A synthetic ebuild is created from the newest non-9999 ebuild of the
+    /// package in a temporary overlay. The original overlays are not modified.
+    /// Can be specified multiple times.
+    #[arg(long, value_name = "PACKAGE=VERSION")]
+    pretend_version: Vec<String>,
+
+    /// Pretends that USE flags are changed for packages matching an atom, e.g.
+    /// `sys-libs/foo=bar,-baz`.
+    ///
+    /// The flags are applied after all other configs, just like a
+    /// `package.use` entry in the most specific profile. Can be specified
+    /// multiple times.
+    #[arg(long, value_name = "ATOM=FLAGS")]
+    pretend_use: Vec<String>,
+}
+
+/// A synthetic version of a package requested by `--pretend-version`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PretendVersion {
+    pub package_name: String,
+    pub version: Version,
+}
+
+/// Overrides to apply to the "what-if" world.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PretendOverrides {
+    pub versions: Vec<PretendVersion>,
+    pub use_updates: Vec<UseUpdate>,
+}
+
+fn parse_pretend_version(raw: &str) -> Result<PretendVersion> {
+    let (package_name, version) = raw
+        .rsplit_once('=')
+        .with_context(|| format!("--pretend-version must be PACKAGE=VERSION: {raw}"))?;
+    let atom: PackageAtom = package_name.parse()?;
+    if atom.package_name() != package_name {
+        bail!("--pretend-version takes a package name without operators: {package_name}");
+    }
+    Ok(PretendVersion {
+        package_name: package_name.to_owned(),
+        version: Version::try_new(version)?,
+    })
+}
+
+fn parse_pretend_use(raw: &str) -> Result<UseUpdate> {
+    let (atom, flags) = raw
+        .rsplit_once('=')
+        .with_context(|| format!("--pretend-use must be ATOM=FLAGS: {raw}"))?;
+    Ok(UseUpdate {
+        kind: UseUpdateKind::Set,
+        filter: UseUpdateFilter {
+            atom: Some(atom.parse()?),
+            stable_only: false,
+        },
+        use_tokens: flags.split(',').join(" "),
+    })
+}
+
+impl Args {
+    /// Parses the command line flags into [`PretendOverrides`].
+    pub fn parse_overrides(&self) -> Result<PretendOverrides> {
+        let overrides = PretendOverrides {
+            versions: self
+                .pretend_version
+                .iter()
+                .map(|raw| parse_pretend_version(raw))
+                .collect::<Result<_>>()?,
+            use_updates: self
+                .pretend_use
+                .iter()
+                .map(|raw| parse_pretend_use(raw))
+                .collect::<Result<_>>()?,
+        };
+        if overrides == PretendOverrides::default() {
+            bail!("Specify at least one of --pretend-version or --pretend-use");
+        }
+        Ok(overrides)
+    }
+}
+
+/// Returns the ebuild that a synthetic version of `package_name` is copied
+/// from, i.e. the newest non-9999 ebuild, or the 9999 ebuild if there is no
+/// other one.
+fn find_template_ebuild(
+    repos: &RepositorySet,
+    package_name: &str,
+) -> Result<Option<(PathBuf, EBuildPathInfo)>> {
+    let version_9999 = Version::try_new("9999")?;
+    let candidates = repos
+        .find_ebuilds(package_name)?
+        .into_iter()
+        .map(|path| {
+            let info = EBuildPathInfo::try_from(path.as_path())?;
+            Ok((path, info))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    // max_by_key returns the last element on ties, which comes from the
+    // highest-priority repository.
+    Ok(candidates
+        .into_iter()
+        .max_by_key(|(_, info)| (info.version != version_9999, info.version.clone())))
+}
+
+/// Creates a copy of `repos` where the synthetic ebuilds requested by
+/// `versions` are visible.
+///
+/// Synthetic ebuilds are written to overlays under `pretend_dir` that are
+/// layered on top of the repository owning the template ebuild. All other
+/// files in the package directory (Manifest, files/, metadata) are symlinked so
+/// that the synthetic ebuild evaluates as if it lived next to the original one.
+///
+/// The overlay layout only depends on the original repositories, so the host
+/// and the board can share the same `pretend_dir`.
+pub fn create_pretend_repos(
+    repos: &RepositorySet,
+    versions: &[PretendVersion],
+    pretend_dir: &Path,
+) -> Result<RepositorySet> {
+    let mut layered: BTreeMap<String, Repository> = BTreeMap::new();
+
+    for pretend in versions {
+        let (template_path, template) = match find_template_ebuild(repos, &pretend.package_name)? {
+            Some(found) => found,
+            // The package doesn't exist in this repository set.
+            None => continue,
+        };
+        let base = repos.get_repo_by_path(&template_path)?;
+        let name = format!("{}-pretend", base.name());
+        let base_dir = pretend_dir.join(&name);
+
+        let package_dir = base_dir.join(&pretend.package_name);
+        create_dir_all(&package_dir)?;
+
+        let original_dir = template_path.parent().unwrap();
+        for entry in read_dir(original_dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "ebuild") {
+                continue;
+            }
+            let link = package_dir.join(entry.file_name());
+            if !link.exists() {
+                symlink(&path, &link)
+                    .with_context(|| format!("Failed to symlink {}", link.display()))?;
+            }
+        }
+
+        let ebuild_path = package_dir.join(format!(
+            "{}-{}.ebuild",
+            template.short_package_name, pretend.version
+        ));
+        copy(&template_path, &ebuild_path)
+            .with_context(|| format!("Failed to create {}", ebuild_path.display()))?;
+
+        layered
+            .entry(name.clone())
+            .or_insert_with(|| Repository::new_layered(&name, &base_dir, base));
+    }
+
+    layered
+        .into_values()
+        .try_fold(repos.clone(), |repos, repo| repos.with_repository(repo))
+}
+
+/// Ensures every pretend version refers to a package found in at least one of
+/// `repo_sets`.
+pub fn check_pretend_versions(
+    repo_sets: &[&RepositorySet],
+    versions: &[PretendVersion],
+) -> Result<()> {
+    for pretend in versions {
+        let mut found = false;
+        for repos in repo_sets {
+            if find_template_ebuild(repos, &pretend.package_name)?.is_some() {
+                found = true;
+                break;
+            }
+        }
+        if !found {
+            bail!(
+                "No ebuild found for {} to use as a template for version {}",
+                pretend.package_name,
+                pretend.version
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Summary of a package selected as the best version in one of the worlds.
+struct Selection<'a> {
+    version: String,
+    package: Result<&'a Package, String>,
+}
+
+fn select_packages(packages: &[MaybePackage]) -> BTreeMap<&str, Selection<'_>> {
+    packages
+        .iter()
+        .into_group_map_by(|package| package.as_basic_data().package_name.as_str())
+        .into_iter()
+        .filter_map(|(package_name, candidates)| {
+            let best = select_best_version(candidates)?;
+            let package = match best {
+                MaybePackage::Ok(package) => Ok(package.as_ref()),
+                MaybePackage::Err(error) => Err(error.error.clone()),
+            };
+            Some((
+                package_name,
+                Selection {
+                    version: best.as_basic_data().version.to_string(),
+                    package,
+                },
+            ))
+        })
+        .collect()
+}
+
+fn to_cpvs(packages: &[std::sync::Arc<PackageDetails>]) -> BTreeSet<String> {
+    packages
+        .iter()
+        .map(|details| {
+            format!(
+                "{}-{}",
+                details.as_basic_data().package_name,
+                details.as_basic_data().version
+            )
+        })
+        .collect()
+}
+
+fn describe_set_change(before: &BTreeSet<String>, after: &BTreeSet<String>) -> Option<String> {
+    let removed = before.difference(after).map(|s| format!("-{s}"));
+    let added = after.difference(before).map(|s| format!("+{s}"));
+    let changes = removed.chain(added).join(" ");
+    (!changes.is_empty()).then_some(changes)
+}
+
+/// Returns the slot operator dependencies in a dependency expression
+/// rewritten by `rewrite_subslot_deps`, e.g. `sys-libs/foo:0/1=`.
+fn slot_operator_deps(expression: &str) -> BTreeSet<String> {
+    let mut atoms = BTreeSet::new();
+    // The expressions are generated by alchemist, so they always parse.
+    if let Ok(deps) = expression.parse::<PackageDependency>() {
+        deps.map_tree(|dep| {
+            if let Dependency::Leaf(atom) = &dep {
+                if atom
+                    .slot()
+                    .is_some_and(|slot| slot.rebuild_on_slot_change())
+                {
+                    atoms.insert(atom.to_string());
+                }
+            }
+            dep
+        });
+    }
+    atoms
+}
+
+/// Changes detected for a single package between the two worlds.
+#[derive(Default)]
+struct PackageReport {
+    version: Option<String>,
+    readiness: Option<String>,
+    resolution: Vec<String>,
+    rebuild: Vec<String>,
+    install_set: Option<String>,
+}
+
+impl PackageReport {
+    fn is_empty(&self) -> bool {
+        self.version.is_none()
+            && self.readiness.is_none()
+            && self.resolution.is_empty()
+            && self.rebuild.is_empty()
+            && self.install_set.is_none()
+    }
+}
+
+fn compare_packages(before: &Package, after: &Package) -> PackageReport {
+    let mut report = PackageReport::default();
+
+    for kind in DependencyKind::ALL {
+        if let Some(change) = describe_set_change(
+            &to_cpvs(before.dependencies.direct.get(kind)),
+            &to_cpvs(after.dependencies.direct.get(kind)),
+        ) {
+            report
+                .resolution
+                .push(format!("{}: {change}", kind.var_name()));
+        }
+    }
+
+    // `rewrite_subslot_deps` rewrites := slot operators to the SLOT and
+    // sub-SLOT of the resolved packages. They are recorded in the binpkg's
+    // XPAK, so any change here makes Portage rebuild the package.
+    for kind in DependencyKind::ALL {
+        if let Some(change) = describe_set_change(
+            &slot_operator_deps(before.dependencies.expressions.get(kind)),
+            &slot_operator_deps(after.dependencies.expressions.get(kind)),
+        ) {
+            report
+                .rebuild
+                .push(format!("{}: {change}", kind.var_name()));
+        }
+    }
+
+    // The install set is computed by `analyze_indirect_dependencies`.
+    report.install_set = describe_set_change(
+        &to_cpvs(&before.dependencies.indirect.install_set),
+        &to_cpvs(&after.dependencies.indirect.install_set),
+    );
+
+    report
+}
+
+fn compare_selections(before: Option<&Selection>, after: Option<&Selection>) -> PackageReport {
+    let version_of = |selection: Option<&Selection>| {
+        selection.map_or_else(|| "(none)".to_owned(), |s| s.version.clone())
+    };
+    let status_of = |selection: Option<&Selection>| match selection.map(|s| &s.package) {
+        None => "not available".to_owned(),
+        Some(Ok(_)) => "OK".to_owned(),
+        Some(Err(error)) => format!("failed ({error})"),
+    };
+
+    let mut report = match (
+        before.and_then(|s| s.package.as_ref().ok()),
+        after.and_then(|s| s.package.as_ref().ok()),
+    ) {
+        (Some(before), Some(after)) => compare_packages(before, after),
+        (None, None) if status_of(before) == status_of(after) => PackageReport::default(),
+        _ => PackageReport {
+            readiness: Some(format!("{} -> {}", status_of(before), status_of(after))),
+            ..Default::default()
+        },
+    };
+
+    if version_of(before) != version_of(after) {
+        report.version = Some(format!("{} -> {}", version_of(before), version_of(after)));
+    }
+
+    report
+}
+
+/// Extracts the lines of a report section from a [`PackageReport`].
+type ReportLines = fn(&PackageReport) -> Vec<String>;
+
+fn print_reports(title: &str, reports: &BTreeMap<&str, PackageReport>) {
+    println!("=======\t{title}");
+
+    let sections: [(&str, ReportLines); 5] = [
+        ("Version changes", |r: &PackageReport| {
+            r.version.iter().cloned().collect()
+        }),
+        ("Readiness changes", |r: &PackageReport| {
+            r.readiness.iter().cloned().collect()
+        }),
+        ("Resolution changes", |r: &PackageReport| {
+            r.resolution.clone()
+        }),
+        ("Sub-slot rebuilds", |r: &PackageReport| r.rebuild.clone()),
+        ("Install set changes", |r: &PackageReport| {
+            r.install_set.iter().cloned().collect()
+        }),
+    ];
+
+    for (name, lines_of) in sections {
+        let entries = reports
+            .iter()
+            .map(|(package_name, report)| (package_name, lines_of(report)))
+            .filter(|(_, lines)| !lines.is_empty())
+            .collect_vec();
+        println!("{name}: {}", entries.len());
+        for (package_name, lines) in entries {
+            for line in lines {
+                println!("  {package_name}: {line}");
+            }
+        }
+    }
+    println!();
+}
+
+fn compare_worlds<'a>(
+    before: &'a [MaybePackage],
+    after: &'a [MaybePackage],
+) -> BTreeMap<&'a str, PackageReport> {
+    let before = select_packages(before);
+    let after = select_packages(after);
+
+    before
+        .keys()
+        .chain(after.keys())
+        .unique()
+        .map(|package_name| {
+            (
+                *package_name,
+                compare_selections(before.get(package_name), after.get(package_name)),
+            )
+        })
+        .filter(|(_, report)| !report.is_empty())
+        .collect()
+}
+
+/// The entry point of "what-if" subcommand.
+///
+/// `host` and `target` describe the current state of the overlays, while
+/// `pretend_host` and `pretend_target` are loaded with [`PretendOverrides`]
+/// applied.
+pub fn what_if_main(
+    host: &TargetData,
+    target: Option<&TargetData>,
+    pretend_host: &TargetData,
+    pretend_target: Option<&TargetData>,
+    src_dir: &Path,
+) -> Result<()> {
+    let mut worlds = vec![(
+        format!("{} (host)", host.board),
+        load_packages(host, host, src_dir)?,
+        load_packages(pretend_host, pretend_host, src_dir)?,
+    )];
+    if let (Some(target), Some(pretend_target)) = (target, pretend_target) {
+        worlds.push((
+            target.board.clone(),
+            load_packages(host, target, src_dir)?,
+            load_packages(pretend_host, pretend_target, src_dir)?,
+        ));
+    }
+
+    for (title, before, after) in &worlds {
+        print_reports(title, &compare_worlds(before, after));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use alchemist::repository::RepositoryLayout;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_overrides() -> Result<()> {
+        let args = Args {
+            pretend_version: vec!["sys-libs/foo=1.2.3".into()],
+            pretend_use: vec![">=sys-libs/bar-2=baz,-qux".into()],
+        };
+        let overrides = args.parse_overrides()?;
+
+        assert_eq!(
+            overrides.versions,
+            vec![PretendVersion {
+                package_name: "sys-libs/foo".into(),
+                version: Version::try_new("1.2.3")?,
+            }]
+        );
+        assert_eq!(
+            overrides.use_updates,
+            vec![UseUpdate {
+                kind: UseUpdateKind::Set,
+                filter: UseUpdateFilter {
+                    atom: Some(">=sys-libs/bar-2".parse()?),
+                    stable_only: false,
+                },
+                use_tokens: "baz -qux".into(),
+            }]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_slot_operator_deps() {
+        assert_eq!(
+            slot_operator_deps("sys-libs/foo:0/1= >=sys-libs/bar-2 sys-libs/baz:2"),
+            BTreeSet::from(["sys-libs/foo:0/1=".to_owned()])
+        );
+        assert_eq!(slot_operator_deps(""), BTreeSet::new());
+    }
+
+    #[test]
+    fn test_parse_overrides_errors() {
+        let parse = |pretend_version: &str, pretend_use: &str| {
+            Args {
+                pretend_version: [pretend_version]
+                    .into_iter()
+                    .filter(|s| !s.is_empty())
+                    .map(String::from)
+                    .collect(),
+                pretend_use: [pretend_use]
+                    .into_iter()
+                    .filter(|s| !s.is_empty())
+                    .map(String::from)
+                    .collect(),
+            }
+            .parse_overrides()
+        };
+
+        assert!(parse("", "").is_err());
+        assert!(parse("sys-libs/foo", "").is_err());
+        assert!(parse("=sys-libs/foo-1=1.2.3", "").is_err());
+        assert!(parse("sys-libs/foo=not-a-version", "").is_err());
+        assert!(parse("", "sys-libs/foo").is_err());
+    }
+
+    #[test]
+    fn test_create_pretend_repos() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let dir = dir.path();
+
+        let overlay_dir = dir.join("overlay");
+        let package_dir = overlay_dir.join("sys-libs/foo");
+        create_dir_all(package_dir.join("files"))?;
+        std::fs::write(package_dir.join("foo-1.0.ebuild"), "EAPI=7\n")?;
+        std::fs::write(package_dir.join("foo-1.1.ebuild"), "EAPI=8\n")?;
+        std::fs::write(package_dir.join("foo-9999.ebuild"), "EAPI=8 # 9999\n")?;
+        std::fs::write(package_dir.join("Manifest"), "DIST foo-1.1.tar.gz 1\n")?;
+
+        let repos = RepositorySet::load_from_layouts(
+            "test",
+            &[RepositoryLayout::new("test", &overlay_dir, &[])],
+        )?;
+
+        let pretend_dir = dir.join("pretend");
+        let pretend_repos = create_pretend_repos(
+            &repos,
+            &[
+                PretendVersion {
+                    package_name: "sys-libs/foo".into(),
+                    version: Version::try_new("1.2.3")?,
+                },
+                PretendVersion {
+                    package_name: "sys-libs/missing".into(),
+                    version: Version::try_new("1.0")?,
+                },
+            ],
+            &pretend_dir,
+        )?;
+
+        let pretend_package_dir = pretend_dir.join("test-pretend/sys-libs/foo");
+        assert_eq!(
+            pretend_repos
+                .find_ebuilds("sys-libs/foo")?
+                .iter()
+                .sorted()
+                .collect_vec(),
+            [
+                package_dir.join("foo-1.0.ebuild"),
+                package_dir.join("foo-1.1.ebuild"),
+                package_dir.join("foo-9999.ebuild"),
+                pretend_package_dir.join("foo-1.2.3.ebuild"),
+            ]
+            .iter()
+            .sorted()
+            .collect_vec()
+        );
+        // The newest non-9999 ebuild is used as the template.
+        assert_eq!(
+            std::fs::read_to_string(pretend_package_dir.join("foo-1.2.3.ebuild"))?,
+            "EAPI=8\n"
+        );
+        assert_eq!(
+            std::fs::read_link(pretend_package_dir.join("Manifest"))?,
+            package_dir.join("Manifest")
+        );
+        assert_eq!(
+            std::fs::read_link(pretend_package_dir.join("files"))?,
+            package_dir.join("files")
+        );
+
+        Ok(())
+    }
+}
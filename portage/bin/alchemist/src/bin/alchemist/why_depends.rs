@@ -0,0 +1,139 @@
+// Copyright 2024 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+use std::{
+    collections::{hash_map::Entry, HashMap, VecDeque},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use alchemist::{
+    analyze::dependency::direct::{analyze_direct_dependencies, DependencyKind},
+    dependency::package::{AsPackageRef, PackageAtom},
+    ebuild::PackageDetails,
+    resolver::PackageResolver,
+};
+use anyhow::{bail, Context, Result};
+
+use crate::alchemist::TargetData;
+
+#[derive(clap::Args, Clone, Debug)]
+pub struct Args {
+    /// Package to start searching from.
+    from: String,
+
+    /// Package to find a dependency path to.
+    to: String,
+}
+
+/// Identifies a package in the host or the target world.
+type Node = (bool, Arc<PackageDetails>);
+
+fn node_key((is_host, details): &Node) -> (bool, PathBuf) {
+    (*is_host, details.as_basic_data().ebuild_path.clone())
+}
+
+fn format_node((is_host, details): &Node) -> String {
+    format!(
+        "{}-{} ({})",
+        details.as_basic_data().package_name,
+        details.as_basic_data().version,
+        if *is_host { "host" } else { "target" }
+    )
+}
+
+/// Prints the shortest dependency path from one package to another.
+///
+/// Dependencies are followed lazily from the starting package, so only the
+/// packages reachable until the destination is found are analyzed.
+pub fn why_depends_main(host: &TargetData, target: Option<&TargetData>, args: Args) -> Result<()> {
+    let from_atom = args.from.parse::<PackageAtom>()?;
+    let to_atom = args.to.parse::<PackageAtom>()?;
+
+    let cross_compile = if let Some(target) = target {
+        let cbuild = host
+            .config
+            .env()
+            .get("CHOST")
+            .context("host is missing CHOST")?;
+        let chost = target
+            .config
+            .env()
+            .get("CHOST")
+            .context("target is missing CHOST")?;
+        cbuild != chost
+    } else {
+        false
+    };
+
+    let resolver_for = |is_host: bool| -> &PackageResolver {
+        match target {
+            Some(target) if !is_host => &target.resolver,
+            _ => &host.resolver,
+        }
+    };
+
+    let start_is_host = target.is_none();
+    let start = resolver_for(start_is_host)
+        .find_best_package(&from_atom)?
+        .with_context(|| format!("No package matches {from_atom}"))?;
+    let start: Node = (start_is_host, start);
+
+    // Maps a visited node to the node and the dependency kind it was reached
+    // from.
+    let mut parents: HashMap<(bool, PathBuf), Option<(Node, DependencyKind)>> = HashMap::new();
+    let mut queue: VecDeque<Node> = VecDeque::new();
+
+    parents.insert(node_key(&start), None);
+    queue.push_back(start);
+
+    while let Some(current) = queue.pop_front() {
+        if to_atom.matches(&current.1.as_package_ref()) {
+            // Reconstruct the path by following the parents.
+            let mut path = vec![(current, None)];
+            while let Some(Some((parent, kind))) = parents.get(&node_key(&path.last().unwrap().0)) {
+                path.push((parent.clone(), Some(*kind)));
+            }
+            path.reverse();
+
+            let mut previous_kind: Option<DependencyKind> = None;
+            for (node, kind) in path {
+                match previous_kind {
+                    None => println!("{}", format_node(&node)),
+                    Some(previous_kind) => {
+                        println!("  {}\t{}", previous_kind.var_name(), format_node(&node))
+                    }
+                }
+                previous_kind = kind;
+            }
+            return Ok(());
+        }
+
+        let (is_host, details) = &current;
+        let (deps, _expressions) = analyze_direct_dependencies(
+            details,
+            !is_host && cross_compile,
+            &host.resolver,
+            resolver_for(*is_host),
+        )
+        .with_context(|| format!("Failed to analyze {}", format_node(&current)))?;
+
+        for kind in DependencyKind::ALL {
+            let dep_is_host = *is_host
+                || matches!(
+                    kind,
+                    DependencyKind::BuildHost | DependencyKind::InstallHost
+                );
+            for dep in deps.get(kind) {
+                let dep: Node = (dep_is_host, dep.clone());
+                if let Entry::Vacant(entry) = parents.entry(node_key(&dep)) {
+                    entry.insert(Some((current.clone(), kind)));
+                    queue.push_back(dep);
+                }
+            }
+        }
+    }
+
+    bail!("{from_atom} does not depend on {to_atom}");
+}
@@ -204,6 +204,23 @@ impl ConfigBundle {
         &self.env
     }
 
+    /// Returns FEATURES with incremental tokens resolved.
+    ///
+    /// [`Self::env`] keeps FEATURES as a plain concatenation so that Portage can
+    /// resolve it against its own defaults. This method resolves it without
+    /// those defaults, which is enough to tell whether a config enables a
+    /// feature.
+    pub fn features(&self) -> HashSet<&str> {
+        merge_incremental_tokens(
+            self.incremental_variables
+                .get("FEATURES")
+                .unwrap()
+                .iter()
+                .map(|s| s.as_str()),
+        )
+        .collect()
+    }
+
     /// Computes ACCEPT_KEYWORDS of a package.
     fn compute_accept_keywords(
         nodes: &[ConfigNode],
@@ -396,6 +413,12 @@ impl ConfigBundle {
         &self.provided_packages
     }
 
+    /// Returns the underlying configuration nodes in the order they are
+    /// evaluated.
+    pub fn nodes(&self) -> &[ConfigNode] {
+        &self.nodes
+    }
+
     /// Returns a list of all the configuration sources.
     pub fn sources(&self) -> Vec<&Path> {
         self.nodes
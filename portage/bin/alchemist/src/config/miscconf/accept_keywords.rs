@@ -6,12 +6,13 @@ use anyhow::{Context, Result};
 use itertools::Itertools;
 use std::{fs::read_to_string, path::Path, path::PathBuf};
 
-use crate::{
-    config::{AcceptKeywordsUpdate, ConfigNode, ConfigNodeValue},
-    dependency::package::PackageAtom,
-};
+use super::parse_config_atom;
+use crate::config::{AcceptKeywordsUpdate, ConfigNode, ConfigNodeValue};
 
-fn load_accept_keywords_configs_internal(source: PathBuf) -> Result<Vec<ConfigNode>> {
+fn load_accept_keywords_configs_internal(
+    source: PathBuf,
+    allow_build_id: bool,
+) -> Result<Vec<ConfigNode>> {
     // Return empty result if the path doesn't exist.
     if !source.try_exists()? {
         return Ok(Vec::new());
@@ -29,7 +30,7 @@ fn load_accept_keywords_configs_internal(source: PathBuf) -> Result<Vec<ConfigNo
             .into_iter()
             .map(|name| {
                 let new_source = source.join(name);
-                load_accept_keywords_configs_internal(new_source)
+                load_accept_keywords_configs_internal(new_source, allow_build_id)
                     .with_context(|| format!("Failed to load {}", source.display()))
             })
             .flatten_ok()
@@ -51,14 +52,15 @@ fn load_accept_keywords_configs_internal(source: PathBuf) -> Result<Vec<ConfigNo
                 .trim()
                 .split_once(|c: char| c.is_ascii_whitespace())
                 .unwrap_or((line, ""));
-            let atom = raw_atom.parse::<PackageAtom>().with_context(|| {
+            let atom = parse_config_atom(raw_atom, allow_build_id).with_context(|| {
                 format!("Failed to load {}: line {}", source.display(), lineno + 1)
             })?;
-            Ok(AcceptKeywordsUpdate {
+            Ok(atom.map(|atom| AcceptKeywordsUpdate {
                 atom,
                 accept_keywords: tokens.to_owned(),
-            })
+            }))
         })
+        .flatten_ok()
         .collect::<Result<Vec<_>>>()?;
 
     Ok(vec![ConfigNode {
@@ -69,15 +71,17 @@ fn load_accept_keywords_configs_internal(source: PathBuf) -> Result<Vec<ConfigNo
 
 /// Loads package.accept_keywords in the specified directory. If it's a file, just loads the file.
 /// If it's a directory, loads its all descendant directories and files.
-pub fn load_accept_keywords_configs(dir: &Path) -> Result<Vec<ConfigNode>> {
-    load_accept_keywords_configs_internal(dir.join("package.accept_keywords"))
+///
+/// See [`super::parse_config_atom`] for `allow_build_id`.
+pub fn load_accept_keywords_configs(dir: &Path, allow_build_id: bool) -> Result<Vec<ConfigNode>> {
+    load_accept_keywords_configs_internal(dir.join("package.accept_keywords"), allow_build_id)
 }
 
 #[cfg(test)]
 mod tests {
     use std::str::FromStr;
 
-    use crate::testutils::write_files;
+    use crate::{dependency::package::PackageAtom, testutils::write_files};
 
     use super::*;
 
@@ -86,7 +90,7 @@ mod tests {
         let dir = tempfile::tempdir()?;
         let dir = dir.as_ref();
 
-        let nodes = load_accept_keywords_configs(dir)?;
+        let nodes = load_accept_keywords_configs(dir, false)?;
         assert_eq!(Vec::<ConfigNode>::new(), nodes);
         Ok(())
     }
@@ -98,7 +102,7 @@ mod tests {
 
         write_files(dir, [("package.accept_keywords", "pkg/a amd64 ~x86")])?;
 
-        let nodes = load_accept_keywords_configs(dir)?;
+        let nodes = load_accept_keywords_configs(dir, false)?;
         assert_eq!(
             vec![ConfigNode {
                 sources: vec![dir.join("package.accept_keywords")],
@@ -125,7 +129,7 @@ mod tests {
             ],
         )?;
 
-        let nodes = load_accept_keywords_configs(dir)?;
+        let nodes = load_accept_keywords_configs(dir, false)?;
         assert_eq!(
             vec![
                 ConfigNode {
@@ -12,11 +12,22 @@ use std::vec;
 use crate::config::{ConfigNode, ConfigNodeValue, PackageBashrc};
 use crate::dependency::package::PackageAtom;
 
-pub fn load_bashrc(dir: &Path) -> Result<Vec<ConfigNode>> {
+/// Loads `profile.bashrc` and `package.bashrc` of a profile.
+///
+/// `package.bashrc` is ignored unless `allow_package_bashrc` is set, i.e. the
+/// repository enables `profile-formats = profile-bashrcs`, like Portage does.
+pub fn load_bashrc(dir: &Path, allow_package_bashrc: bool) -> Result<Vec<ConfigNode>> {
     let mut nodes: Vec<ConfigNode> = vec![];
 
     nodes.extend(load_profile_bashrc(dir)?);
-    nodes.extend(load_package_bashrc(dir)?);
+    if allow_package_bashrc {
+        nodes.extend(load_package_bashrc(dir)?);
+    } else if dir.join("package.bashrc").exists() {
+        eprintln!(
+            "WARNING: Ignoring {}, which requires profile-formats = profile-bashrcs",
+            dir.join("package.bashrc").display()
+        );
+    }
 
     Ok(nodes)
 }
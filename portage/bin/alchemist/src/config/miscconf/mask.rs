@@ -5,12 +5,14 @@
 use anyhow::{Context, Result};
 use std::{fs::read_to_string, path::Path};
 
-use crate::{
-    config::{ConfigNode, ConfigNodeValue, PackageMaskKind, PackageMaskUpdate},
-    dependency::package::PackageAtom,
-};
-
-fn load_package_config(source: &Path, kind: PackageMaskKind) -> Result<Vec<ConfigNode>> {
+use super::parse_config_atom;
+use crate::config::{ConfigNode, ConfigNodeValue, PackageMaskKind, PackageMaskUpdate};
+
+fn load_package_config(
+    source: &Path,
+    kind: PackageMaskKind,
+    allow_build_id: bool,
+) -> Result<Vec<ConfigNode>> {
     if !source.exists() {
         return Ok(Vec::new());
     }
@@ -26,7 +28,7 @@ fn load_package_config(source: &Path, kind: PackageMaskKind) -> Result<Vec<Confi
         for name in names {
             let new_source = source.join(name);
             nodes.extend(
-                load_package_config(&new_source, kind)
+                load_package_config(&new_source, kind, allow_build_id)
                     .with_context(|| format!("Failed to load {}", source.display()))?,
             );
         }
@@ -43,14 +45,16 @@ fn load_package_config(source: &Path, kind: PackageMaskKind) -> Result<Vec<Confi
         .enumerate()
         .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
     {
-        let atom = line.trim().parse::<PackageAtom>().with_context(|| {
+        let atom = parse_config_atom(line.trim(), allow_build_id).with_context(|| {
             format!(
                 "Failed to load {}: syntax error at line {}",
                 source.display(),
                 lineno + 1
             )
         })?;
-        updates.push(PackageMaskUpdate { kind, atom })
+        if let Some(atom) = atom {
+            updates.push(PackageMaskUpdate { kind, atom })
+        }
     }
 
     Ok(vec![ConfigNode {
@@ -59,9 +63,20 @@ fn load_package_config(source: &Path, kind: PackageMaskKind) -> Result<Vec<Confi
     }])
 }
 
-pub fn load_package_configs(dir: &Path) -> Result<Vec<ConfigNode>> {
-    let mask_nodes = load_package_config(&dir.join("package.mask"), PackageMaskKind::Mask)?;
-    let unmask_nodes = load_package_config(&dir.join("package.unmask"), PackageMaskKind::Unmask)?;
+/// Loads package.mask and package.unmask in the specified directory.
+///
+/// See [`super::parse_config_atom`] for `allow_build_id`.
+pub fn load_package_configs(dir: &Path, allow_build_id: bool) -> Result<Vec<ConfigNode>> {
+    let mask_nodes = load_package_config(
+        &dir.join("package.mask"),
+        PackageMaskKind::Mask,
+        allow_build_id,
+    )?;
+    let unmask_nodes = load_package_config(
+        &dir.join("package.unmask"),
+        PackageMaskKind::Unmask,
+        allow_build_id,
+    )?;
     Ok([mask_nodes, unmask_nodes].concat())
 }
 
@@ -69,7 +84,7 @@ pub fn load_package_configs(dir: &Path) -> Result<Vec<ConfigNode>> {
 mod tests {
     use std::str::FromStr;
 
-    use crate::testutils::write_files;
+    use crate::{dependency::package::PackageAtom, testutils::write_files};
 
     use super::*;
 
@@ -86,7 +101,7 @@ mod tests {
             ],
         )?;
 
-        let nodes = load_package_configs(dir)?;
+        let nodes = load_package_configs(dir, false)?;
         assert_eq!(
             vec![
                 ConfigNode {
@@ -140,7 +155,7 @@ mod tests {
             ],
         )?;
 
-        let nodes = load_package_configs(dir)?;
+        let nodes = load_package_configs(dir, false)?;
         assert_eq!(
             vec![
                 ConfigNode {
@@ -7,3 +7,44 @@ pub mod bashrc;
 pub mod mask;
 pub mod provided;
 pub mod useflags;
+
+use anyhow::Result;
+
+use crate::dependency::package::PackageAtom;
+
+/// Parses an atom at the beginning of a line in a `package.*` file.
+///
+/// If `allow_build_id` is true, `=` atoms may end with a build ID, e.g.
+/// `=sys-libs/zlib-1.2.13-r1-2`. Build IDs only identify binary packages, so
+/// such atoms never match an ebuild and `None` is returned for them.
+fn parse_config_atom(raw_atom: &str, allow_build_id: bool) -> Result<Option<PackageAtom>> {
+    let err = match raw_atom.parse::<PackageAtom>() {
+        Ok(atom) => return Ok(Some(atom)),
+        Err(err) => err,
+    };
+    if allow_build_id && raw_atom.starts_with('=') {
+        if let Some((prefix, build_id)) = raw_atom.rsplit_once('-') {
+            if !build_id.is_empty()
+                && build_id.chars().all(|c| c.is_ascii_digit())
+                && prefix.parse::<PackageAtom>().is_ok()
+            {
+                return Ok(None);
+            }
+        }
+    }
+    Err(err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config_atom_build_id() -> Result<()> {
+        assert!(parse_config_atom("=sys-libs/zlib-1.2.13-r1", false)?.is_some());
+        assert!(parse_config_atom("=sys-libs/zlib-1.2.13-r1-2", false).is_err());
+        assert!(parse_config_atom("=sys-libs/zlib-1.2.13-r1-2", true)?.is_none());
+        assert!(parse_config_atom("=sys-libs/zlib-1.2.13-r1-x", true).is_err());
+        Ok(())
+    }
+}
@@ -5,10 +5,8 @@
 use anyhow::{Context, Result};
 use std::{fs::read_to_string, path::Path};
 
-use crate::{
-    config::{ConfigNode, ConfigNodeValue, UseUpdate, UseUpdateFilter, UseUpdateKind},
-    dependency::package::PackageAtom,
-};
+use super::parse_config_atom;
+use crate::config::{ConfigNode, ConfigNodeValue, UseUpdate, UseUpdateFilter, UseUpdateKind};
 
 fn load_wildcard_use_config(
     source: &Path,
@@ -66,6 +64,7 @@ fn load_package_use_config(
     source: &Path,
     kind: UseUpdateKind,
     stable_only: bool,
+    allow_build_id: bool,
 ) -> Result<Vec<ConfigNode>> {
     if !source.exists() {
         return Ok(Vec::new());
@@ -82,7 +81,7 @@ fn load_package_use_config(
         for name in names {
             let new_source = source.join(name);
             nodes.extend(
-                load_package_use_config(&new_source, kind, stable_only)
+                load_package_use_config(&new_source, kind, stable_only, allow_build_id)
                     .with_context(|| format!("Failed to load {}", source.display()))?,
             );
         }
@@ -103,9 +102,11 @@ fn load_package_use_config(
             .trim()
             .split_once(|c: char| c.is_ascii_whitespace())
             .unwrap_or((line, ""));
-        let atom = raw_atom
-            .parse::<PackageAtom>()
+        let atom = parse_config_atom(raw_atom, allow_build_id)
             .with_context(|| format!("Failed to load {}: line {}", source.display(), lineno + 1))?;
+        let Some(atom) = atom else {
+            continue;
+        };
         updates.push(UseUpdate {
             kind,
             filter: UseUpdateFilter {
@@ -122,28 +123,26 @@ fn load_package_use_config(
     }])
 }
 
-pub fn load_use_configs(dir: &Path) -> Result<Vec<ConfigNode>> {
+/// Loads use.* and package.use* in the specified directory.
+///
+/// See [`super::parse_config_atom`] for `allow_build_id`.
+pub fn load_use_configs(dir: &Path, allow_build_id: bool) -> Result<Vec<ConfigNode>> {
+    let load_package_use_config = |name: &str, kind: UseUpdateKind, stable_only: bool| {
+        load_package_use_config(&dir.join(name), kind, stable_only, allow_build_id)
+    };
     Ok([
         // Set
-        load_package_use_config(&dir.join("package.use"), UseUpdateKind::Set, false)?,
+        load_package_use_config("package.use", UseUpdateKind::Set, false)?,
         // Mask
         load_wildcard_use_config(&dir.join("use.mask"), UseUpdateKind::Mask, false)?,
         load_wildcard_use_config(&dir.join("use.stable.mask"), UseUpdateKind::Mask, true)?,
-        load_package_use_config(&dir.join("package.use.mask"), UseUpdateKind::Mask, false)?,
-        load_package_use_config(
-            &dir.join("package.use.stable.mask"),
-            UseUpdateKind::Mask,
-            true,
-        )?,
+        load_package_use_config("package.use.mask", UseUpdateKind::Mask, false)?,
+        load_package_use_config("package.use.stable.mask", UseUpdateKind::Mask, true)?,
         // Force
         load_wildcard_use_config(&dir.join("use.force"), UseUpdateKind::Force, false)?,
         load_wildcard_use_config(&dir.join("use.stable.force"), UseUpdateKind::Force, true)?,
-        load_package_use_config(&dir.join("package.use.force"), UseUpdateKind::Force, false)?,
-        load_package_use_config(
-            &dir.join("package.use.stable.force"),
-            UseUpdateKind::Force,
-            true,
-        )?,
+        load_package_use_config("package.use.force", UseUpdateKind::Force, false)?,
+        load_package_use_config("package.use.stable.force", UseUpdateKind::Force, true)?,
     ]
     .concat())
 }
@@ -152,7 +151,7 @@ pub fn load_use_configs(dir: &Path) -> Result<Vec<ConfigNode>> {
 mod tests {
     use std::str::FromStr;
 
-    use crate::testutils::write_files;
+    use crate::{dependency::package::PackageAtom, testutils::write_files};
 
     use super::*;
 
@@ -176,7 +175,7 @@ mod tests {
             ],
         )?;
 
-        let nodes = load_use_configs(dir)?;
+        let nodes = load_use_configs(dir, false)?;
         assert_eq!(
             vec![
                 ConfigNode {
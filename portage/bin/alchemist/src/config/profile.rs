@@ -2,7 +2,7 @@
 // Use of this source code is governed by a BSD-style license that can be
 // found in the LICENSE file.
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use itertools::Itertools;
 use std::{
     fs::read_to_string,
@@ -22,13 +22,29 @@ use crate::{
     },
     data::Vars,
     path::clean_path,
-    repository::{RepositorySet, RepositorySetOperations},
+    repository::{ProfileFormat, ProfileFormats, RepositorySet, RepositorySetOperations},
 };
 
+/// EAPIs that profiles can declare in their `eapi` file.
+const SUPPORTED_PROFILE_EAPIS: &[&str] = &[
+    "0",
+    "1",
+    "2",
+    "3",
+    "4",
+    "4-python",
+    "5",
+    "5-progress",
+    "6",
+    "7",
+    "8",
+];
+
 /// Parsed Portage profile.
 #[derive(Debug, Eq, PartialEq)]
 pub struct Profile {
     profile_path: PathBuf,
+    eapi: String,
     parents: Vec<Profile>,
     makeconf: MakeConf,
     precomputed_nodes: Vec<ConfigNode>,
@@ -41,7 +57,12 @@ impl Profile {
     /// parents.
     pub fn load(dir: &Path, repos: &RepositorySet) -> Result<Self> {
         let context = || format!("Failed to load profile {}", dir.display());
+        let formats = find_profile_formats(dir, repos);
+        let eapi = load_eapi(dir, &formats).with_context(context)?;
         let parent_keys = load_parents(&dir.join("parent"))?;
+        check_profile_formats(dir, &formats, &eapi, &parent_keys).with_context(context)?;
+        let allow_build_id = formats.contains(ProfileFormat::BuildId);
+        let allow_package_bashrc = formats.contains(ProfileFormat::ProfileBashrcs);
         let parents = parent_keys
             .into_iter()
             .map(|parent_key| {
@@ -60,16 +81,17 @@ impl Profile {
             MakeConf::load(&dir.join("make.defaults"), dir, false, true).with_context(context)?;
 
         let precomputed_nodes = [
-            load_package_configs(dir).with_context(context)?,
-            load_accept_keywords_configs(dir).with_context(context)?,
-            load_use_configs(dir).with_context(context)?,
+            load_package_configs(dir, allow_build_id).with_context(context)?,
+            load_accept_keywords_configs(dir, allow_build_id).with_context(context)?,
+            load_use_configs(dir, allow_build_id).with_context(context)?,
             load_provided_packages_config(dir).with_context(context)?,
-            load_bashrc(dir).with_context(context)?,
+            load_bashrc(dir, allow_package_bashrc).with_context(context)?,
         ]
         .concat();
 
         Ok(Self {
             profile_path: dir.to_owned(),
+            eapi,
             parents,
             makeconf,
             precomputed_nodes,
@@ -92,6 +114,11 @@ impl Profile {
     pub fn profile_path(&self) -> &Path {
         &self.profile_path
     }
+
+    /// The EAPI of the profile directory, which is not inherited by children.
+    pub fn eapi(&self) -> &str {
+        &self.eapi
+    }
 }
 
 impl ConfigSource for Profile {
@@ -107,6 +134,70 @@ impl ConfigSource for Profile {
     }
 }
 
+/// Returns the profile formats of the repository containing the profile at
+/// `dir`.
+///
+/// Profiles outside known repositories are loaded without restrictions.
+fn find_profile_formats(dir: &Path, repos: &RepositorySet) -> ProfileFormats {
+    repos
+        .get_repos()
+        .into_iter()
+        .filter(|repo| dir.starts_with(repo.profiles_dir()))
+        .max_by_key(|repo| repo.profiles_dir().components().count())
+        .map(|repo| repo.profile_formats().clone())
+        .unwrap_or_else(ProfileFormats::unrestricted)
+}
+
+/// Loads the `eapi` file of a profile directory.
+fn load_eapi(dir: &Path, formats: &ProfileFormats) -> Result<String> {
+    let path = dir.join("eapi");
+    let eapi = match read_to_string(&path) {
+        Ok(contents) => contents.trim().to_owned(),
+        Err(err) if err.kind() == ErrorKind::NotFound => formats.default_eapi().to_owned(),
+        Err(err) => return Err(err).with_context(|| format!("Reading {}", path.display())),
+    };
+    if !SUPPORTED_PROFILE_EAPIS.contains(&eapi.as_str()) {
+        bail!("Unsupported profile EAPI: {}", eapi);
+    }
+    Ok(eapi)
+}
+
+/// Rejects profile features not enabled by `profile-formats` of the
+/// repository, which Portage would interpret differently.
+fn check_profile_formats(
+    dir: &Path,
+    formats: &ProfileFormats,
+    eapi: &str,
+    parent_keys: &[String],
+) -> Result<()> {
+    if !formats.contains(ProfileFormat::Portage2) {
+        if let Some(parent_key) = parent_keys.iter().find(|key| key.contains(':')) {
+            bail!(
+                "Parent {} refers to a repository, which requires profile-formats = portage-2",
+                parent_key
+            );
+        }
+    }
+
+    if !formats.allows_directories(eapi) && dir.is_dir() {
+        for entry in dir.read_dir()? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if (name.starts_with("package.") || name.starts_with("use."))
+                && entry.file_type()?.is_dir()
+            {
+                bail!(
+                    "{} is a directory, which requires profile-formats = portage-1 or portage-2",
+                    name
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
 fn load_parents(path: &Path) -> Result<Vec<String>> {
     let contents = read_to_string(path).or_else(|err| {
         if err.kind() == ErrorKind::NotFound {
@@ -185,9 +276,11 @@ mod tests {
 
         let expected = Profile {
             profile_path: dir.join("mnt/host/source/src/overlays/overlay-amd64-generic/profiles/base"),
+            eapi: "0".to_owned(),
             parents: vec![
                 Profile {
                     profile_path: dir.join("mnt/host/source/src/third_party/chromiumos-overlay/profiles/default/linux/amd64/10.0/chromeos"),
+                    eapi: "0".to_owned(),
                     parents: vec![],
                     makeconf: MakeConf::new_for_testing(
                         vec![dir.join(CHROMIUM_MAKE_CONF)],
@@ -197,6 +290,7 @@ mod tests {
                 },
                 Profile {
                     profile_path: dir.join("mnt/host/source/src/third_party/chromiumos-overlay/profiles/features/selinux"),
+                    eapi: "0".to_owned(),
                     parents: vec![],
                     makeconf: MakeConf::new_for_testing(vec![], HashMap::new()),
                     precomputed_nodes: vec![],
@@ -213,4 +307,97 @@ mod tests {
 
         Ok(())
     }
+
+    fn load_test_repos(dir: &Path, layout_conf: &str) -> Result<RepositorySet> {
+        write_files(
+            dir,
+            [(
+                "metadata/layout.conf",
+                format!("repo-name = test\n{}", layout_conf).as_str(),
+            )],
+        )?;
+        RepositorySet::load_from_layouts("test", &[RepositoryLayout::load(dir)?])
+    }
+
+    #[test]
+    fn test_profile_formats_pms() -> Result<()> {
+        let dir = tempdir()?;
+        let dir = dir.as_ref();
+        let repos = load_test_repos(dir, "")?;
+
+        write_files(
+            dir,
+            [
+                ("profiles/plain/package.use", "sys-libs/zlib minizip\n"),
+                (
+                    "profiles/plain/packages",
+                    "*sys-libs/zlib\n-*sys-apps/foo\n",
+                ),
+                ("profiles/dir/package.use/zlib", "sys-libs/zlib minizip\n"),
+                ("profiles/colon/parent", "test:plain\n"),
+                ("profiles/bashrc/package.bashrc", "sys-libs/zlib zlib.sh\n"),
+                (
+                    "profiles/build-id/package.mask",
+                    "=sys-libs/zlib-1.2.13-1\n",
+                ),
+                ("profiles/eapi/eapi", "9\n"),
+            ],
+        )?;
+
+        let profile = Profile::load(&dir.join("profiles/plain"), &repos)?;
+        assert_eq!(profile.eapi(), "0");
+
+        // Portage ignores package.bashrc without profile-bashrcs.
+        let profile = Profile::load(&dir.join("profiles/bashrc"), &repos)?;
+        assert_eq!(profile.precomputed_nodes, vec![]);
+
+        for name in ["dir", "colon", "build-id", "eapi"] {
+            assert!(
+                Profile::load(&dir.join("profiles").join(name), &repos).is_err(),
+                "profiles/{name} should be rejected"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_profile_formats_portage2() -> Result<()> {
+        let dir = tempdir()?;
+        let dir = dir.as_ref();
+        let repos = load_test_repos(
+            dir,
+            "profile-formats = portage-2 profile-default-eapi profile-bashrcs build-id\n\
+             profile_eapi_when_unspecified = 5-progress\n",
+        )?;
+
+        write_files(
+            dir,
+            [
+                ("profiles/base/package.use/zlib", "sys-libs/zlib minizip\n"),
+                ("profiles/base/package.mask", "=sys-libs/zlib-1.2.13-1\n"),
+                ("profiles/base/package.bashrc", "sys-libs/zlib zlib.sh\n"),
+                ("profiles/base/bashrc/zlib.sh", ""),
+                ("profiles/child/parent", "test:base\n"),
+                ("profiles/child/eapi", "7\n"),
+            ],
+        )?;
+
+        let profile = Profile::load(&dir.join("profiles/child"), &repos)?;
+        assert_eq!(profile.eapi(), "7");
+        assert_eq!(profile.parents[0].eapi(), "5-progress");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unsupported_profile_format() -> Result<()> {
+        let dir = tempdir()?;
+        let dir = dir.as_ref();
+
+        assert!(load_test_repos(dir, "profile-formats = portage-2 profile-foo\n").is_err());
+        assert!(load_test_repos(dir, "profile-formats = portage-2 profile-set\n").is_err());
+
+        Ok(())
+    }
 }
@@ -47,13 +47,13 @@ impl SiteSettings {
         let portage_dir = root_dir.join("etc/portage");
         let site_profile_dir = portage_dir.join("profile");
         let precomputed_nodes = [
-            load_package_configs(&site_profile_dir)?,
-            load_accept_keywords_configs(&site_profile_dir)?,
-            load_use_configs(&site_profile_dir)?,
+            load_package_configs(&site_profile_dir, false)?,
+            load_accept_keywords_configs(&site_profile_dir, false)?,
+            load_use_configs(&site_profile_dir, false)?,
             load_provided_packages_config(&site_profile_dir)?,
-            load_package_configs(&portage_dir)?,
-            load_accept_keywords_configs(&portage_dir)?,
-            load_use_configs(&portage_dir)?,
+            load_package_configs(&portage_dir, false)?,
+            load_accept_keywords_configs(&portage_dir, false)?,
+            load_use_configs(&portage_dir, false)?,
             load_provided_packages_config(&portage_dir)?,
         ]
         .concat();
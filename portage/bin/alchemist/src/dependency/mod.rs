@@ -44,8 +44,9 @@ pub trait ThreeValuedPredicate<T: ?Sized> {
 
 /// A bundle of types needed to instantiate [`Dependency`].
 pub trait DependencyMeta: Clone + std::fmt::Debug + Eq {
-    /// The type of leaf elements of the dependency type.
-    type Leaf: Clone + std::fmt::Debug + Eq;
+    /// The type of leaf elements of the dependency type. Its [`Display`]
+    /// implementation must produce the PMS syntax accepted by the parser.
+    type Leaf: Clone + std::fmt::Debug + Display + Eq;
 
     /// The type of the parser producing the dependency type.
     type Parser: DependencyParser;
@@ -250,42 +251,80 @@ impl<M: DependencyMeta> Default for Dependency<M> {
     }
 }
 
-impl<M: DependencyMeta> Display for Dependency<M>
-where
-    M::Leaf: Display,
-{
+/// Formats a group expression, e.g. `|| ( a b )`.
+///
+/// With the alternate flag (`{:#}`), the opening and closing parentheses are
+/// put on their own lines and each child on its own line indented by two
+/// spaces.
+fn fmt_group<D: Display>(
+    f: &mut std::fmt::Formatter<'_>,
+    prefix: &str,
+    children: &[D],
+) -> std::fmt::Result {
+    if children.is_empty() {
+        return write!(f, "{}( )", prefix);
+    }
+    if !f.alternate() {
+        write!(f, "{}(", prefix)?;
+        for child in children {
+            write!(f, " {}", child)?;
+        }
+        return write!(f, " )");
+    }
+    writeln!(f, "{}(", prefix)?;
+    for child in children {
+        for line in format!("{:#}", child).lines() {
+            writeln!(f, "  {}", line)?;
+        }
+    }
+    write!(f, ")")
+}
+
+/// Formats the children of a top-level all-of expression one per line, i.e.
+/// as they are written in ebuild variables.
+fn fmt_top_level<D: Display>(children: &[D]) -> String {
+    children
+        .iter()
+        .map(|child| format!("{:#}", child))
+        .join("\n")
+}
+
+impl<M: DependencyMeta> Dependency<M> {
+    /// Formats the expression in the canonical form to be written in an
+    /// ebuild variable: one item per line, with nested groups indented.
+    ///
+    /// Unlike `{:#}`, the top-level all-of expression is not enclosed in
+    /// parentheses so that parsing the output gives back the same expression.
+    pub fn to_pretty_string(&self) -> String {
+        match self {
+            Self::Composite(composite) => match &**composite {
+                CompositeDependency::AllOf { children } => fmt_top_level(children),
+                CompositeDependency::Constant { value: true, .. } => String::new(),
+                _ => format!("{:#}", self),
+            },
+            Self::Leaf(_) => format!("{:#}", self),
+        }
+    }
+}
+
+/// `{}` formats the expression in a single line, and `{:#}` formats it with
+/// one item per line.
+impl<M: DependencyMeta> Display for Dependency<M> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Leaf(leaf) => leaf.fmt(f),
             Self::Composite(composite) => match &**composite {
-                CompositeDependency::AllOf { children } => {
-                    write!(f, "(")?;
-                    for child in children.iter() {
-                        write!(f, " {}", child)?;
-                    }
-                    write!(f, " )")
-                }
-                CompositeDependency::AnyOf { children } => {
-                    write!(f, "|| (")?;
-                    for child in children.iter() {
-                        write!(f, " {}", child)?;
-                    }
-                    write!(f, " )")
-                }
+                CompositeDependency::AllOf { children } => fmt_group(f, "", children),
+                CompositeDependency::AnyOf { children } => fmt_group(f, "|| ", children),
                 CompositeDependency::UseConditional {
                     name,
                     expect,
                     children,
-                } => {
-                    if !expect {
-                        write!(f, "!")?;
-                    }
-                    write!(f, "{}? (", name)?;
-                    for child in children.iter() {
-                        write!(f, " {}", child)?;
-                    }
-                    write!(f, " )")
-                }
+                } => fmt_group(
+                    f,
+                    &format!("{}{}? ", if *expect { "" } else { "!" }, name),
+                    children,
+                ),
                 CompositeDependency::Constant { value, .. } => {
                     if *value {
                         write!(f, "( )")
@@ -384,56 +423,44 @@ impl<M: DependencyMeta> ComplexDependency<M> {
     }
 }
 
-impl<M: DependencyMeta> Display for ComplexDependency<M>
-where
-    M::Leaf: Display,
-{
+impl<M: DependencyMeta> ComplexDependency<M> {
+    /// Formats the expression in the canonical form to be written in an
+    /// ebuild variable. See [`Dependency::to_pretty_string`].
+    pub fn to_pretty_string(&self) -> String {
+        match self {
+            Self::Composite(composite) => match &**composite {
+                ComplexCompositeDependency::AllOf { children } => fmt_top_level(children),
+                _ => format!("{:#}", self),
+            },
+            Self::Leaf(_) => format!("{:#}", self),
+        }
+    }
+}
+
+/// `{}` formats the expression in a single line, and `{:#}` formats it with
+/// one item per line.
+impl<M: DependencyMeta> Display for ComplexDependency<M> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Leaf(leaf) => leaf.fmt(f),
             Self::Composite(composite) => match &**composite {
-                ComplexCompositeDependency::AllOf { children } => {
-                    write!(f, "(")?;
-                    for child in children.iter() {
-                        write!(f, " {}", child)?;
-                    }
-                    write!(f, " )")
-                }
-                ComplexCompositeDependency::AnyOf { children } => {
-                    write!(f, "|| (")?;
-                    for child in children.iter() {
-                        write!(f, " {}", child)?;
-                    }
-                    write!(f, " )")
-                }
+                ComplexCompositeDependency::AllOf { children } => fmt_group(f, "", children),
+                ComplexCompositeDependency::AnyOf { children } => fmt_group(f, "|| ", children),
                 ComplexCompositeDependency::ExactlyOneOf { children } => {
-                    write!(f, "^^ (")?;
-                    for child in children.iter() {
-                        write!(f, " {}", child)?;
-                    }
-                    write!(f, " )")
+                    fmt_group(f, "^^ ", children)
                 }
                 ComplexCompositeDependency::AtMostOneOf { children } => {
-                    write!(f, "?? (")?;
-                    for child in children.iter() {
-                        write!(f, " {}", child)?;
-                    }
-                    write!(f, " )")
+                    fmt_group(f, "?? ", children)
                 }
                 ComplexCompositeDependency::UseConditional {
                     name,
                     expect,
                     children,
-                } => {
-                    if !expect {
-                        write!(f, "!")?;
-                    }
-                    write!(f, "{}? (", name)?;
-                    for child in children.iter() {
-                        write!(f, " {}", child)?;
-                    }
-                    write!(f, " )")
-                }
+                } => fmt_group(
+                    f,
+                    &format!("{}{}? ", if *expect { "" } else { "!" }, name),
+                    children,
+                ),
             },
         }
     }
@@ -550,3 +577,78 @@ where
         M::Parser::parse(s)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use self::{
+        package::PackageDependency, requse::RequiredUseDependency, restrict::RestrictDependency,
+        uri::UriDependency,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_display_single_line() -> Result<()> {
+        let deps: PackageDependency = "a/b || ( c/d !x? ( >=e/f-1:0/1=[y(+)?,-z] ) )".parse()?;
+        assert_eq!(
+            deps.to_string(),
+            "( a/b || ( c/d !x? ( >=e/f-1:0/1=[y(+)?,-z] ) ) )"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_pretty_string() -> Result<()> {
+        let deps: PackageDependency =
+            "a/b || ( c/d !x? ( >=e/f-1:0/1=[y(+)?,-z] !!g/h ) ) ( i/j )".parse()?;
+        assert_eq!(
+            deps.to_pretty_string(),
+            "a/b\n\
+             || (\n  \
+               c/d\n  \
+               !x? (\n    \
+                 >=e/f-1:0/1=[y(+)?,-z]\n    \
+                 !!g/h\n  \
+               )\n\
+             )\n\
+             (\n  \
+               i/j\n\
+             )"
+        );
+
+        let required_use: RequiredUseDependency = "^^ ( a b ) x? ( ?? ( c !d ) )".parse()?;
+        assert_eq!(
+            required_use.to_pretty_string(),
+            "^^ (\n  a\n  b\n)\nx? (\n  ?? (\n    c\n    !d\n  )\n)"
+        );
+
+        assert_eq!(PackageDependency::default().to_pretty_string(), "");
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_pretty_string_round_trip() -> Result<()> {
+        for raw in [
+            "a/b:= c/d:* =e/f-1.2* ~g/h-1 !i/j x? ( k/l[m=,!n?] || ( o/p q/r ) )",
+            "",
+        ] {
+            let deps: PackageDependency = raw.parse()?;
+            assert_eq!(deps.to_pretty_string().parse::<PackageDependency>()?, deps);
+        }
+
+        let deps: RequiredUseDependency = "|| ( a !b ) c? ( ^^ ( d e ) )".parse()?;
+        assert_eq!(
+            deps.to_pretty_string().parse::<RequiredUseDependency>()?,
+            deps
+        );
+
+        let deps: UriDependency =
+            "https://example.com/a.tar.gz -> b.tar.gz x? ( https://example.com/c.tar.gz )"
+                .parse()?;
+        assert_eq!(deps.to_pretty_string().parse::<UriDependency>()?, deps);
+
+        let deps: RestrictDependency = "strip !test? ( test )".parse()?;
+        assert_eq!(deps.to_pretty_string().parse::<RestrictDependency>()?, deps);
+        Ok(())
+    }
+}
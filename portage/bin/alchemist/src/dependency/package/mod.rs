@@ -107,6 +107,14 @@ impl PackageSlotDependency {
         }
     }
 
+    /// Returns the SLOT and the optional sub-SLOT required by the dependency.
+    /// Returns [`None`] for the `:*` and `:=` operators without a SLOT.
+    pub fn slot(&self) -> Option<(&str, Option<&str>)> {
+        self.slot
+            .as_ref()
+            .map(|(main, sub)| (main.as_str(), sub.as_deref()))
+    }
+
     pub fn rebuild_on_slot_change(&self) -> bool {
         self.rebuild_on_slot_change
     }
@@ -183,6 +191,12 @@ pub struct PackageUseDependency {
 }
 
 impl PackageUseDependency {
+    /// Returns the value assumed when the target package doesn't declare the
+    /// USE flag, i.e. `(+)` or `(-)`.
+    pub fn missing_default(&self) -> Option<bool> {
+        self.missing_default
+    }
+
     fn matches(&self, source_use_map: &UseMap, target_use_map: &UseMap) -> Result<bool> {
         let target_value = target_use_map
             .get(&self.flag)
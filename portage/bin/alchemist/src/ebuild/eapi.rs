@@ -0,0 +1,288 @@
+// Copyright 2024 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! Validates ebuild metadata against the rules of the ebuild's EAPI.
+
+use anyhow::{bail, Result};
+
+use crate::{
+    bash::vars::BashVars,
+    dependency::{
+        package::{PackageBlock, PackageDependency, PackageDependencyAtom},
+        CompositeDependency, Dependency,
+    },
+};
+
+/// The newest EAPI supported by alchemist. All EAPIs from 0 to this are
+/// supported.
+const LATEST_EAPI: i32 = 8;
+
+/// A section of the PMS explaining a rule.
+struct PmsSection {
+    number: &'static str,
+    anchor: &'static str,
+}
+
+impl PmsSection {
+    const EAPI: Self = Self::new("7.3.1", "x1-630007.3.1");
+    const REQUIRED_USE: Self = Self::new("7.3.4", "x1-660007.3.4");
+    const DEPENDENCY_CLASSES: Self = Self::new("8.1", "x1-720008.1");
+    const BLOCK: Self = Self::new("8.3.2", "x1-810008.3.2");
+    const SLOT_DEPENDENCY: Self = Self::new("8.3.3", "x1-820008.3.3");
+    const USE_DEPENDENCY: Self = Self::new("8.3.4", "x1-830008.3.4");
+    const DEFINED_VARIABLES: Self = Self::new("11.1", "x1-10900011.1");
+
+    const fn new(number: &'static str, anchor: &'static str) -> Self {
+        Self { number, anchor }
+    }
+}
+
+impl std::fmt::Display for PmsSection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "PMS {}: https://projects.gentoo.org/pms/8/pms.html#{}",
+            self.number, self.anchor
+        )
+    }
+}
+
+/// Variables that an ebuild may set only in EAPIs supporting them.
+const EAPI_VARIABLES: &[(&str, i32, PmsSection)] = &[
+    ("REQUIRED_USE", 4, PmsSection::REQUIRED_USE),
+    ("BDEPEND", 7, PmsSection::DEPENDENCY_CLASSES),
+    ("IDEPEND", 8, PmsSection::DEPENDENCY_CLASSES),
+];
+
+/// Variables that ebuilds must not set or refer to since the EAPI.
+const BANNED_VARIABLES: &[(&str, i32)] = &[
+    ("PORTDIR", 7),
+    ("ECLASSDIR", 7),
+    ("DESTTREE", 7),
+    ("INSDESTTREE", 7),
+];
+
+const DEPENDENCY_VARIABLES: &[&str] = &["DEPEND", "RDEPEND", "PDEPEND", "BDEPEND", "IDEPEND"];
+
+/// Parses an EAPI value. An unset or empty EAPI means EAPI 0.
+pub fn parse_eapi(raw: Option<&str>) -> Result<i32> {
+    let raw = raw.unwrap_or_default();
+    if raw.is_empty() {
+        return Ok(0);
+    }
+    match raw.parse::<i32>() {
+        Ok(eapi) if (0..=LATEST_EAPI).contains(&eapi) && eapi.to_string() == raw => Ok(eapi),
+        _ => bail!(
+            "Unsupported EAPI \"{}\": only EAPI 0 to {} are supported (see {})",
+            raw,
+            LATEST_EAPI,
+            PmsSection::EAPI
+        ),
+    }
+}
+
+fn require_eapi(
+    eapi: i32,
+    min_eapi: i32,
+    feature: impl std::fmt::Display,
+    section: &PmsSection,
+) -> Result<()> {
+    if eapi < min_eapi {
+        bail!(
+            "{} requires EAPI {} or later, but the ebuild uses EAPI {} (see {})",
+            feature,
+            min_eapi,
+            eapi,
+            section
+        );
+    }
+    Ok(())
+}
+
+fn for_each_atom(
+    deps: &PackageDependency,
+    f: &mut impl FnMut(&PackageDependencyAtom) -> Result<()>,
+) -> Result<()> {
+    match deps {
+        Dependency::Leaf(atom) => f(atom),
+        Dependency::Composite(composite) => match &**composite {
+            CompositeDependency::AllOf { children }
+            | CompositeDependency::AnyOf { children }
+            | CompositeDependency::UseConditional { children, .. } => children
+                .iter()
+                .try_for_each(|child| for_each_atom(child, f)),
+            CompositeDependency::Constant { .. } => Ok(()),
+        },
+    }
+}
+
+fn validate_atom(eapi: i32, var_name: &str, atom: &PackageDependencyAtom) -> Result<()> {
+    if atom.block() == PackageBlock::Strong {
+        require_eapi(
+            eapi,
+            2,
+            format_args!("{}: strong blocker in {}", var_name, atom),
+            &PmsSection::BLOCK,
+        )?;
+    }
+
+    if let Some(slot) = atom.slot() {
+        require_eapi(
+            eapi,
+            1,
+            format_args!("{}: SLOT dependency in {}", var_name, atom),
+            &PmsSection::SLOT_DEPENDENCY,
+        )?;
+        if slot.slot().is_none() || slot.rebuild_on_slot_change() {
+            require_eapi(
+                eapi,
+                5,
+                format_args!("{}: slot operator in {}", var_name, atom),
+                &PmsSection::SLOT_DEPENDENCY,
+            )?;
+        }
+        if let Some((_, Some(_))) = slot.slot() {
+            require_eapi(
+                eapi,
+                5,
+                format_args!("{}: sub-SLOT dependency in {}", var_name, atom),
+                &PmsSection::SLOT_DEPENDENCY,
+            )?;
+        }
+    }
+
+    if !atom.uses().is_empty() {
+        require_eapi(
+            eapi,
+            2,
+            format_args!("{}: USE dependency in {}", var_name, atom),
+            &PmsSection::USE_DEPENDENCY,
+        )?;
+    }
+    if atom.uses().iter().any(|u| u.missing_default().is_some()) {
+        require_eapi(
+            eapi,
+            4,
+            format_args!("{}: USE dependency default (+)/(-) in {}", var_name, atom),
+            &PmsSection::USE_DEPENDENCY,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Checks that ebuild metadata only uses features supported by its EAPI.
+pub fn validate_metadata(vars: &BashVars) -> Result<()> {
+    let eapi = parse_eapi(vars.maybe_get_scalar("EAPI")?)?;
+
+    for (name, min_eapi, section) in EAPI_VARIABLES {
+        if !vars.get_scalar_or_default(name)?.trim().is_empty() {
+            require_eapi(eapi, *min_eapi, name, section)?;
+        }
+    }
+
+    for (name, banned_eapi) in BANNED_VARIABLES {
+        if eapi >= *banned_eapi && vars.hash_map().contains_key(*name) {
+            bail!(
+                "{} must not be set in EAPI {} or later (see {})",
+                name,
+                banned_eapi,
+                PmsSection::DEFINED_VARIABLES
+            );
+        }
+    }
+
+    for name in DEPENDENCY_VARIABLES {
+        // Syntax errors are reported when dependencies are analyzed.
+        let Ok(deps) = vars
+            .get_scalar_or_default(name)?
+            .parse::<PackageDependency>()
+        else {
+            continue;
+        };
+        for_each_atom(&deps, &mut |atom| validate_atom(eapi, name, atom))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::bash::vars::BashValue;
+
+    use super::*;
+
+    fn vars(values: &[(&str, &str)]) -> BashVars {
+        BashVars::new(HashMap::from_iter(values.iter().map(|(name, value)| {
+            ((*name).to_owned(), BashValue::Scalar((*value).to_owned()))
+        })))
+    }
+
+    #[test]
+    fn test_parse_eapi() -> Result<()> {
+        assert_eq!(parse_eapi(None)?, 0);
+        assert_eq!(parse_eapi(Some(""))?, 0);
+        assert_eq!(parse_eapi(Some("7"))?, 7);
+        assert!(parse_eapi(Some("9")).is_err());
+        assert!(parse_eapi(Some("07")).is_err());
+        assert!(parse_eapi(Some("5-progress")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_metadata() -> Result<()> {
+        validate_metadata(&vars(&[
+            ("EAPI", "8"),
+            ("DEPEND", "dev-libs/foo:= x? ( dev-libs/bar[baz(+)] )"),
+            ("BDEPEND", "!!dev-libs/baz"),
+            ("IDEPEND", "dev-libs/qux:0/1"),
+        ]))?;
+
+        for (values, error) in [
+            (
+                vec![("EAPI", "4"), ("RDEPEND", "x? ( dev-libs/foo:= )")],
+                "RDEPEND: slot operator in dev-libs/foo:= requires EAPI 5 or later, \
+                 but the ebuild uses EAPI 4 (see PMS 8.3.3: \
+                 https://projects.gentoo.org/pms/8/pms.html#x1-820008.3.3)",
+            ),
+            (
+                vec![("EAPI", "3"), ("DEPEND", "dev-libs/foo[bar(-)]")],
+                "DEPEND: USE dependency default (+)/(-) in dev-libs/foo[bar(-)] requires \
+                 EAPI 4 or later, but the ebuild uses EAPI 3 (see PMS 8.3.4: \
+                 https://projects.gentoo.org/pms/8/pms.html#x1-830008.3.4)",
+            ),
+            (
+                vec![("EAPI", "6"), ("BDEPEND", "dev-libs/foo")],
+                "BDEPEND requires EAPI 7 or later, but the ebuild uses EAPI 6 (see PMS 8.1: \
+                 https://projects.gentoo.org/pms/8/pms.html#x1-720008.1)",
+            ),
+            (
+                vec![("EAPI", "7"), ("IDEPEND", "dev-libs/foo")],
+                "IDEPEND requires EAPI 8 or later, but the ebuild uses EAPI 7 (see PMS 8.1: \
+                 https://projects.gentoo.org/pms/8/pms.html#x1-720008.1)",
+            ),
+            (
+                vec![("EAPI", "7"), ("PORTDIR", "/usr/portage")],
+                "PORTDIR must not be set in EAPI 7 or later (see PMS 11.1: \
+                 https://projects.gentoo.org/pms/8/pms.html#x1-10900011.1)",
+            ),
+            (
+                vec![("EAPI", "foo")],
+                "Unsupported EAPI \"foo\": only EAPI 0 to 8 are supported (see PMS 7.3.1: \
+                 https://projects.gentoo.org/pms/8/pms.html#x1-630007.3.1)",
+            ),
+        ] {
+            assert_eq!(
+                validate_metadata(&vars(&values)).unwrap_err().to_string(),
+                error
+            );
+        }
+
+        // Empty BDEPEND is always set by the ebuild prelude.
+        validate_metadata(&vars(&[("BDEPEND", "")]))?;
+        Ok(())
+    }
+}
@@ -3,14 +3,24 @@
 # Use of this source code is governed by a BSD-style license that can be
 # found in the LICENSE file.
 
+# This script runs as a long-lived worker that evaluates ebuilds one after
+# another. It reads requests from stdin, each of which consists of the
+# following NUL-terminated fields:
+#
+#   - the path to the ebuild
+#   - the eclass directories, separated by newlines
+#   - the number of environment variables N
+#   - N environment variables in the NAME=VALUE format
+#
+# Each ebuild is evaluated in a subshell so that nothing it defines leaks to
+# the next evaluation. The subshell writes the `set` output to stdout followed
+# by a NUL and the exit status terminated by a newline. Anything the ebuild
+# prints is sent to stderr followed by a NUL.
+#
 # Note: The variables are prefixed with the "__alchemist_" to reduce the risk
 # of colliding with definitions inside the ebuild and eclasses that are source'd
 # below.
 
-declare -a __alchemist_out_inherit_paths=()
-
-readarray -t __alchemist_eclass_dirs <<< "${__alchemist_in_eclass_dirs:?}"
-
 # TODO: Is it okay to enable extglob by default?
 shopt -s extglob
 
@@ -21,11 +31,14 @@ die() {
 
 inherit() {
   local names=("$@")
-  local name path
+  local name path start
   for name in "${names[@]}"; do
     path=$(__alchemist_find_eclass "${name}")
+    start="${EPOCHREALTIME/./}"
     __alchemist_source_eclass "${name}" "${path}"
     __alchemist_out_inherit_paths+=("${path}")
+    # Record the time spent sourcing the eclass in microseconds.
+    __alchemist_out_eclass_times+=("${name}=$(( ${EPOCHREALTIME/./} - start ))")
   done
 }
 
@@ -110,51 +123,215 @@ EXPORT_FUNCTIONS() {
 # that use it.
 export XORG_BASE_INDIVIDUAL_URI="https://www.x.org/releases/individual"
 
+# Version manipulation and comparison commands introduced in EAPI 7. They are
+# evaluated natively in bash rather than by running the alchemist multicall
+# binary because they're called many times in global scope of ebuilds.
+# They must behave the same as the `version` crate.
+# https://projects.gentoo.org/pms/8/pms.html#x1-15400012.3.14
+
+# Splits a version string into __alchemist_ver_comp, which contains separators
+# and components alternately, starting from separator 0.
+__alchemist_ver_split() {
+  local version="$1"
+  local separator component
+  __alchemist_ver_comp=()
+  while [[ -n "${version}" ]]; do
+    separator="${version%%[a-zA-Z0-9]*}"
+    version="${version:${#separator}}"
+    if [[ "${version}" == [0-9]* ]]; then
+      component="${version%%[^0-9]*}"
+    else
+      component="${version%%[^a-zA-Z]*}"
+    fi
+    version="${version:${#component}}"
+    __alchemist_ver_comp+=("${separator}" "${component}")
+  done
+}
+
+# Parses a range into __alchemist_ver_start and __alchemist_ver_end, capping
+# the end to the given maximum.
+__alchemist_ver_parse_range() {
+  local range="$1"
+  local max="$2"
+  case "${range}" in
+  +([0-9]))
+    __alchemist_ver_start="${range}"
+    __alchemist_ver_end="${range}"
+    ;;
+  +([0-9])-)
+    __alchemist_ver_start="${range%-}"
+    __alchemist_ver_end="${max}"
+    ;;
+  +([0-9])-+([0-9]))
+    __alchemist_ver_start="${range%-*}"
+    __alchemist_ver_end="${range#*-}"
+    if (( __alchemist_ver_end < __alchemist_ver_start )); then
+      die "${FUNCNAME[1]}: end of range must be >= start: ${range}"
+    fi
+    ;;
+  *) die "${FUNCNAME[1]}: invalid range ${range}";;
+  esac
+  if (( __alchemist_ver_end > max )); then
+    __alchemist_ver_end="${max}"
+  fi
+}
+
 ver_cut() {
   local range="$1"
-  local version="$2"
-  if [[ -z "${version}" ]]; then
+  local version="${2:-${PV:?}}"
+
+  local -a __alchemist_ver_comp
+  local __alchemist_ver_start __alchemist_ver_end
+  __alchemist_ver_split "${version}"
+  __alchemist_ver_parse_range "${range}" "$(( ${#__alchemist_ver_comp[@]} / 2 ))"
+
+  local offset=0
+  if (( __alchemist_ver_start > 0 )); then
+    offset=$(( __alchemist_ver_start * 2 - 1 ))
+  fi
+  local length=$(( __alchemist_ver_end * 2 - offset ))
+  if (( length > 0 )); then
+    local IFS=
+    echo "${__alchemist_ver_comp[*]:${offset}:${length}}"
+  else
+    echo
+  fi
+}
+
+ver_rs() {
+  local version
+  if (( $# % 2 == 1 )); then
+    version="${*: -1}"
+  else
     version="${PV:?}"
   fi
 
-  local begin end
-  case "${range}" in
-  +([0-9])) begin="${range}"; end="${range}";;
-  +([0-9])-) begin="${range%-}"; end=1000;;
-  +([0-9])-+([0-9])) begin="${range%-*}"; end="${range#*-}";;
-  *) die "ver_cut: invalid range ${range}";;
-  esac
+  local -a __alchemist_ver_comp
+  local __alchemist_ver_start __alchemist_ver_end
+  __alchemist_ver_split "${version}"
+  local max=$(( ${#__alchemist_ver_comp[@]} / 2 - 1 ))
 
-  local versions=('') separators=()
-  local head tail
-  while :; do
-    if [[ -z "${version}" ]]; then
-      break
+  local i
+  while (( $# >= 2 )); do
+    if (( max >= 0 )); then
+      __alchemist_ver_parse_range "$1" "${max}"
+      for (( i = __alchemist_ver_start; i <= __alchemist_ver_end; i++ )); do
+        if (( i == 0 )) && [[ -z "${__alchemist_ver_comp[0]}" ]]; then
+          continue
+        fi
+        __alchemist_ver_comp[i * 2]="$2"
+      done
     fi
-    tail="${version##*([^A-Za-z0-9])}"
-    head="${version:0:$(( ${#version} - ${#tail} ))}"
-    separators+=("${head}")
-    version="${tail}"
+    shift 2
+  done
+
+  local IFS=
+  echo "${__alchemist_ver_comp[*]}"
+}
 
-    if [[ -z "${version}" ]]; then
-      break
+# Compares two integers of arbitrary length. Returns 1 if lhs < rhs, 2 if
+# lhs == rhs and 3 if lhs > rhs.
+__alchemist_ver_compare_int() {
+  local lhs="$1" rhs="$2"
+  local diff=$(( ${#lhs} - ${#rhs} ))
+  if (( diff > 0 )); then
+    printf -v rhs "%0${diff}d%s" 0 "${rhs}"
+  elif (( diff < 0 )); then
+    printf -v lhs "%0$(( -diff ))d%s" 0 "${lhs}"
+  fi
+  [[ "${lhs}" > "${rhs}" ]] && return 3
+  [[ "${lhs}" < "${rhs}" ]] && return 1
+  return 2
+}
+
+# Compares two versions with the PMS algorithm. Returns 1 if lhs < rhs, 2 if
+# lhs == rhs and 3 if lhs > rhs.
+# https://projects.gentoo.org/pms/8/pms.html#x1-260003.3
+__alchemist_ver_compare() {
+  local LC_ALL=C
+  local re='^([0-9]+(\.[0-9]+)*)([a-z]?)((_(alpha|beta|pre|rc|p)[0-9]*)*)(-r[0-9]+)?$'
+  local an al as ar bn bl bs br a b ret
+
+  [[ "$1" =~ ${re} ]] || die "ver_test: invalid version: $1"
+  an="${BASH_REMATCH[1]}" al="${BASH_REMATCH[3]}"
+  as="${BASH_REMATCH[4]}" ar="${BASH_REMATCH[7]}"
+  [[ "$2" =~ ${re} ]] || die "ver_test: invalid version: $2"
+  bn="${BASH_REMATCH[1]}" bl="${BASH_REMATCH[3]}"
+  bs="${BASH_REMATCH[4]}" br="${BASH_REMATCH[7]}"
+
+  # Numeric components.
+  __alchemist_ver_compare_int "${an%%.*}" "${bn%%.*}"
+  ret=$?
+  (( ret != 2 )) && return "${ret}"
+  while [[ "${an}" == *.* && "${bn}" == *.* ]]; do
+    an="${an#*.}" bn="${bn#*.}"
+    a="${an%%.*}" b="${bn%%.*}"
+    if [[ "${a}" == 0* || "${b}" == 0* ]]; then
+      # Compare as strings without trailing zeros.
+      a="${a%%+(0)}" b="${b%%+(0)}"
+      [[ "${a}" > "${b}" ]] && return 3
+      [[ "${a}" < "${b}" ]] && return 1
+    else
+      __alchemist_ver_compare_int "${a}" "${b}"
+      ret=$?
+      (( ret != 2 )) && return "${ret}"
     fi
-    case "${version}" in
-    [A-Za-z]*) tail=${version##*([A-Za-z])};;
-    [0-9]*) tail=${version##*([0-9])};;
-    esac
-    head="${version:0:$(( ${#version} - ${#tail} ))}"
-    versions+=("${head}")
-    version="${tail}"
   done
-
-  local i
-  for (( i=begin; i<=end; i++ )); do
-    echo -n "${versions[${i}]}"
-    if [[ ${i} -lt ${end} ]]; then
-      echo -n "${separators[${i}]}"
+  [[ "${an}" == *.* ]] && return 3
+  [[ "${bn}" == *.* ]] && return 1
+
+  # Letter components.
+  [[ "${al}" > "${bl}" ]] && return 3
+  [[ "${al}" < "${bl}" ]] && return 1
+
+  # Suffixes.
+  as="${as#_}${as:+_}" bs="${bs#_}${bs:+_}"
+  while [[ -n "${as}" && -n "${bs}" ]]; do
+    a="${as%%_*}" b="${bs%%_*}"
+    if [[ "${a%%[0-9]*}" == "${b%%[0-9]*}" ]]; then
+      __alchemist_ver_compare_int "${a##*[a-z]}" "${b##*[a-z]}"
+      ret=$?
+      (( ret != 2 )) && return "${ret}"
+    else
+      [[ "${a%%[0-9]*}" == p ]] && return 3
+      [[ "${b%%[0-9]*}" == p ]] && return 1
+      # alpha < beta < pre < rc happens to be the alphabetical order.
+      [[ "${a}" > "${b}" ]] && return 3
+      return 1
     fi
+    as="${as#*_}" bs="${bs#*_}"
   done
+  if [[ -n "${as}" ]]; then
+    [[ "${as}" == p[_0-9]* ]] && return 3
+    return 1
+  elif [[ -n "${bs}" ]]; then
+    [[ "${bs}" == p[_0-9]* ]] && return 1
+    return 3
+  fi
+
+  # Revisions.
+  __alchemist_ver_compare_int "${ar#-r}" "${br#-r}"
+}
+
+ver_test() {
+  local lhs
+  if (( $# == 3 )); then
+    lhs="$1"
+    shift
+  else
+    lhs="${PVR}"
+  fi
+  (( $# == 2 )) || die "ver_test: Needs 2 or 3 arguments"
+  local op="$1" rhs="$2"
+  case "${op}" in
+  -eq|-ne|-lt|-le|-gt|-ge) ;;
+  *) die "ver_test: Unsupported operator: ${op}";;
+  esac
+
+  local ret
+  __alchemist_ver_compare "${lhs}" "${rhs}"
+  ret=$?
+  test "${ret}" "${op}" 2
 }
 
 __alchemist_find_eclass() {
@@ -221,40 +398,88 @@ __alchemist_source_eclass() {
   IDEPEND="${saved_IDEPEND}"
 }
 
-unset EAPI EBUILD ECLASS INHERITED
-# ShellCheck can't figure out that $EBUILD may be used in ebuilds.
-# shellcheck disable=SC2034
-EBUILD="${__alchemist_in_ebuild:?}"
-set -- "${__alchemist_in_ebuild:?}"
-
-# ShellCheck can't find the source, that is okay.
-# shellcheck disable=SC1090
-source "${__alchemist_in_ebuild:?}"
-
-# In EAPI=0/1/2/3, RDEPEND=DEPEND if RDEPEND is unset.
-# https://projects.gentoo.org/pms/8/pms.html#x1-690007.3.7
-case "${EAPI:-}" in
-0|1|2|3)
-  if [[ -z "${RDEPEND+x}" ]]; then
-    RDEPEND="${DEPEND}"
-  fi
-esac
-
-# Collect accumulated metadata keys in eclasses.
-# https://projects.gentoo.org/pms/8/pms.html#x1-10600010.2
-IUSE="${__alchemist_eclass_IUSE:+${__alchemist_eclass_IUSE} }${IUSE}"
-REQUIRED_USE="${__alchemist_eclass_REQUIRED_USE:+${__alchemist_eclass_REQUIRED_USE} }${REQUIRED_USE}"
-DEPEND="${__alchemist_eclass_DEPEND:+${__alchemist_eclass_DEPEND} }${DEPEND}"
-BDEPEND="${__alchemist_eclass_BDEPEND:+${__alchemist_eclass_BDEPEND} }${BDEPEND}"
-RDEPEND="${__alchemist_eclass_RDEPEND:+${__alchemist_eclass_RDEPEND} }${RDEPEND}"
-PDEPEND="${__alchemist_eclass_PDEPEND:+${__alchemist_eclass_PDEPEND} }${PDEPEND}"
-IDEPEND="${__alchemist_eclass_IDEPEND:+${__alchemist_eclass_IDEPEND} }${IDEPEND}"
-
-if [[ "$(type -t src_compile)" == "function" ]]; then
-  __alchemist_out_has_src_compile=1
-else
-  __alchemist_out_has_src_compile=0
-fi
-
-set -o posix
-set > "${__alchemist_in_output_vars:?}"
+__alchemist_worker_seq=0
+while IFS= read -r -d '' __alchemist_in_ebuild; do
+  __alchemist_worker_seq=$(( __alchemist_worker_seq + 1 ))
+  IFS= read -r -d '' __alchemist_in_eclass_dirs
+  IFS= read -r -d '' __alchemist_worker_env_count
+  __alchemist_worker_env=()
+  for (( __alchemist_worker_i = 0; __alchemist_worker_i < __alchemist_worker_env_count; __alchemist_worker_i++ )); do
+    IFS= read -r -d '' __alchemist_worker_var
+    __alchemist_worker_env+=("${__alchemist_worker_var}")
+  done
+
+  (
+    # Send the ebuild output to stderr and keep stdout for the `set` output.
+    exec 3>&1 1>&2 </dev/null
+
+    for __alchemist_worker_var in "${__alchemist_worker_env[@]}"; do
+      export "${__alchemist_worker_var?}"
+    done
+    unset __alchemist_worker_env __alchemist_worker_env_count \
+      __alchemist_worker_i __alchemist_worker_var
+
+    declare -a __alchemist_out_inherit_paths=()
+    declare -a __alchemist_out_eclass_times=()
+
+    readarray -t __alchemist_eclass_dirs <<< "${__alchemist_in_eclass_dirs:?}"
+
+    unset EAPI EBUILD ECLASS INHERITED
+    # ShellCheck can't figure out that $EBUILD may be used in ebuilds.
+    # shellcheck disable=SC2034
+    EBUILD="${__alchemist_in_ebuild:?}"
+    set -- "${__alchemist_in_ebuild:?}"
+
+    # ShellCheck can't find the source, that is okay.
+    # shellcheck disable=SC1090
+    source "${__alchemist_in_ebuild:?}"
+
+    # In EAPI=0/1/2/3, RDEPEND=DEPEND if RDEPEND is unset.
+    # https://projects.gentoo.org/pms/8/pms.html#x1-690007.3.7
+    case "${EAPI:-}" in
+    0|1|2|3)
+      if [[ -z "${RDEPEND+x}" ]]; then
+        RDEPEND="${DEPEND}"
+      fi
+    esac
+
+    # Collect accumulated metadata keys in eclasses.
+    # https://projects.gentoo.org/pms/8/pms.html#x1-10600010.2
+    #
+    # Like Portage, ignore keys set by eclasses if the ebuild's EAPI doesn't
+    # support them, so eclasses can set them for newer EAPIs only. The keys
+    # set by the ebuild itself are validated against its EAPI later.
+    IUSE="${__alchemist_eclass_IUSE:+${__alchemist_eclass_IUSE} }${IUSE}"
+    case "${EAPI:-}" in
+    ""|0|1|2|3) ;;
+    *)
+      REQUIRED_USE="${__alchemist_eclass_REQUIRED_USE:+${__alchemist_eclass_REQUIRED_USE} }${REQUIRED_USE}"
+    esac
+    DEPEND="${__alchemist_eclass_DEPEND:+${__alchemist_eclass_DEPEND} }${DEPEND}"
+    case "${EAPI:-}" in
+    ""|0|1|2|3|4|5|6) ;;
+    *)
+      BDEPEND="${__alchemist_eclass_BDEPEND:+${__alchemist_eclass_BDEPEND} }${BDEPEND}"
+    esac
+    RDEPEND="${__alchemist_eclass_RDEPEND:+${__alchemist_eclass_RDEPEND} }${RDEPEND}"
+    PDEPEND="${__alchemist_eclass_PDEPEND:+${__alchemist_eclass_PDEPEND} }${PDEPEND}"
+    case "${EAPI:-}" in
+    ""|0|1|2|3|4|5|6|7) ;;
+    *)
+      IDEPEND="${__alchemist_eclass_IDEPEND:+${__alchemist_eclass_IDEPEND} }${IDEPEND}"
+    esac
+
+    if [[ "$(type -t src_compile)" == "function" ]]; then
+      __alchemist_out_has_src_compile=1
+    else
+      __alchemist_out_has_src_compile=0
+    fi
+
+    set -o posix
+    set >&3
+  )
+  printf '\0%d\n' "$?"
+  # Terminate the ebuild output with the request number, so that the reader
+  # can tell NULs printed by the ebuild from the end of the output.
+  printf '\0%d\0' "${__alchemist_worker_seq}" >&2
+done
@@ -8,15 +8,19 @@ use crate::repository::{Repository, RepositorySetOperations, UnorderedRepository
 use anyhow::{anyhow, bail, Context, Result};
 use itertools::Itertools;
 use once_cell::sync::OnceCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::{
     ffi::OsStr,
-    io::{Read, Seek, SeekFrom, Write},
+    io::{BufRead, BufReader, Write},
+    os::unix::ffi::OsStrExt,
     path::{Path, PathBuf},
-    process::Command,
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    sync::mpsc::Receiver,
+    time::{Duration, Instant},
 };
+use tracing::instrument;
 use version::Version;
 
 use crate::{
@@ -24,78 +28,262 @@ use crate::{
     data::Vars,
 };
 
-fn run_ebuild<'a>(
-    ebuild_path: &Path,
-    env: &Vars,
-    eclass_dirs: impl IntoIterator<Item = &'a Path>,
-    tools_dir: &Path,
-) -> Result<BashVars> {
-    let mut script_file = tempfile::tempfile()?;
-    script_file.write_all(include_bytes!("ebuild_prelude.sh"))?;
-    script_file.seek(SeekFrom::Start(0))?;
-
-    let mut set_output_file = tempfile::NamedTempFile::new()?;
-
-    let output = Command::new("/bin/bash")
-        .stdin(script_file)
-        .current_dir("/")
-        .env_clear()
-        .envs(env)
-        .env("PATH", tools_dir.to_string_lossy().as_ref())
-        .env(
-            "__alchemist_in_ebuild",
-            ebuild_path.to_string_lossy().as_ref(),
-        )
-        .env(
-            "__alchemist_in_eclass_dirs",
+/// The exit status and the outputs of evaluating an ebuild in a [`BashWorker`].
+struct WorkerResponse {
+    status: i32,
+    set_output: String,
+    output: String,
+}
+
+/// A long-lived bash process that evaluates ebuilds sent over a pipe.
+///
+/// See `ebuild_prelude.sh` for the protocol. Each ebuild is evaluated in a
+/// forked subshell, so evaluations are isolated from each other while the
+/// cost of starting bash and parsing the prelude is paid only once.
+#[derive(Debug)]
+struct BashWorker {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    /// Receives NUL-delimited chunks of stderr read by a background thread.
+    /// Stderr must be drained concurrently as an ebuild may print more than
+    /// the pipe capacity before the worker writes to stdout.
+    stderr: Receiver<Vec<u8>>,
+    /// The number of requests sent to the worker. The worker terminates the
+    /// output of each request with its number.
+    requests: u64,
+}
+
+impl BashWorker {
+    fn spawn(tools_dir: &Path) -> Result<Self> {
+        let mut child = Command::new("/bin/bash")
+            .arg("--noprofile")
+            .arg("--norc")
+            .arg("-c")
+            .arg(include_str!("ebuild_prelude.sh"))
+            .current_dir("/")
+            .env_clear()
+            .env("PATH", tools_dir.to_string_lossy().as_ref())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Failed to spawn bash for ebuild metadata evaluation")?;
+
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let mut stderr = BufReader::new(child.stderr.take().unwrap());
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn(move || loop {
+            let mut chunk = Vec::new();
+            match stderr.read_until(b'\0', &mut chunk) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {
+                    if chunk.last() == Some(&b'\0') {
+                        chunk.pop();
+                    }
+                    if sender.send(chunk).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            child,
+            stdin,
+            stdout,
+            stderr: receiver,
+            requests: 0,
+        })
+    }
+
+    fn evaluate<'a>(
+        &mut self,
+        ebuild_path: &Path,
+        env: &Vars,
+        eclass_dirs: impl IntoIterator<Item = &'a Path>,
+    ) -> Result<WorkerResponse> {
+        let mut request = Vec::new();
+        let mut push_field = |field: &[u8]| -> Result<()> {
+            if field.contains(&b'\0') {
+                bail!(
+                    "NUL in a request to bash: {:?}",
+                    String::from_utf8_lossy(field)
+                );
+            }
+            request.extend_from_slice(field);
+            request.push(b'\0');
+            Ok(())
+        };
+        push_field(ebuild_path.as_os_str().as_bytes())?;
+        push_field(
             eclass_dirs
                 .into_iter()
                 .map(|path| format!("{}\n", path.to_string_lossy()))
-                .join(""),
-        )
-        .env(
-            "__alchemist_in_output_vars",
-            set_output_file.as_ref().to_string_lossy().as_ref(),
-        )
-        .output()
-        .context("Failed to spawn bash for ebuild metadata evaluation")?;
-
-    if !output.status.success() {
-        bail!(
-            "ebuild failed to evaluate {}: {}\nstdout: {}\nstderr: {}",
-            ebuild_path.display(),
-            &output.status,
-            String::from_utf8_lossy(&output.stdout),
-            String::from_utf8_lossy(&output.stderr)
-        );
-    }
-    if !output.stdout.is_empty() || !output.stderr.is_empty() {
-        bail!(
-            "ebuild printed errors to stdout/stderr\nstdout: {}\nstderr: {}",
-            String::from_utf8_lossy(&output.stdout),
-            String::from_utf8_lossy(&output.stderr)
-        );
+                .join("")
+                .as_bytes(),
+        )?;
+        push_field(env.len().to_string().as_bytes())?;
+        for (name, value) in env {
+            push_field(format!("{name}={value}").as_bytes())?;
+        }
+        self.stdin.write_all(&request)?;
+        self.stdin.flush()?;
+        self.requests += 1;
+
+        let mut set_output = Vec::new();
+        self.stdout.read_until(b'\0', &mut set_output)?;
+        if set_output.pop() != Some(b'\0') {
+            bail!("bash worker exited unexpectedly");
+        }
+        let mut status = String::new();
+        self.stdout.read_line(&mut status)?;
+        let status: i32 = status
+            .trim_end()
+            .parse()
+            .with_context(|| format!("Corrupted status from bash worker: {status:?}"))?;
+        // The ebuild may print NULs itself, so read chunks up to the request
+        // number rather than a single chunk.
+        let terminator = self.requests.to_string().into_bytes();
+        let mut chunks = Vec::new();
+        loop {
+            let chunk = self
+                .stderr
+                .recv()
+                .context("bash worker exited unexpectedly")?;
+            if chunk == terminator {
+                break;
+            }
+            chunks.push(chunk);
+        }
+        let output = chunks.join(&b'\0');
+
+        Ok(WorkerResponse {
+            status,
+            set_output: String::from_utf8(set_output)?,
+            output: String::from_utf8_lossy(&output).into_owned(),
+        })
     }
+}
 
-    let mut set_output = String::new();
-    set_output_file
-        .as_file_mut()
-        .read_to_string(&mut set_output)?;
-    parse_set_output(&set_output)
+impl Drop for BashWorker {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
 }
 
 #[derive(Debug)]
 pub(super) struct EBuildEvaluator {
     tools_dir: PathBuf,
+    /// Idle workers. A worker is taken out while it evaluates an ebuild, so
+    /// the pool grows up to the number of concurrent evaluations.
+    workers: Mutex<Vec<BashWorker>>,
+    /// Timings of ebuild evaluations, recorded only if enabled since they
+    /// accumulate for the lifetime of the evaluator.
+    timings: Option<Mutex<Vec<EBuildTiming>>>,
+}
+
+/// The wall-clock time spent evaluating an ebuild.
+#[derive(Clone, Debug)]
+pub struct EBuildTiming {
+    pub ebuild_path: PathBuf,
+    pub elapsed: Duration,
+    /// The time spent sourcing each eclass in the order they were inherited,
+    /// including the time spent sourcing the eclasses they inherit.
+    /// Empty if the ebuild failed to evaluate.
+    pub eclasses: Vec<(String, Duration)>,
+}
+
+/// Parses the eclass timings recorded by `inherit` in `ebuild_prelude.sh`.
+///
+/// Timings are diagnostics only, so malformed entries are reported and
+/// skipped rather than failing the evaluation.
+fn parse_eclass_times(vars: &BashVars) -> Vec<(String, Duration)> {
+    let entries = match vars.get_indexed_array("__alchemist_out_eclass_times") {
+        Ok(entries) => entries,
+        Err(err) => {
+            eprintln!("WARNING: Failed to read eclass timings: {err:#}");
+            return Vec::new();
+        }
+    };
+    entries
+        .iter()
+        .filter_map(|entry| {
+            let parsed = entry
+                .split_once('=')
+                .and_then(|(name, micros)| Some((name, micros.parse::<u64>().ok()?)));
+            match parsed {
+                Some((name, micros)) => Some((name.to_owned(), Duration::from_micros(micros))),
+                None => {
+                    eprintln!("WARNING: Ignoring invalid eclass timing: {entry}");
+                    None
+                }
+            }
+        })
+        .collect()
 }
 
 impl EBuildEvaluator {
     pub(super) fn new(tools_dir: &Path) -> Self {
         Self {
             tools_dir: tools_dir.to_owned(),
+            workers: Default::default(),
+            timings: None,
+        }
+    }
+
+    /// Enables or disables recording timings of ebuild evaluations.
+    pub(super) fn set_record_timings(&mut self, enabled: bool) {
+        self.timings = enabled.then(Default::default);
+    }
+
+    /// Returns the timings of ebuild evaluations recorded since the last call,
+    /// and forgets them. Returns an empty list if timings are not recorded.
+    pub(super) fn take_timings(&self) -> Vec<EBuildTiming> {
+        self.timings
+            .as_ref()
+            .map(|timings| std::mem::take(&mut *timings.lock().unwrap()))
+            .unwrap_or_default()
+    }
+
+    fn run_ebuild<'a>(
+        &self,
+        ebuild_path: &Path,
+        env: &Vars,
+        eclass_dirs: impl IntoIterator<Item = &'a Path>,
+    ) -> Result<BashVars> {
+        let worker = self.workers.lock().unwrap().pop();
+        let mut worker = match worker {
+            Some(worker) => worker,
+            None => BashWorker::spawn(&self.tools_dir)?,
+        };
+
+        // Discard the worker on protocol errors as its state is unknown.
+        let response = worker.evaluate(ebuild_path, env, eclass_dirs)?;
+        self.workers.lock().unwrap().push(worker);
+
+        if response.status != 0 {
+            bail!(
+                "ebuild failed to evaluate {}: exit status: {}\noutput: {}",
+                ebuild_path.display(),
+                response.status,
+                response.output
+            );
         }
+        if !response.output.is_empty() {
+            bail!(
+                "ebuild printed errors to stdout/stderr\noutput: {}",
+                response.output
+            );
+        }
+
+        parse_set_output(&response.set_output)
     }
 
+    #[instrument(skip_all, fields(ebuild = %ebuild_path.display()))]
     pub(super) fn evaluate_metadata(
         &self,
         ebuild_path: &Path,
@@ -118,7 +306,19 @@ impl EBuildEvaluator {
             category_name: path_info.category_name,
             version: path_info.version,
         };
-        match run_ebuild(ebuild_path, &env, repo.eclass_dirs(), &self.tools_dir) {
+        let start = Instant::now();
+        let result = self.run_ebuild(ebuild_path, &env, repo.eclass_dirs());
+        if let Some(timings) = &self.timings {
+            timings.lock().unwrap().push(EBuildTiming {
+                ebuild_path: ebuild_path.to_path_buf(),
+                elapsed: start.elapsed(),
+                eclasses: match &result {
+                    Ok(vars) => parse_eclass_times(vars),
+                    Err(_) => vec![],
+                },
+            });
+        }
+        match result {
             Ok(vars) => Ok(MaybeEBuildMetadata::Ok(Arc::new(EBuildMetadata {
                 basic_data,
                 vars,
@@ -338,12 +538,74 @@ impl CachedEBuildEvaluator {
         })?;
         Ok(details.clone())
     }
+
+    /// Makes the evaluator record the time spent evaluating each ebuild.
+    ///
+    /// Timings are not recorded by default since they accumulate until
+    /// retrieved with [`Self::take_timings`].
+    pub fn with_timings(mut self, enabled: bool) -> Self {
+        self.evaluator.set_record_timings(enabled);
+        self
+    }
+
+    /// Returns the timings of ebuild evaluations recorded since the last call,
+    /// including evaluations whose results were dropped by
+    /// [`Self::invalidate`].
+    pub fn take_timings(&self) -> Vec<EBuildTiming> {
+        self.evaluator.take_timings()
+    }
+
+    /// Drops cached metadata of ebuilds affected by changes to `changed_paths`.
+    ///
+    /// An ebuild is affected if it is changed itself or it inherits a changed
+    /// eclass. Ebuilds that failed to evaluate are considered affected by any
+    /// eclass change since the eclasses they inherit are unknown.
+    ///
+    /// Returns the paths of the ebuilds whose metadata was dropped.
+    pub fn invalidate(&self, changed_paths: &HashSet<PathBuf>) -> Vec<PathBuf> {
+        let eclass_changed = changed_paths
+            .iter()
+            .any(|path| path.extension() == Some(OsStr::new("eclass")));
+
+        let mut cache_guard = self.cache.lock().unwrap();
+        let invalidated = cache_guard
+            .iter()
+            .filter(|(ebuild_path, once_cell)| {
+                if changed_paths.contains(*ebuild_path) {
+                    return true;
+                }
+                if !eclass_changed {
+                    return false;
+                }
+                match once_cell.get() {
+                    Some(MaybeEBuildMetadata::Ok(metadata)) => match metadata
+                        .vars
+                        .get_indexed_array("__alchemist_out_inherit_paths")
+                    {
+                        Ok(inherit_paths) => inherit_paths
+                            .iter()
+                            .any(|path| changed_paths.contains(Path::new(path))),
+                        Err(_) => true,
+                    },
+                    Some(MaybeEBuildMetadata::Err(_)) => true,
+                    None => false,
+                }
+            })
+            .map(|(ebuild_path, _)| ebuild_path.clone())
+            .collect_vec();
+        for ebuild_path in &invalidated {
+            cache_guard.remove(ebuild_path);
+        }
+        invalidated
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use tempfile::TempDir;
 
+    use crate::bash::vars::BashValue;
+
     use super::*;
 
     /// Ensures [`EBuildEvaluator`] successfully evaluates variables of a minimal ebuild.
@@ -419,7 +681,7 @@ ARRAY=(hello, "${WORLD}!")
         std::fs::write(
             &ebuild_path,
             r#"
-EAPI=7
+EAPI=8
 SLOT=0
 KEYWORDS="*"
 
@@ -510,6 +772,215 @@ NOT_INCREMENTAL="not_incremental2"
         Ok(())
     }
 
+    /// Ensures [`EBuildEvaluator`] ignores incremental variables set by
+    /// eclasses if the ebuild's EAPI doesn't support them.
+    #[test]
+    fn test_evaluate_incremental_vars_unsupported_by_eapi() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let temp_dir = temp_dir.path();
+
+        let ebuild_path = temp_dir.join("sys-apps/hello/hello-1.2.3.ebuild");
+        std::fs::create_dir_all(ebuild_path.parent().unwrap())?;
+        std::fs::write(
+            &ebuild_path,
+            r#"
+EAPI=3
+SLOT=0
+KEYWORDS="*"
+
+inherit aaa
+
+DEPEND="depend0"
+BDEPEND="bdepend0"
+"#,
+        )?;
+        let eclass_dir = temp_dir.join("eclass");
+        std::fs::create_dir_all(&eclass_dir)?;
+        std::fs::write(
+            eclass_dir.join("aaa.eclass"),
+            r#"
+REQUIRED_USE="requse1"
+DEPEND="depend1"
+BDEPEND="bdepend1"
+IDEPEND="idepend1"
+"#,
+        )?;
+
+        let evaluator = EBuildEvaluator::new(&temp_dir.join("tools"));
+        let repo = Repository::new_no_parents("test", temp_dir);
+
+        let metadata = evaluator.evaluate_metadata(&ebuild_path, &repo)?;
+
+        let metadata = match metadata {
+            MaybeEBuildMetadata::Ok(metadata) => metadata,
+            MaybeEBuildMetadata::Err(error) => panic!("Failed to evaluate metadata: {error:?}"),
+        };
+
+        let vars = &metadata.vars;
+        assert_eq!(vars.get_scalar_or_default("REQUIRED_USE")?, "");
+        assert_eq!(vars.get_scalar("DEPEND").unwrap(), "depend1 depend0");
+        // BDEPEND set by the ebuild itself is kept so that it can be rejected.
+        assert_eq!(vars.get_scalar("BDEPEND").unwrap(), "bdepend0");
+        assert_eq!(vars.get_scalar_or_default("IDEPEND")?, "");
+
+        Ok(())
+    }
+
+    /// Ensures [`EBuildEvaluator`] records the time spent evaluating ebuilds
+    /// and sourcing eclasses.
+    #[test]
+    fn test_evaluate_timings() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let temp_dir = temp_dir.path();
+
+        let ebuild_path = temp_dir.join("sys-apps/hello/hello-1.2.3.ebuild");
+        std::fs::create_dir_all(ebuild_path.parent().unwrap())?;
+        std::fs::write(&ebuild_path, "EAPI=7\nSLOT=0\ninherit aaa\n")?;
+        let failure_path = temp_dir.join("sys-apps/failure/failure-1.0.ebuild");
+        std::fs::create_dir_all(failure_path.parent().unwrap())?;
+        std::fs::write(&failure_path, "EAPI=7\nSLOT=0\ninherit aaa\ndie\n")?;
+        let eclass_dir = temp_dir.join("eclass");
+        std::fs::create_dir_all(&eclass_dir)?;
+        std::fs::write(eclass_dir.join("aaa.eclass"), "inherit bbb\n")?;
+        std::fs::write(eclass_dir.join("bbb.eclass"), "")?;
+
+        let mut evaluator = EBuildEvaluator::new(&temp_dir.join("tools"));
+        let repo = Repository::new_no_parents("test", temp_dir);
+
+        // Timings are not recorded by default.
+        evaluator.evaluate_metadata(&ebuild_path, &repo)?;
+        assert!(evaluator.take_timings().is_empty());
+
+        evaluator.set_record_timings(true);
+        evaluator.evaluate_metadata(&ebuild_path, &repo)?;
+        evaluator.evaluate_metadata(&failure_path, &repo)?;
+
+        let timings = evaluator.take_timings();
+        assert_eq!(
+            timings
+                .iter()
+                .map(|timing| timing.ebuild_path.as_path())
+                .collect_vec(),
+            vec![ebuild_path.as_path(), failure_path.as_path()]
+        );
+
+        // Nested eclasses finish sourcing first.
+        let eclasses = &timings[0].eclasses;
+        assert_eq!(
+            eclasses.iter().map(|(name, _)| name.as_str()).collect_vec(),
+            vec!["bbb", "aaa"]
+        );
+        assert!(eclasses[1].1 >= eclasses[0].1);
+        assert!(timings[0].elapsed >= eclasses[1].1);
+
+        assert!(timings[1].eclasses.is_empty());
+
+        // Timings are forgotten once taken.
+        assert!(evaluator.take_timings().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_eclass_times_skips_invalid_entries() {
+        let vars = BashVars::new(HashMap::from([(
+            "__alchemist_out_eclass_times".to_owned(),
+            BashValue::IndexedArray(vec![
+                "aaa=10".to_owned(),
+                "bbb".to_owned(),
+                "ccc=ten".to_owned(),
+                "ddd=20".to_owned(),
+            ]),
+        )]));
+
+        assert_eq!(
+            parse_eclass_times(&vars),
+            vec![
+                ("aaa".to_owned(), Duration::from_micros(10)),
+                ("ddd".to_owned(), Duration::from_micros(20)),
+            ]
+        );
+    }
+
+    /// Ensures the version helpers evaluated natively in bash agree with the
+    /// `version` crate.
+    #[test]
+    fn test_evaluate_version_helpers() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let temp_dir = temp_dir.path();
+
+        let versions = [
+            "1.2.3",
+            "2 Ab 9 s",
+            "A.4.",
+            ".11.2.",
+            "1.2b_alpha4",
+            "1.2.3.4.5.6",
+        ];
+        let ranges = ["0", "0-1", "1", "1-", "1-2", "2", "2-3", "3", "3-", "4-5"];
+        let comparisons = [
+            ("1.2.3", "1.2.3"),
+            ("1.2.3", "1.2.3-r0"),
+            ("1.2", "1.2.0"),
+            ("1.01", "1.010"),
+            ("1.1", "1.01"),
+            ("1.2a", "1.2"),
+            ("1.2_alpha", "1.2_beta1"),
+            ("1.2_rc2", "1.2_p"),
+            ("1.2_pre", "1.2"),
+            ("1.2_p1_alpha", "1.2_p1"),
+            ("1.2-r2", "1.2-r10"),
+            ("12345678901234567890", "12345678901234567891"),
+        ];
+
+        let mut script = String::from("EAPI=7\nSLOT=0\nKEYWORDS=\"*\"\nRESULTS=(\n");
+        let mut expected = Vec::new();
+        for version in versions {
+            for range in ranges {
+                script.push_str(&format!("\"$(ver_cut {range} '{version}')\"\n"));
+                expected.push(version::ver_cut(range, version)?);
+                script.push_str(&format!("\"$(ver_rs {range} '#' '{version}')\"\n"));
+                expected.push(version::ver_rs(&[(range, "#")], version)?);
+            }
+        }
+        for (lhs, rhs) in comparisons {
+            for (a, b) in [(lhs, rhs), (rhs, lhs)] {
+                for op in ["-eq", "-ne", "-lt", "-le", "-gt", "-ge"] {
+                    script.push_str(&format!("\"$(ver_test {a} {op} {b} && echo 1)\"\n"));
+                    expected.push(if version::ver_test(a, op, b)? {
+                        "1".to_owned()
+                    } else {
+                        "".to_owned()
+                    });
+                }
+            }
+        }
+        script.push_str(")\nDEFAULT_CUT=\"$(ver_cut 2-)\"\nDEFAULT_RS=\"$(ver_rs 1 _)\"\n");
+        script.push_str("DEFAULT_TEST=\"$(ver_test -gt 1.2.3 && echo 1)\"\n");
+
+        let ebuild_path = temp_dir.join("sys-apps/hello/hello-1.2.3_p4-r5.ebuild");
+        std::fs::create_dir_all(ebuild_path.parent().unwrap())?;
+        std::fs::write(&ebuild_path, script)?;
+
+        // The tools directory doesn't exist, so the helpers must be evaluated
+        // without running external commands.
+        let evaluator = EBuildEvaluator::new(&temp_dir.join("tools"));
+        let repo = Repository::new_no_parents("test", temp_dir);
+
+        let metadata = match evaluator.evaluate_metadata(&ebuild_path, &repo)? {
+            MaybeEBuildMetadata::Ok(metadata) => metadata,
+            MaybeEBuildMetadata::Err(error) => panic!("Failed to evaluate metadata: {error:?}"),
+        };
+
+        let vars = &metadata.vars;
+        assert_eq!(vars.get_indexed_array("RESULTS")?, expected);
+        assert_eq!(vars.get_scalar("DEFAULT_CUT")?, "2.3_p4");
+        assert_eq!(vars.get_scalar("DEFAULT_RS")?, "1_2.3_p4");
+        assert_eq!(vars.get_scalar("DEFAULT_TEST")?, "1");
+
+        Ok(())
+    }
+
     /// Ensures [`EBuildEvaluator`] correctly handles evaluation errors.
     #[test]
     fn test_evaluate_die() -> Result<()> {
@@ -546,6 +1017,150 @@ die "failed failed failed"
         Ok(())
     }
 
+    /// Ensures [`EBuildEvaluator`] reuses a bash worker without leaking state
+    /// from one ebuild to the next.
+    #[test]
+    fn test_evaluate_isolation() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let temp_dir = temp_dir.path();
+
+        let dirty_path = temp_dir.join("sys-apps/dirty/dirty-1.0.ebuild");
+        let clean_path = temp_dir.join("sys-apps/clean/clean-1.0.ebuild");
+        std::fs::create_dir_all(dirty_path.parent().unwrap())?;
+        std::fs::create_dir_all(clean_path.parent().unwrap())?;
+        std::fs::write(
+            &dirty_path,
+            r#"
+EAPI=7
+SLOT=0
+KEYWORDS="*"
+IUSE="dirty"
+LEAKED=1
+export LEAKED_EXPORT=1
+leaked_fn() { :; }
+cd /tmp || die
+WORKER_PID=$$
+"#,
+        )?;
+        std::fs::write(
+            &clean_path,
+            r#"
+EAPI=7
+SLOT=0
+KEYWORDS="*"
+HAS_LEAKED="${LEAKED-unset}${LEAKED_EXPORT-unset}"
+HAS_FN="$(type -t leaked_fn)"
+CWD="${PWD}"
+WORKER_PID=$$
+"#,
+        )?;
+
+        let evaluator = EBuildEvaluator::new(&temp_dir.join("tools"));
+        let repo = Repository::new_no_parents("test", temp_dir);
+
+        let evaluate = |path: &Path| -> Result<Arc<EBuildMetadata>> {
+            match evaluator.evaluate_metadata(path, &repo)? {
+                MaybeEBuildMetadata::Ok(metadata) => Ok(metadata),
+                MaybeEBuildMetadata::Err(error) => bail!("Failed to evaluate: {error:?}"),
+            }
+        };
+        let dirty = evaluate(&dirty_path)?;
+        let clean = evaluate(&clean_path)?;
+
+        assert_eq!(
+            dirty.vars.get_scalar("WORKER_PID")?,
+            clean.vars.get_scalar("WORKER_PID")?,
+            "the worker should be reused"
+        );
+        assert_eq!(clean.vars.get_scalar("HAS_LEAKED")?, "unsetunset");
+        assert_eq!(clean.vars.get_scalar("HAS_FN")?, "");
+        assert_eq!(clean.vars.get_scalar("CWD")?, "/");
+        assert_eq!(clean.vars.get_scalar("IUSE")?, "");
+        assert_eq!(clean.vars.get_scalar("PN")?, "clean");
+
+        Ok(())
+    }
+
+    /// Ensures [`EBuildEvaluator`] handles ebuilds printing more than the pipe
+    /// capacity.
+    #[test]
+    fn test_evaluate_large_output() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let temp_dir = temp_dir.path();
+
+        let ebuild_path = temp_dir.join("sys-apps/hello/hello-1.2.3.ebuild");
+        std::fs::create_dir_all(ebuild_path.parent().unwrap())?;
+        std::fs::write(
+            &ebuild_path,
+            r#"
+EAPI=7
+SLOT=0
+KEYWORDS="*"
+for (( i = 0; i < 10000; i++ )); do
+  ewarn "this is a very long warning line to fill the pipe buffer quickly"
+done
+"#,
+        )?;
+
+        let evaluator = EBuildEvaluator::new(&temp_dir.join("tools"));
+        let repo = Repository::new_no_parents("test", temp_dir);
+
+        for _ in 0..2 {
+            match evaluator.evaluate_metadata(&ebuild_path, &repo)? {
+                MaybeEBuildMetadata::Err(error) if error.error.contains("very long warning") => {}
+                metadata => panic!("Unexpected return value from evaluate_metadata: {metadata:?}"),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Ensures [`EBuildEvaluator`] keeps a bash worker in sync when an ebuild
+    /// prints NULs.
+    #[test]
+    fn test_evaluate_nul_output() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let temp_dir = temp_dir.path();
+
+        let nul_ebuild_path = temp_dir.join("sys-apps/hello/hello-1.2.3.ebuild");
+        std::fs::create_dir_all(nul_ebuild_path.parent().unwrap())?;
+        std::fs::write(
+            &nul_ebuild_path,
+            r#"
+EAPI=7
+SLOT=0
+KEYWORDS="*"
+printf 'before\0after\0\0' >&2
+"#,
+        )?;
+
+        let ok_ebuild_path = temp_dir.join("sys-apps/hello/hello-1.2.4.ebuild");
+        std::fs::write(
+            &ok_ebuild_path,
+            r#"
+EAPI=7
+SLOT=0
+KEYWORDS="*"
+"#,
+        )?;
+
+        let evaluator = EBuildEvaluator::new(&temp_dir.join("tools"));
+        let repo = Repository::new_no_parents("test", temp_dir);
+
+        for _ in 0..2 {
+            match evaluator.evaluate_metadata(&nul_ebuild_path, &repo)? {
+                MaybeEBuildMetadata::Err(error) if error.error.contains("before\0after\0\0") => {}
+                metadata => panic!("Unexpected return value from evaluate_metadata: {metadata:?}"),
+            }
+            match evaluator.evaluate_metadata(&ok_ebuild_path, &repo)? {
+                MaybeEBuildMetadata::Ok(_) => {}
+                metadata => panic!("Unexpected return value from evaluate_metadata: {metadata:?}"),
+            }
+        }
+
+        Ok(())
+    }
+
     /// Ensures [`EBuildEvaluator`] correctly sets up the ebuild environment.
     #[test]
     fn test_evaluate_ebuild_env() -> Result<()> {
@@ -623,4 +1238,56 @@ assert_var PVR "1.2.3-r99"
 
         Ok(())
     }
+
+    /// Ensures [`CachedEBuildEvaluator::invalidate`] drops ebuilds affected by changed files.
+    #[test]
+    fn test_cached_evaluator_invalidate() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let temp_dir = temp_dir.path();
+
+        let eclass_dir = temp_dir.join("eclass");
+        std::fs::create_dir_all(&eclass_dir)?;
+        let eclass_path = eclass_dir.join("aaa.eclass");
+        std::fs::write(&eclass_path, "DESCRIPTION=old\n")?;
+
+        let inheriting_path = temp_dir.join("sys-apps/hello/hello-1.2.3.ebuild");
+        let standalone_path = temp_dir.join("sys-apps/world/world-1.2.3.ebuild");
+        for (ebuild_path, content) in [
+            (&inheriting_path, "EAPI=7\nSLOT=0\ninherit aaa\n"),
+            (&standalone_path, "EAPI=7\nSLOT=0\nDESCRIPTION=standalone\n"),
+        ] {
+            std::fs::create_dir_all(ebuild_path.parent().unwrap())?;
+            std::fs::write(ebuild_path, content)?;
+        }
+
+        let evaluator = CachedEBuildEvaluator::new(
+            [Repository::new_no_parents("test", temp_dir)]
+                .into_iter()
+                .collect(),
+            &temp_dir.join("tools"),
+        );
+        let description = |ebuild_path: &Path| -> Result<String> {
+            match evaluator.evaluate_metadata(ebuild_path)? {
+                MaybeEBuildMetadata::Ok(metadata) => {
+                    Ok(metadata.vars.get_scalar("DESCRIPTION")?.to_owned())
+                }
+                MaybeEBuildMetadata::Err(error) => bail!("{}", error.error),
+            }
+        };
+
+        assert_eq!(description(&inheriting_path)?, "old");
+        assert_eq!(description(&standalone_path)?, "standalone");
+
+        std::fs::write(&eclass_path, "DESCRIPTION=new\n")?;
+
+        // The cached metadata is used until invalidated.
+        assert_eq!(description(&inheriting_path)?, "old");
+
+        let invalidated = evaluator.invalidate(&HashSet::from([eclass_path]));
+        assert_eq!(invalidated, vec![inheriting_path.clone()]);
+
+        assert_eq!(description(&inheriting_path)?, "new");
+
+        Ok(())
+    }
 }
@@ -2,6 +2,7 @@
 // Use of this source code is governed by a BSD-style license that can be
 // found in the LICENSE file.
 
+mod eapi;
 pub mod metadata;
 
 use anyhow::{bail, Context, Result};
@@ -258,6 +259,15 @@ impl BazelSpecificMetadata {
         Ok(merged_metadata)
     }
 
+    /// Checks that a TOML metadata file can be parsed.
+    pub fn validate_file(config_path: &Path) -> Result<()> {
+        let toml_content = std::fs::read_to_string(config_path)
+            .with_context(|| format!("Failed to read {}", config_path.display()))?;
+        toml::from_str::<TomlMetadata>(&toml_content)
+            .with_context(|| format!("Failed to parse {}", config_path.display()))?;
+        Ok(())
+    }
+
     fn merge(&mut self, other: TomlMetadata) {
         if let Some(other) = other.bazel {
             if let Some(extra_sources) = other.extra_sources {
@@ -291,8 +301,7 @@ pub struct PackageDetails {
 impl PackageDetails {
     /// EAPI is technically a string, but working with an integer is easier.
     fn eapi(&self) -> Result<i32> {
-        let eapi = self.metadata.vars.get_scalar("EAPI")?;
-        eapi.parse::<i32>().with_context(|| format!("EAPI: {eapi}"))
+        eapi::parse_eapi(self.metadata.vars.maybe_get_scalar("EAPI")?)
     }
 
     pub fn supports_bdepend(&self) -> bool {
@@ -303,15 +312,6 @@ impl PackageDetails {
 
         eapi >= 7
     }
-
-    pub fn supports_idepend(&self) -> bool {
-        let eapi = match self.eapi() {
-            Ok(val) => val,
-            Err(_) => return false,
-        };
-
-        eapi >= 8
-    }
 }
 
 impl PackageDetails {
@@ -440,6 +440,8 @@ impl PackageLoader {
             }
         };
 
+        eapi::validate_metadata(&metadata.vars)?;
+
         let slot = Slot::<String>::new(metadata.vars.get_scalar("SLOT")?);
 
         let package = PackageRef {
@@ -572,6 +574,15 @@ impl CachedPackageLoader {
         let details = once_cell.get_or_try_init(|| self.loader.load_package(ebuild_path))?;
         Ok(details.clone())
     }
+
+    /// Drops cached details of the specified ebuilds so that they are loaded
+    /// again on the next access.
+    pub fn invalidate(&self, ebuild_paths: &[PathBuf]) {
+        let mut cache_guard = self.cache.lock().unwrap();
+        for ebuild_path in ebuild_paths {
+            cache_guard.remove(ebuild_path);
+        }
+    }
 }
 
 #[cfg(test)]
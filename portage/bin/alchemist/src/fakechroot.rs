@@ -12,13 +12,14 @@ use crate::toolchain::load_toolchains;
 use crate::toolchain::ToolchainConfig;
 use std::fs::create_dir_all;
 use std::{
+    ffi::OsString,
     fs::{create_dir, read_dir},
     io::ErrorKind,
-    os::unix::fs::symlink,
+    os::unix::{ffi::OsStringExt, fs::symlink},
     path::{Path, PathBuf},
 };
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use nix::{
     mount::{mount, MsFlags},
     sched::{unshare, CloneFlags},
@@ -28,60 +29,201 @@ use walkdir::WalkDir;
 
 const OLD_ROOT_NAME: &str = ".old-root";
 
+/// A pair of directories that refer to the same files inside and outside a
+/// container, e.g. the two sides of a bind mount.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PathMapping {
+    pub inner: PathBuf,
+    pub outer: PathBuf,
+}
+
+impl PathMapping {
+    pub fn new(inner: impl AsRef<Path>, outer: impl AsRef<Path>) -> Self {
+        Self {
+            inner: inner.as_ref().to_owned(),
+            outer: outer.as_ref().to_owned(),
+        }
+    }
+}
+
+/// Decodes the octal escapes, e.g. `\040` for a space, that the kernel uses
+/// for whitespaces and backslashes in paths in `/proc/*/mountinfo`.
+fn unescape_mountinfo_path(path: &str) -> PathBuf {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let digits = bytes.get(i + 1..i + 4).unwrap_or_default();
+        if bytes[i] == b'\\'
+            && digits.len() == 3
+            && digits.iter().all(|b| (b'0'..=b'7').contains(b))
+        {
+            let code = digits.iter().try_fold(0u8, |acc, digit| {
+                acc.checked_mul(8)?.checked_add(digit - b'0')
+            });
+            if let Some(code) = code {
+                decoded.push(code);
+                i += 4;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    PathBuf::from(OsString::from_vec(decoded))
+}
+
 /// Provides a way to translate paths inner and outer paths.
-/// This is useful when running inside a container and you have a "bind mount"
+/// This is useful when running inside a container and you have "bind mounts"
 /// between the host and container.
+///
+/// A path is translated with the mapping whose prefix is the longest match, so
+/// nested mappings, e.g. a sysroot under `/build` mounted separately from its
+/// parent, are handled as expected.
 #[derive(Clone, Debug)]
 pub struct PathTranslator {
-    inner: PathBuf,
-    outer: PathBuf,
+    mappings: Vec<PathMapping>,
 }
 
 impl PathTranslator {
-    fn new(inner: impl AsRef<Path>, outer: impl AsRef<Path>) -> Self {
-        Self {
-            inner: inner.as_ref().to_owned(),
-            outer: outer.as_ref().to_owned(),
-        }
+    pub fn new(mappings: Vec<PathMapping>) -> Self {
+        Self { mappings }
     }
 
     /// Creates a PathTranslator which does nothing.
     pub fn noop() -> Self {
-        Self::new(Path::new(""), Path::new(""))
+        Self::new(vec![PathMapping::new("", "")])
     }
 
-    pub fn to_outer(&self, path: impl AsRef<Path>) -> Result<PathBuf> {
-        let path = path.as_ref();
-        if path.starts_with(&self.outer) {
-            return Ok(path.to_path_buf());
+    /// Creates a PathTranslator from the bind mounts listed in the content of
+    /// `/proc/self/mountinfo`.
+    ///
+    /// Mounts whose root is the root of the mounted filesystem, e.g. `/proc`
+    /// or tmpfs, have no outer counterpart and are ignored. Note that the root
+    /// is relative to the mounted filesystem, so the outer paths are correct
+    /// only if the filesystem is mounted at `/` outside the container.
+    pub fn from_mountinfo(mountinfo: &str) -> Result<Self> {
+        let mut mappings = Vec::new();
+        for (lineno, line) in mountinfo.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            // See proc(5) for the format. We only need the 4th (root) and 5th
+            // (mount point) fields, which precede the optional fields.
+            let fields: Vec<&str> = line.split(' ').collect();
+            if fields.len() < 5 {
+                bail!("Malformed mountinfo line {}: {}", lineno + 1, line);
+            }
+            let outer = unescape_mountinfo_path(fields[3]);
+            let inner = unescape_mountinfo_path(fields[4]);
+            if outer == Path::new("/") || outer == inner {
+                continue;
+            }
+            mappings.push(PathMapping { inner, outer });
         }
+        Ok(Self::new(mappings))
+    }
 
-        let remaining = path.strip_prefix(&self.inner).with_context(|| {
-            format!(
-                "Cannot convert non-inner path {} to outer path. Must have a {} prefix.",
-                path.display(),
-                self.inner.display()
-            )
-        })?;
+    /// Creates a PathTranslator from the mounts of the current process.
+    pub fn from_self_mountinfo() -> Result<Self> {
+        let mountinfo = std::fs::read_to_string("/proc/self/mountinfo")
+            .with_context(|| "Reading /proc/self/mountinfo")?;
+        Self::from_mountinfo(&mountinfo)
+    }
 
-        Ok(self.outer.join(remaining))
+    /// Returns the mappings used by the translator.
+    pub fn mappings(&self) -> &[PathMapping] {
+        &self.mappings
     }
 
-    pub fn to_inner(&self, path: impl AsRef<Path>) -> Result<PathBuf> {
-        let path = path.as_ref();
-        if path.starts_with(&self.inner) {
-            return Ok(path.to_path_buf());
+    /// Composes the translator with `outer`, which translates the outer paths
+    /// of this translator further, e.g. to the host of a container.
+    ///
+    /// Paths only known to `outer` are translated by its mappings.
+    pub fn then(&self, outer: &PathTranslator) -> Self {
+        let mut mappings: Vec<PathMapping> = self
+            .mappings
+            .iter()
+            .map(|mapping| PathMapping {
+                inner: mapping.inner.clone(),
+                outer: outer
+                    .to_outer(&mapping.outer)
+                    .unwrap_or_else(|_| mapping.outer.clone()),
+            })
+            .collect();
+        mappings.extend(outer.mappings.iter().cloned());
+        Self::new(mappings)
+    }
+
+    /// Finds the mapping whose side selected by `side` is the longest prefix
+    /// of `path`. Among equally long prefixes, the earlier mapping wins.
+    fn find_longest_match<'a>(
+        &'a self,
+        path: &Path,
+        side: impl Fn(&'a PathMapping) -> &'a Path,
+    ) -> Option<&'a PathMapping> {
+        self.mappings
+            .iter()
+            .filter(|mapping| path.starts_with(side(mapping)))
+            .rev()
+            .max_by_key(|mapping| side(mapping).components().count())
+    }
+
+    /// Translates `path` from the `from` side to the `to` side of the best
+    /// matching mapping. Paths that are already on the `to` side are returned
+    /// as is.
+    fn translate<'a>(
+        &'a self,
+        path: &Path,
+        from: impl Fn(&'a PathMapping) -> &'a Path,
+        to: impl Fn(&'a PathMapping) -> &'a Path,
+    ) -> Option<PathBuf> {
+        let from_match = self.find_longest_match(path, &from);
+        let to_match = self.find_longest_match(path, &to);
+        let from_len = from_match.map(|mapping| from(mapping).components().count());
+        let to_len = to_match.map(|mapping| to(mapping).components().count());
+
+        // Prefer keeping the path as is on ties, as the path may be already
+        // translated.
+        if to_len.is_some() && to_len >= from_len {
+            return Some(path.to_path_buf());
         }
+        let from_match = from_match?;
+        Some(to(from_match).join(path.strip_prefix(from(from_match)).ok()?))
+    }
 
-        let remaining = path.strip_prefix(&self.outer).with_context(|| {
-            format!(
-                "Cannot convert non-outer path {} to inner path. Must have a {} prefix.",
-                path.display(),
-                self.outer.display()
-            )
-        })?;
+    fn describe_prefixes(&self, side: impl Fn(&PathMapping) -> &Path) -> String {
+        self.mappings
+            .iter()
+            .map(|mapping| side(mapping).display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
 
-        Ok(self.inner.join(remaining))
+    pub fn to_outer(&self, path: impl AsRef<Path>) -> Result<PathBuf> {
+        let path = path.as_ref();
+        self.translate(path, |m| &m.inner, |m| &m.outer)
+            .with_context(|| {
+                format!(
+                    "Cannot convert non-inner path {} to outer path. Must have one of {} \
+                     prefixes.",
+                    path.display(),
+                    self.describe_prefixes(|m| &m.inner)
+                )
+            })
+    }
+
+    pub fn to_inner(&self, path: impl AsRef<Path>) -> Result<PathBuf> {
+        let path = path.as_ref();
+        self.translate(path, |m| &m.outer, |m| &m.inner)
+            .with_context(|| {
+                format!(
+                    "Cannot convert non-outer path {} to inner path. Must have one of {} \
+                     prefixes.",
+                    path.display(),
+                    self.describe_prefixes(|m| &m.outer)
+                )
+            })
     }
 }
 
@@ -449,7 +591,7 @@ pub fn enter_fake_chroot(targets: &[&BoardTarget], source_dir: &Path) -> Result<
         symlink(&source_dir, source_mount_point)?;
     }
 
-    let translator = PathTranslator::new(CHROOT_SOURCE_DIR, &source_dir);
+    let translator = PathTranslator::new(vec![PathMapping::new(CHROOT_SOURCE_DIR, &source_dir)]);
 
     // Generate configs.
     generate_host_configs()?;
@@ -465,7 +607,8 @@ mod tests {
 
     #[test]
     fn test_path_translator() -> Result<()> {
-        let translator = PathTranslator::new(CHROOT_SOURCE_DIR, "/home/cros");
+        let translator =
+            PathTranslator::new(vec![PathMapping::new(CHROOT_SOURCE_DIR, "/home/cros")]);
 
         assert_eq!(
             translator.to_outer(Path::new(CHROOT_SOURCE_DIR).join("src/BUILD.bazel"))?,
@@ -482,4 +625,82 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_path_translator_longest_prefix() -> Result<()> {
+        let translator = PathTranslator::new(vec![
+            PathMapping::new(CHROOT_SOURCE_DIR, "/home/cros"),
+            PathMapping::new("/build", "/home/cros/out/build"),
+            PathMapping::new("/build/betty", "/var/sysroots/betty"),
+        ]);
+
+        assert_eq!(
+            translator.to_outer("/build/betty/etc/make.conf")?,
+            Path::new("/var/sysroots/betty/etc/make.conf")
+        );
+        assert_eq!(
+            translator.to_outer("/build/amd64-generic/etc/make.conf")?,
+            Path::new("/home/cros/out/build/amd64-generic/etc/make.conf")
+        );
+        assert_eq!(
+            translator.to_inner("/home/cros/out/build/amd64-generic")?,
+            Path::new("/build/amd64-generic")
+        );
+        assert_eq!(
+            translator.to_inner("/home/cros/src/BUILD.bazel")?,
+            Path::new(CHROOT_SOURCE_DIR).join("src/BUILD.bazel")
+        );
+        assert_eq!(
+            translator.to_inner("/var/sysroots/betty")?,
+            Path::new("/build/betty")
+        );
+
+        // Already translated paths are kept as is.
+        assert_eq!(
+            translator.to_outer("/var/sysroots/betty/etc")?,
+            Path::new("/var/sysroots/betty/etc")
+        );
+
+        assert!(translator.to_outer("/etc/make.conf").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_path_translator_from_mountinfo() -> Result<()> {
+        let translator = PathTranslator::from_mountinfo(
+            r#"22 1 0:21 / / rw,relatime - overlay overlay rw
+23 22 0:22 / /proc rw,nosuid shared:2 - proc proc rw
+24 22 254:1 /home/me/chromiumos /mnt/host/source rw,relatime master:1 - ext4 /dev/vda1 rw
+25 22 254:1 /home/me/out\040dir/build /build rw,relatime - ext4 /dev/vda1 rw
+26 22 254:1 /etc/hosts /etc/hosts rw,relatime - ext4 /dev/vda1 rw
+"#,
+        )?;
+
+        assert_eq!(
+            translator.mappings(),
+            [
+                PathMapping::new("/mnt/host/source", "/home/me/chromiumos"),
+                PathMapping::new("/build", "/home/me/out dir/build"),
+            ]
+        );
+
+        assert!(PathTranslator::from_mountinfo("22 1 0:21").is_err());
+
+        let fake_chroot = PathTranslator::new(vec![PathMapping::new(
+            CHROOT_SOURCE_DIR,
+            "/mnt/host/source",
+        )]);
+        let translator = fake_chroot.then(&translator);
+        assert_eq!(
+            translator.to_outer(Path::new(CHROOT_SOURCE_DIR).join("src"))?,
+            Path::new("/home/me/chromiumos/src")
+        );
+        assert_eq!(
+            translator.to_outer("/build/betty")?,
+            Path::new("/home/me/out dir/build/betty")
+        );
+
+        Ok(())
+    }
 }
@@ -11,10 +11,10 @@ pub mod dependency;
 pub mod ebuild;
 pub mod fakechroot;
 pub mod fileops;
+pub mod lint;
 pub mod path;
 pub mod repository;
 pub mod resolver;
-pub mod simpleversion;
 #[cfg(test)]
 pub(crate) mod testutils;
 pub mod toolchain;
@@ -0,0 +1,775 @@
+// Copyright 2024 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! Collects problems in overlays and profiles.
+//!
+//! Alchemist tolerates most of these problems: packages failing to load or
+//! analyze are turned into targets that fail at build time, and configuration
+//! entries that match nothing are silently ignored. This module reports them
+//! all at once so that they can be caught early, e.g. in CI.
+
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use itertools::Itertools;
+
+use crate::{
+    analyze::MaybePackage,
+    config::{bundle::ConfigBundle, ConfigNodeValue, UseUpdate},
+    dependency::{
+        package::{
+            AsPackageRef, PackageAtom, PackageBlock, PackageDependency, PackageDependencyAtom,
+        },
+        Dependency,
+    },
+    ebuild::{
+        metadata::MaybeEBuildMetadata, BazelSpecificMetadata, MaybePackageDetails, PackageDetails,
+    },
+    repository::RepositorySet,
+};
+
+/// Variables containing package dependencies.
+const DEPENDENCY_VARS: [&str; 5] = ["DEPEND", "RDEPEND", "PDEPEND", "BDEPEND", "IDEPEND"];
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum LintSeverity {
+    Error,
+    Warning,
+}
+
+/// The kind of a problem found by lint.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum LintKind {
+    /// An ebuild failed to evaluate. See
+    /// [`crate::ebuild::metadata::EBuildEvaluationError`].
+    EBuildEvaluationError,
+    /// An ebuild was evaluated, but its metadata is invalid. See
+    /// [`crate::ebuild::PackageLoadError`].
+    PackageLoadError,
+    /// A package failed to analyze, e.g. due to unsatisfiable dependencies.
+    /// See [`crate::analyze::PackageAnalysisError`].
+    PackageAnalysisError,
+    /// A TOML metadata file next to an ebuild or an eclass can't be parsed.
+    InvalidTomlMetadata,
+    /// A USE flag in `package.use`, `use.mask` and such is missing in IUSE of
+    /// all packages the entry applies to.
+    UnknownUseFlag,
+    /// A USE flag in `package.use`, `use.mask` and such is overridden by a
+    /// later entry, e.g. in a child profile.
+    ShadowedUseFlag,
+    /// An atom in a configuration file matches no package.
+    UnmatchedAtom,
+    /// No package depends on an entry in `package.provided`.
+    UnusedProvidedPackage,
+}
+
+impl LintKind {
+    pub fn severity(&self) -> LintSeverity {
+        match self {
+            Self::EBuildEvaluationError | Self::PackageLoadError | Self::PackageAnalysisError => {
+                LintSeverity::Error
+            }
+            Self::InvalidTomlMetadata
+            | Self::UnknownUseFlag
+            | Self::ShadowedUseFlag
+            | Self::UnmatchedAtom
+            | Self::UnusedProvidedPackage => LintSeverity::Warning,
+        }
+    }
+
+    /// Returns a short human-readable description of the kind.
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::EBuildEvaluationError => "ebuilds failing to evaluate",
+            Self::PackageLoadError => "packages with invalid metadata",
+            Self::PackageAnalysisError => "packages failing to analyze",
+            Self::InvalidTomlMetadata => "unparsable TOML metadata",
+            Self::UnknownUseFlag => "USE flags missing in IUSE",
+            Self::ShadowedUseFlag => "USE flags overridden by later entries",
+            Self::UnmatchedAtom => "atoms matching no package",
+            Self::UnusedProvidedPackage => "unused package.provided entries",
+        }
+    }
+}
+
+/// A problem found by lint.
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct LintIssue {
+    pub kind: LintKind,
+    /// The file containing the problem.
+    pub location: PathBuf,
+    pub message: String,
+}
+
+/// Reports packages that failed to load or analyze.
+///
+/// Masked packages are not reported even though they fail to analyze, since
+/// masking packages is intentional.
+pub fn lint_packages(packages: &[MaybePackage]) -> Vec<LintIssue> {
+    packages
+        .iter()
+        .filter_map(|package| {
+            let MaybePackage::Err(error) = package else {
+                return None;
+            };
+            let (kind, message) = match &error.details {
+                MaybePackageDetails::Err(load_error) => match &load_error.metadata {
+                    MaybeEBuildMetadata::Err(evaluation_error) => (
+                        LintKind::EBuildEvaluationError,
+                        evaluation_error.error.clone(),
+                    ),
+                    MaybeEBuildMetadata::Ok(_) => {
+                        (LintKind::PackageLoadError, load_error.error.clone())
+                    }
+                },
+                MaybePackageDetails::Ok(details) => {
+                    if !details.readiness.ok() {
+                        return None;
+                    }
+                    (LintKind::PackageAnalysisError, error.error.clone())
+                }
+            };
+            Some(LintIssue {
+                kind,
+                location: error.as_basic_data().ebuild_path.clone(),
+                message,
+            })
+        })
+        .collect()
+}
+
+/// Lists the entries of `dir` with the `toml` extension.
+fn find_toml_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let read_dir = match dir.read_dir() {
+        Ok(read_dir) => read_dir,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err.into()),
+    };
+    let mut paths = vec![];
+    for entry in read_dir {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "toml") {
+            paths.push(path);
+        }
+    }
+    Ok(paths)
+}
+
+/// Reports TOML metadata files of ebuilds and eclasses that can't be parsed.
+///
+/// All TOML files are checked, even if no ebuild uses them.
+pub fn lint_toml_metadata(repos: &RepositorySet) -> Result<Vec<LintIssue>> {
+    let mut toml_paths = BTreeSet::new();
+    for repo in repos.get_repos() {
+        for eclass_dir in repo.eclass_dirs() {
+            toml_paths.extend(find_toml_files(eclass_dir)?);
+        }
+        // Find */*/*.toml.
+        for category_entry in repo.base_dir().read_dir()? {
+            let category_path = category_entry?.path();
+            if !category_path.is_dir() {
+                continue;
+            }
+            for package_entry in category_path.read_dir()? {
+                let package_path = package_entry?.path();
+                if package_path.is_dir() {
+                    toml_paths.extend(find_toml_files(&package_path)?);
+                }
+            }
+        }
+    }
+
+    Ok(toml_paths
+        .into_iter()
+        .filter_map(|path| match BazelSpecificMetadata::validate_file(&path) {
+            Ok(()) => None,
+            Err(err) => Some(LintIssue {
+                kind: LintKind::InvalidTomlMetadata,
+                location: path,
+                message: format!("{err:#}"),
+            }),
+        })
+        .collect())
+}
+
+/// Collects the non-blocker dependency atoms of `packages` on any of
+/// `package_names`.
+fn collect_dependency_atoms(
+    packages: &[MaybePackageDetails],
+    package_names: &BTreeSet<&str>,
+) -> Vec<PackageDependencyAtom> {
+    let mut atoms = vec![];
+    for details in packages {
+        let MaybePackageDetails::Ok(details) = details else {
+            continue;
+        };
+        for name in DEPENDENCY_VARS {
+            let Ok(expression) = details.metadata.vars.get_scalar_or_default(name) else {
+                continue;
+            };
+            // Parse errors are reported as analysis errors.
+            let Ok(deps) = expression.parse::<PackageDependency>() else {
+                continue;
+            };
+            deps.map_tree(|dep| {
+                if let Dependency::Leaf(atom) = &dep {
+                    if atom.block() == PackageBlock::None
+                        && package_names.contains(atom.package_name())
+                    {
+                        atoms.push(atom.clone());
+                    }
+                }
+                dep
+            });
+        }
+    }
+    atoms
+}
+
+fn unmatched_atom_issue(location: &Path, atom: &PackageAtom) -> LintIssue {
+    LintIssue {
+        kind: LintKind::UnmatchedAtom,
+        location: location.to_owned(),
+        message: format!("{atom} matches no package"),
+    }
+}
+
+/// Looks up packages matching atoms.
+struct PackageIndex<'a> {
+    packages_by_name: HashMap<&'a str, Vec<&'a MaybePackageDetails>>,
+}
+
+impl<'a> PackageIndex<'a> {
+    fn new(packages: &'a [MaybePackageDetails]) -> Self {
+        Self {
+            packages_by_name: packages
+                .iter()
+                .into_group_map_by(|details| details.as_basic_data().package_name.as_str()),
+        }
+    }
+
+    fn find(&self, atom: &PackageAtom) -> Vec<&'a MaybePackageDetails> {
+        self.packages_by_name
+            .get(atom.package_name().as_str())
+            .into_iter()
+            .flatten()
+            .copied()
+            .filter(|details| atom.matches(&details.as_package_ref()))
+            .collect()
+    }
+}
+
+/// Returns true if `later` overrides the value `earlier` sets for `flag`,
+/// i.e. `later` is evaluated after `earlier`, updates the same state of
+/// `flag` and applies to all packages `earlier` applies to.
+fn overrides_use_update(later: &UseUpdate, earlier: &UseUpdate, flag: &str) -> bool {
+    later.kind == earlier.kind
+        && (!later.filter.stable_only || earlier.filter.stable_only)
+        && (later.filter.atom.is_none() || later.filter.atom == earlier.filter.atom)
+        && later
+            .use_tokens
+            .split_ascii_whitespace()
+            .any(|token| token == "-*" || token.strip_prefix('-').unwrap_or(token) == flag)
+}
+
+/// USE flags that profiles may set without any package declaring them in
+/// IUSE: IUSE_IMPLICIT, the arch flag and USE_EXPAND flags.
+struct ProfileUseFlags {
+    flags: HashSet<String>,
+    /// Prefixes of USE_EXPAND flags, e.g. `video_cards_`.
+    prefixes: Vec<String>,
+}
+
+impl ProfileUseFlags {
+    fn new(config: &ConfigBundle) -> Self {
+        let env = config.env();
+        let words = |name: &str| -> Vec<String> {
+            env.get(name)
+                .map(|value| value.split_ascii_whitespace().map(str::to_owned).collect())
+                .unwrap_or_default()
+        };
+
+        let mut flags: HashSet<String> = words("IUSE_IMPLICIT").into_iter().collect();
+        flags.extend(words("ARCH"));
+        for name in words("USE_EXPAND_UNPREFIXED") {
+            flags.extend(words(&format!("USE_EXPAND_VALUES_{name}")));
+        }
+        let prefixes = words("USE_EXPAND")
+            .into_iter()
+            .map(|name| format!("{}_", name.to_ascii_lowercase()))
+            .collect();
+
+        Self { flags, prefixes }
+    }
+
+    fn contains(&self, flag: &str) -> bool {
+        self.flags.contains(flag) || self.prefixes.iter().any(|prefix| flag.starts_with(prefix))
+    }
+}
+
+/// Reports problems in USE flag configurations, i.e. `package.use`,
+/// `use.mask`, `package.use.force` and their variants:
+///
+/// - atoms matching none of `packages`,
+/// - USE flags missing in IUSE of all matching packages, or of all packages
+///   for entries without atoms, except flags provided by the profile such as
+///   arch and USE_EXPAND flags,
+/// - entries overridden by later entries for the same atom or all packages,
+///   e.g. in a later profile layer.
+///
+/// Only the entries parsed into [`ConfigNodeValue::Uses`] are checked. USE
+/// set in `make.defaults` or `make.conf` is not. Configuration nodes without
+/// sources, i.e. the ones synthesized by alchemist itself, are not checked
+/// either.
+pub fn lint_use_configs(config: &ConfigBundle, packages: &[MaybePackageDetails]) -> Vec<LintIssue> {
+    let index = PackageIndex::new(packages);
+    let profile_flags = ProfileUseFlags::new(config);
+    let all_iuse: HashSet<&str> = packages
+        .iter()
+        .filter_map(|details| match details {
+            MaybePackageDetails::Ok(details) => Some(details.use_map.keys()),
+            MaybePackageDetails::Err(_) => None,
+        })
+        .flatten()
+        .map(|flag| flag.as_str())
+        .collect();
+
+    let entries: Vec<(&Path, &UseUpdate)> = config
+        .nodes()
+        .iter()
+        .filter_map(|node| match (&node.value, node.sources.first()) {
+            (ConfigNodeValue::Uses(updates), Some(location)) => Some(
+                updates
+                    .iter()
+                    .map(move |update| (location.as_path(), update)),
+            ),
+            _ => None,
+        })
+        .flatten()
+        .collect();
+
+    let mut issues = vec![];
+    for (i, (location, update)) in entries.iter().enumerate() {
+        let target = match &update.filter.atom {
+            Some(atom) => atom.to_string(),
+            None => "all packages".to_owned(),
+        };
+
+        // None means all packages.
+        let matched: Option<Vec<&PackageDetails>> = match &update.filter.atom {
+            Some(atom) => {
+                let matched = index.find(atom);
+                if matched.is_empty() {
+                    issues.push(unmatched_atom_issue(location, atom));
+                    continue;
+                }
+                Some(
+                    matched
+                        .into_iter()
+                        .filter_map(|details| match details {
+                            MaybePackageDetails::Ok(details) => Some(details.as_ref()),
+                            MaybePackageDetails::Err(_) => None,
+                        })
+                        .collect(),
+                )
+            }
+            None => None,
+        };
+
+        for token in update.use_tokens.split_ascii_whitespace() {
+            let flag = token.strip_prefix('-').unwrap_or(token);
+            if flag.contains('*') || flag.ends_with(':') {
+                continue;
+            }
+
+            let known = match &matched {
+                // Don't guess IUSE of packages that failed to load.
+                Some(matched) => {
+                    matched.is_empty()
+                        || matched
+                            .iter()
+                            .any(|details| details.use_map.contains_key(flag))
+                }
+                None => all_iuse.contains(flag),
+            };
+            if !known && !profile_flags.contains(flag) {
+                issues.push(LintIssue {
+                    kind: LintKind::UnknownUseFlag,
+                    location: location.to_path_buf(),
+                    message: match &update.filter.atom {
+                        Some(atom) => format!("{flag} is not in IUSE of any {atom}"),
+                        None => format!("{flag} is not in IUSE of any package"),
+                    },
+                });
+            }
+
+            if let Some((later_location, _)) = entries[i + 1..]
+                .iter()
+                .find(|(_, later)| overrides_use_update(later, update, flag))
+            {
+                issues.push(LintIssue {
+                    kind: LintKind::ShadowedUseFlag,
+                    location: location.to_path_buf(),
+                    message: format!(
+                        "{token} for {target} is overridden by {}",
+                        later_location.display()
+                    ),
+                });
+            }
+        }
+    }
+    issues
+}
+
+/// Reports configuration entries that are likely mistakes:
+///
+/// - problems in USE flag configurations reported by [`lint_use_configs`],
+/// - other atoms matching none of `packages`, e.g. in `package.mask`,
+/// - `package.provided` entries no package depends on.
+///
+/// Configuration nodes without sources, i.e. the ones synthesized by
+/// alchemist itself, are not checked.
+pub fn lint_config(config: &ConfigBundle, packages: &[MaybePackageDetails]) -> Vec<LintIssue> {
+    let index = PackageIndex::new(packages);
+    let find_packages = |atom: &PackageAtom| index.find(atom);
+
+    let provided_names: BTreeSet<&str> = config
+        .provided_packages()
+        .iter()
+        .map(|provided| provided.package_name.as_str())
+        .collect();
+    let dependency_atoms = collect_dependency_atoms(packages, &provided_names);
+
+    let mut issues = lint_use_configs(config, packages);
+    for node in config.nodes() {
+        let Some(location) = node.sources.first() else {
+            continue;
+        };
+        match &node.value {
+            ConfigNodeValue::PackageMasks(updates) => {
+                for update in updates {
+                    if find_packages(&update.atom).is_empty() {
+                        issues.push(unmatched_atom_issue(location, &update.atom));
+                    }
+                }
+            }
+            ConfigNodeValue::AcceptKeywords(updates) => {
+                for update in updates {
+                    if find_packages(&update.atom).is_empty() {
+                        issues.push(unmatched_atom_issue(location, &update.atom));
+                    }
+                }
+            }
+            ConfigNodeValue::PackageBashrcs(bashrcs) => {
+                for bashrc in bashrcs {
+                    if find_packages(&bashrc.atom).is_empty() {
+                        issues.push(unmatched_atom_issue(location, &bashrc.atom));
+                    }
+                }
+            }
+            ConfigNodeValue::ProvidedPackages(provided_packages) => {
+                for provided in provided_packages {
+                    if !dependency_atoms
+                        .iter()
+                        .any(|atom| atom.matches_provided(provided))
+                    {
+                        issues.push(LintIssue {
+                            kind: LintKind::UnusedProvidedPackage,
+                            location: location.clone(),
+                            message: format!(
+                                "No package depends on {}-{}",
+                                provided.package_name, provided.version
+                            ),
+                        });
+                    }
+                }
+            }
+            // Checked by lint_use_configs.
+            ConfigNodeValue::Uses(_) => {}
+            ConfigNodeValue::Vars(_) | ConfigNodeValue::ProfileBashrc(_) => {}
+        }
+    }
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{HashMap, HashSet},
+        sync::Arc,
+    };
+
+    use crate::{
+        bash::vars::{BashValue, BashVars},
+        config::{
+            ConfigNode, PackageMaskKind, PackageMaskUpdate, ProvidedPackage, SimpleConfigSource,
+            UseUpdateFilter, UseUpdateKind,
+        },
+        data::{Slot, UseMap},
+        ebuild::{
+            metadata::{EBuildBasicData, EBuildMetadata},
+            PackageDetails, PackageReadiness,
+        },
+        repository::RepositoryLayout,
+        testutils::write_files,
+    };
+
+    use super::*;
+
+    fn new_package(package_name: &str, iuse: &[&str], depend: &str) -> MaybePackageDetails {
+        let (category_name, short_package_name) = package_name.split_once('/').unwrap();
+        MaybePackageDetails::Ok(Arc::new(PackageDetails {
+            metadata: Arc::new(EBuildMetadata {
+                basic_data: EBuildBasicData {
+                    repo_name: "chromiumos".to_owned(),
+                    ebuild_path: PathBuf::from(format!(
+                        "/{package_name}/{short_package_name}-1.0.ebuild"
+                    )),
+                    package_name: package_name.to_owned(),
+                    short_package_name: short_package_name.to_owned(),
+                    category_name: category_name.to_owned(),
+                    version: "1.0".parse().unwrap(),
+                },
+                vars: BashVars::new(HashMap::from([(
+                    "DEPEND".to_owned(),
+                    BashValue::Scalar(depend.to_owned()),
+                )])),
+            }),
+            slot: Slot::new("0"),
+            use_map: iuse
+                .iter()
+                .map(|flag| (flag.to_string(), false))
+                .collect::<UseMap>(),
+            stable: true,
+            readiness: PackageReadiness::Ok,
+            inherited: HashSet::new(),
+            inherit_paths: vec![],
+            direct_build_target: None,
+            bazel_metadata: Default::default(),
+        }))
+    }
+
+    fn use_update(atom: &str, use_tokens: &str) -> UseUpdate {
+        UseUpdate {
+            kind: UseUpdateKind::Set,
+            filter: UseUpdateFilter {
+                atom: Some(atom.parse().unwrap()),
+                stable_only: false,
+            },
+            use_tokens: use_tokens.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_lint_config() -> Result<()> {
+        let packages = [
+            new_package("sys-apps/foo", &["ssl", "test"], "dev-libs/bar"),
+            new_package("dev-libs/bar", &[], ">=virtual/provided-2"),
+        ];
+        let config = ConfigBundle::from_sources([SimpleConfigSource::new(vec![
+            ConfigNode {
+                sources: vec![PathBuf::from("/profile/package.use")],
+                value: ConfigNodeValue::Uses(vec![
+                    use_update("sys-apps/foo", "ssl -test typo"),
+                    use_update("sys-apps/missing", "ssl"),
+                    use_update(">=sys-apps/foo-2", "ssl"),
+                ]),
+            },
+            ConfigNode {
+                sources: vec![PathBuf::from("/profile/package.mask")],
+                value: ConfigNodeValue::PackageMasks(vec![
+                    PackageMaskUpdate {
+                        kind: PackageMaskKind::Mask,
+                        atom: "dev-libs/bar".parse()?,
+                    },
+                    PackageMaskUpdate {
+                        kind: PackageMaskKind::Mask,
+                        atom: "dev-libs/gone".parse()?,
+                    },
+                ]),
+            },
+            ConfigNode {
+                sources: vec![PathBuf::from("/profile/package.provided")],
+                value: ConfigNodeValue::ProvidedPackages(vec![
+                    ProvidedPackage {
+                        package_name: "virtual/provided".to_owned(),
+                        version: "2".parse()?,
+                    },
+                    ProvidedPackage {
+                        package_name: "virtual/unused".to_owned(),
+                        version: "1".parse()?,
+                    },
+                ]),
+            },
+            // Nodes without sources are not checked.
+            ConfigNode {
+                sources: vec![],
+                value: ConfigNodeValue::PackageMasks(vec![PackageMaskUpdate {
+                    kind: PackageMaskKind::Mask,
+                    atom: "dev-libs/hack".parse()?,
+                }]),
+            },
+        ])]);
+
+        let issues = lint_config(&config, &packages)
+            .into_iter()
+            .map(|issue| (issue.kind, issue.location, issue.message))
+            .collect_vec();
+        assert_eq!(
+            issues,
+            vec![
+                (
+                    LintKind::UnknownUseFlag,
+                    PathBuf::from("/profile/package.use"),
+                    "typo is not in IUSE of any sys-apps/foo".to_owned()
+                ),
+                (
+                    LintKind::UnmatchedAtom,
+                    PathBuf::from("/profile/package.use"),
+                    "sys-apps/missing matches no package".to_owned()
+                ),
+                (
+                    LintKind::UnmatchedAtom,
+                    PathBuf::from("/profile/package.use"),
+                    ">=sys-apps/foo-2 matches no package".to_owned()
+                ),
+                (
+                    LintKind::UnmatchedAtom,
+                    PathBuf::from("/profile/package.mask"),
+                    "dev-libs/gone matches no package".to_owned()
+                ),
+                (
+                    LintKind::UnusedProvidedPackage,
+                    PathBuf::from("/profile/package.provided"),
+                    "No package depends on virtual/unused-1".to_owned()
+                ),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_lint_use_configs() -> Result<()> {
+        let packages = [
+            new_package("sys-apps/foo", &["ssl", "test"], ""),
+            new_package("sys-apps/bar", &["debug"], ""),
+        ];
+        let global_update = |kind: UseUpdateKind, use_tokens: &str| UseUpdate {
+            kind,
+            filter: UseUpdateFilter {
+                atom: None,
+                stable_only: false,
+            },
+            use_tokens: use_tokens.to_owned(),
+        };
+        let config = ConfigBundle::from_sources([SimpleConfigSource::new(vec![
+            ConfigNode {
+                sources: vec![PathBuf::from("/base/make.defaults")],
+                value: ConfigNodeValue::Vars(HashMap::from([
+                    ("ARCH".to_owned(), "amd64".to_owned()),
+                    ("IUSE_IMPLICIT".to_owned(), "prefix".to_owned()),
+                    ("USE_EXPAND".to_owned(), "VIDEO_CARDS".to_owned()),
+                    ("USE_EXPAND_UNPREFIXED".to_owned(), "ARCH".to_owned()),
+                    (
+                        "USE_EXPAND_VALUES_ARCH".to_owned(),
+                        "amd64 arm64".to_owned(),
+                    ),
+                ])),
+            },
+            ConfigNode {
+                sources: vec![PathBuf::from("/base/use.mask")],
+                value: ConfigNodeValue::Uses(vec![global_update(
+                    UseUpdateKind::Mask,
+                    "debug typo amd64 arm64 prefix video_cards_intel",
+                )]),
+            },
+            ConfigNode {
+                sources: vec![PathBuf::from("/base/package.use")],
+                value: ConfigNodeValue::Uses(vec![
+                    use_update("sys-apps/foo", "ssl test"),
+                    use_update("sys-apps/bar", "debug"),
+                ]),
+            },
+            ConfigNode {
+                sources: vec![PathBuf::from("/board/package.use")],
+                value: ConfigNodeValue::Uses(vec![
+                    use_update("sys-apps/foo", "-ssl"),
+                    use_update("sys-apps/bar", "-*"),
+                ]),
+            },
+            ConfigNode {
+                sources: vec![PathBuf::from("/board/use.mask")],
+                value: ConfigNodeValue::Uses(vec![global_update(UseUpdateKind::Mask, "-debug")]),
+            },
+        ])]);
+
+        let issues = lint_use_configs(&config, &packages)
+            .into_iter()
+            .map(|issue| (issue.kind, issue.location, issue.message))
+            .collect_vec();
+        assert_eq!(
+            issues,
+            vec![
+                (
+                    LintKind::ShadowedUseFlag,
+                    PathBuf::from("/base/use.mask"),
+                    "debug for all packages is overridden by /board/use.mask".to_owned()
+                ),
+                (
+                    LintKind::UnknownUseFlag,
+                    PathBuf::from("/base/use.mask"),
+                    "typo is not in IUSE of any package".to_owned()
+                ),
+                (
+                    LintKind::ShadowedUseFlag,
+                    PathBuf::from("/base/package.use"),
+                    "ssl for sys-apps/foo is overridden by /board/package.use".to_owned()
+                ),
+                (
+                    LintKind::ShadowedUseFlag,
+                    PathBuf::from("/base/package.use"),
+                    "debug for sys-apps/bar is overridden by /board/package.use".to_owned()
+                ),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_lint_toml_metadata() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let dir = dir.path();
+        write_files(
+            dir,
+            [
+                ("metadata/layout.conf", "repo-name = test\n"),
+                ("eclass/good.toml", "[bazel]\nextra_sources = []\n"),
+                ("eclass/bad.toml", "[bazel\n"),
+                ("sys-apps/foo/foo.toml", "[bazel]\nextra_sources = 1\n"),
+                ("sys-apps/foo/foo-1.0.ebuild", ""),
+            ],
+        )?;
+        let repos = RepositorySet::load_from_layouts("test", &[RepositoryLayout::load(dir)?])?;
+
+        let issues = lint_toml_metadata(&repos)?;
+        assert_eq!(
+            issues
+                .iter()
+                .map(|issue| (issue.kind, issue.location.clone()))
+                .collect_vec(),
+            vec![
+                (LintKind::InvalidTomlMetadata, dir.join("eclass/bad.toml")),
+                (
+                    LintKind::InvalidTomlMetadata,
+                    dir.join("sys-apps/foo/foo.toml")
+                ),
+            ]
+        );
+        Ok(())
+    }
+}
@@ -12,7 +12,7 @@ use regex::Regex;
 use sha2::digest::generic_array::GenericArray;
 use sha2::{Digest, Sha256};
 use std::cell::{Ref, RefCell};
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashSet};
 use std::fs::{read_link, File};
 use std::io;
 use std::os::unix::prelude::OsStrExt;
@@ -24,6 +24,7 @@ use std::{
     io::ErrorKind,
     iter,
     path::{Path, PathBuf},
+    str::FromStr,
 };
 use walkdir::{DirEntry, WalkDir};
 
@@ -33,6 +34,105 @@ pub type Sha256Digest = GenericArray<u8, sha2::digest::consts::U32>;
 static LAYOUT_CONF_LINE_RE: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"^\s*(\S+)\s*=\s*(.*)$").unwrap());
 
+/// A profile feature enabled by `profile-formats` in `metadata/layout.conf`.
+///
+/// See `man 5 portage` for the details of each format.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ProfileFormat {
+    /// The format specified by PMS. This is the default.
+    Pms,
+    /// Allows directories in place of `package.*` and `use.*` files.
+    Portage1,
+    /// Same as [`ProfileFormat::Portage1`], and also allows `repo:path`
+    /// entries in `parent` files.
+    Portage2,
+    /// Allows `package.bashrc` in profiles.
+    ProfileBashrcs,
+    /// Uses `profile_eapi_when_unspecified` for profiles without an `eapi`
+    /// file.
+    ProfileDefaultEapi,
+    /// Allows build IDs in atoms of `package.*` files.
+    BuildId,
+}
+
+impl FromStr for ProfileFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "pms" => Self::Pms,
+            "portage-1" => Self::Portage1,
+            "portage-2" => Self::Portage2,
+            "profile-bashrcs" => Self::ProfileBashrcs,
+            // Alchemist does not support package sets, so it can't honor
+            // the @profile set defined by `packages` files.
+            "profile-set" => bail!("profile-formats = profile-set is not supported"),
+            "profile-default-eapi" => Self::ProfileDefaultEapi,
+            "build-id" => Self::BuildId,
+            _ => bail!("Unsupported profile format: {}", s),
+        })
+    }
+}
+
+/// Profile formats of a repository, which determine how its profiles are
+/// parsed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ProfileFormats {
+    formats: BTreeSet<ProfileFormat>,
+    eapi_when_unspecified: Option<String>,
+}
+
+impl Default for ProfileFormats {
+    fn default() -> Self {
+        Self {
+            formats: BTreeSet::from([ProfileFormat::Pms]),
+            eapi_when_unspecified: None,
+        }
+    }
+}
+
+impl ProfileFormats {
+    pub fn new(
+        formats: impl IntoIterator<Item = ProfileFormat>,
+        eapi_when_unspecified: Option<&str>,
+    ) -> Self {
+        Self {
+            formats: formats.into_iter().collect(),
+            eapi_when_unspecified: eapi_when_unspecified.map(str::to_owned),
+        }
+    }
+
+    /// Returns the formats that alchemist has always assumed for profiles
+    /// outside known repositories, e.g. those under `/etc/portage`.
+    pub fn unrestricted() -> Self {
+        Self::new(
+            [ProfileFormat::Portage2, ProfileFormat::ProfileBashrcs],
+            None,
+        )
+    }
+
+    pub fn contains(&self, format: ProfileFormat) -> bool {
+        self.formats.contains(&format)
+    }
+
+    /// Returns the EAPI of profiles without an `eapi` file.
+    pub fn default_eapi(&self) -> &str {
+        match &self.eapi_when_unspecified {
+            Some(eapi) if self.contains(ProfileFormat::ProfileDefaultEapi) => eapi,
+            _ => "0",
+        }
+    }
+
+    /// Whether `package.*` and `use.*` files can be directories in a profile
+    /// of `eapi`.
+    pub fn allows_directories(&self, eapi: &str) -> bool {
+        self.contains(ProfileFormat::Portage1)
+            || self.contains(ProfileFormat::Portage2)
+            // These EAPIs are Portage extensions allowing directories.
+            || matches!(eapi, "4-python" | "5-progress")
+    }
+}
+
 /// Layout information of a Portage repository that is loaded from `metadata/layout.conf`.
 ///
 /// This struct is used to construct [`RepositorySet`].
@@ -41,6 +141,7 @@ pub struct RepositoryLayout {
     name: String,
     base_dir: PathBuf,
     parents: Vec<String>,
+    profile_formats: ProfileFormats,
 }
 
 impl RepositoryLayout {
@@ -53,6 +154,8 @@ impl RepositoryLayout {
 
         let mut name: Option<String> = None;
         let mut parents = Vec::<String>::new();
+        let mut profile_formats = Vec::<ProfileFormat>::new();
+        let mut profile_eapi_when_unspecified: Option<&str> = None;
 
         for (lineno, line) in content.split('\n').enumerate() {
             let line = line.trim();
@@ -76,6 +179,16 @@ impl RepositoryLayout {
                         .map(|s| s.to_owned())
                         .collect();
                 }
+                "profile-formats" => {
+                    profile_formats = value
+                        .split_ascii_whitespace()
+                        .map(ProfileFormat::from_str)
+                        .collect::<Result<_>>()
+                        .with_context(context)?;
+                }
+                "profile_eapi_when_unspecified" => {
+                    profile_eapi_when_unspecified = Some(value);
+                }
                 _ => {
                     // Ignore unsupported entries.
                 }
@@ -85,10 +198,16 @@ impl RepositoryLayout {
         let name = name
             .ok_or_else(|| anyhow!("repo-name not defined"))
             .with_context(context)?;
+        let profile_formats = if profile_formats.is_empty() {
+            ProfileFormats::default()
+        } else {
+            ProfileFormats::new(profile_formats, profile_eapi_when_unspecified)
+        };
         Ok(Self {
             name,
             base_dir: base_dir.to_owned(),
             parents,
+            profile_formats,
         })
     }
 
@@ -99,6 +218,7 @@ impl RepositoryLayout {
             name: name.to_string(),
             base_dir: base_dir.to_path_buf(),
             parents: parents.iter().map(|s| s.to_string()).collect(),
+            profile_formats: ProfileFormats::default(),
         }
     }
 }
@@ -131,6 +251,7 @@ pub struct Repository {
     /// The list of parent repository locations (aka "masters"), in the order
     /// from the least to the most preferred one.
     parents: Vec<RepositoryLocation>,
+    profile_formats: ProfileFormats,
 }
 
 impl Repository {
@@ -159,6 +280,7 @@ impl Repository {
             name: name.to_owned(),
             location,
             parents,
+            profile_formats: layout.borrow().profile_formats.clone(),
         })
     }
 
@@ -172,6 +294,27 @@ impl Repository {
             name: name.to_string(),
             location: RepositoryLocation::new(base_dir),
             parents: Vec::new(),
+            profile_formats: ProfileFormats::default(),
+        }
+    }
+
+    /// Creates a [`Repository`] layered on top of `base`.
+    ///
+    /// The new repository searches eclasses in the same directories as `base`,
+    /// so ebuilds copied from `base` evaluate exactly as they would in `base`.
+    /// This is useful to inject synthetic ebuilds without touching the original
+    /// repository.
+    pub fn new_layered(name: &str, base_dir: &Path, base: &Repository) -> Self {
+        Self {
+            name: name.to_string(),
+            location: RepositoryLocation::new(base_dir),
+            parents: base
+                .parents
+                .iter()
+                .cloned()
+                .chain(iter::once(base.location.clone()))
+                .collect(),
+            profile_formats: base.profile_formats.clone(),
         }
     }
 
@@ -200,6 +343,11 @@ impl Repository {
         &self.location.profiles_dir
     }
 
+    /// Returns the formats of the profiles in the repository.
+    pub fn profile_formats(&self) -> &ProfileFormats {
+        &self.profile_formats
+    }
+
     /// Scans the repository and returns ebuild file paths for the specified
     /// package.
     pub fn find_ebuilds(&self, package_name: &str) -> Result<Vec<PathBuf>> {
@@ -431,6 +579,19 @@ impl RepositorySet {
         })
     }
 
+    /// Returns a copy of this repository set with `repo` added as the
+    /// highest-priority repository.
+    pub fn with_repository(&self, repo: Repository) -> Result<Self> {
+        if self.repos.contains_key(repo.name()) {
+            bail!("multiple repositories have the same name: {}", repo.name());
+        }
+
+        let mut new_set = self.clone();
+        new_set.order.push(repo.name().to_owned());
+        new_set.repos.insert(repo.name().to_owned(), repo);
+        Ok(new_set)
+    }
+
     /// The name of the repository set.
     pub fn name(&self) -> &str {
         &self.name
@@ -1369,4 +1530,43 @@ use-manifests = strict
 
         Ok(())
     }
+
+    #[test]
+    fn layered_repository() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let dir = dir.as_ref();
+
+        let repo_set = RepositorySet::load_from_layouts(
+            "test",
+            &[
+                RepositoryLayout::new("eclass-overlay", &dir.join("eclass-overlay"), &[]),
+                RepositoryLayout::new("chromiumos", &dir.join("chromiumos"), &["eclass-overlay"]),
+            ],
+        )?;
+
+        let layered = Repository::new_layered(
+            "chromiumos-pretend",
+            &dir.join("pretend"),
+            repo_set.get_repo_by_name("chromiumos")?,
+        );
+        assert_eq!(
+            vec![
+                dir.join("eclass-overlay/eclass"),
+                dir.join("chromiumos/eclass"),
+                dir.join("pretend/eclass"),
+            ],
+            layered.eclass_dirs().collect_vec()
+        );
+
+        let new_set = repo_set.with_repository(layered.clone())?;
+        assert_eq!(
+            vec!["eclass-overlay", "chromiumos", "chromiumos-pretend"],
+            new_set.get_repos().iter().map(|r| r.name()).collect_vec()
+        );
+
+        // Repository names must stay unique.
+        assert!(new_set.with_repository(layered).is_err());
+
+        Ok(())
+    }
 }
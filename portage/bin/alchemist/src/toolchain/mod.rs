@@ -14,7 +14,7 @@ use std::{
     str::FromStr,
 };
 
-use crate::repository::Repository;
+use crate::{dependency::package::PackageAtom, repository::Repository, resolver::PackageResolver};
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ToolchainOptions {
@@ -102,6 +102,42 @@ impl Toolchain {
             _ => bail!("Unknown arch {}", &self.arch),
         }
     }
+
+    /// Returns the names of the `cross-<triple>` packages that make up the
+    /// cross compiler, i.e. `cross-<triple>/gcc` and friends.
+    ///
+    /// The C library is guessed from the triple: glibc for GNU userlands and
+    /// newlib for bare-metal EABI targets.
+    pub fn cross_package_names(&self) -> Vec<String> {
+        let mut names = vec!["binutils", "gcc"];
+        if self.name.contains("-gnu") {
+            names.push("glibc");
+        } else if self.name.ends_with("-eabi") {
+            names.push("newlib");
+        }
+        names
+            .into_iter()
+            .map(|name| format!("cross-{}/{}", self.name, name))
+            .collect()
+    }
+}
+
+/// A problem found in a [`ToolchainConfig`].
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct ToolchainIssue {
+    /// The triple of the toolchain with the problem, or `None` if the problem
+    /// is with the configuration as a whole.
+    pub toolchain: Option<String>,
+    pub message: String,
+}
+
+impl ToolchainIssue {
+    fn new(toolchain: &Toolchain, message: String) -> Self {
+        Self {
+            toolchain: Some(toolchain.name.clone()),
+            message,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -131,6 +167,122 @@ impl ToolchainConfig {
         self.toolchains.iter().find(|t| t.name == name)
     }
 
+    /// Checks the configuration for problems that can be found without
+    /// looking at packages: unknown triples and conflicting defaults.
+    pub fn validate(&self) -> Vec<ToolchainIssue> {
+        let mut issues = Vec::new();
+
+        for toolchain in &self.toolchains {
+            if let Err(err) = toolchain.portage_arch() {
+                issues.push(ToolchainIssue::new(toolchain, err.to_string()));
+            }
+        }
+
+        // Toolchains are default candidates unless they opt out, so only
+        // explicit `{"default": true}` declarations can conflict.
+        let explicit_defaults: Vec<&Toolchain> = self
+            .toolchains
+            .iter()
+            .filter(|toolchain| {
+                matches!(
+                    toolchain.options,
+                    Some(ToolchainOptions {
+                        default: Some(true),
+                        ..
+                    })
+                )
+            })
+            .collect();
+        if explicit_defaults.len() > 1 {
+            issues.push(ToolchainIssue {
+                toolchain: None,
+                message: format!(
+                    "More than one toolchain is marked as default: {}",
+                    explicit_defaults
+                        .iter()
+                        .map(|toolchain| toolchain.name.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            });
+        } else if let (Some(explicit), Some(primary)) = (explicit_defaults.first(), self.primary())
+        {
+            if explicit.name != primary.name {
+                issues.push(ToolchainIssue::new(
+                    explicit,
+                    format!(
+                        "Marked as default, but {} is chosen as the default toolchain",
+                        primary.name
+                    ),
+                ));
+            }
+        }
+
+        issues
+    }
+
+    /// Checks that the cross compilers of the toolchains can be built by the
+    /// host, i.e. that their `cross-<triple>` packages resolve in
+    /// `host_resolver`.
+    ///
+    /// `native_triple` is the CHOST of the host, whose compiler is not a cross
+    /// compiler and is skipped.
+    ///
+    /// The default toolchain must also provide the `cross-<triple>/glibc`
+    /// package that `sdk_install_glibc` copies into the board's sysroot.
+    pub fn validate_cross_packages(
+        &self,
+        host_resolver: &PackageResolver,
+        native_triple: &str,
+    ) -> Result<Vec<ToolchainIssue>> {
+        let mut issues = Vec::new();
+
+        let resolves = |package_name: &str| -> Result<bool> {
+            let atom = PackageAtom::from_str(package_name)?;
+            Ok(host_resolver.find_best_package(&atom)?.is_some())
+        };
+
+        for toolchain in &self.toolchains {
+            if toolchain.name == native_triple {
+                continue;
+            }
+            for package_name in toolchain.cross_package_names() {
+                if !resolves(&package_name)? {
+                    issues.push(ToolchainIssue::new(
+                        toolchain,
+                        format!("Cross package {} not found in the host", package_name),
+                    ));
+                }
+            }
+        }
+
+        match self.primary() {
+            None => {
+                if !self.toolchains.is_empty() {
+                    issues.push(ToolchainIssue {
+                        toolchain: None,
+                        message: "No toolchain can be the default toolchain".to_owned(),
+                    });
+                }
+            }
+            Some(primary) if primary.name != native_triple => {
+                let glibc = format!("cross-{}/glibc", primary.name);
+                if !primary.cross_package_names().contains(&glibc) && !resolves(&glibc)? {
+                    issues.push(ToolchainIssue::new(
+                        primary,
+                        format!(
+                            "{} is required by sdk_install_glibc, but not found in the host",
+                            glibc
+                        ),
+                    ));
+                }
+            }
+            Some(_) => {}
+        }
+
+        Ok(issues)
+    }
+
     /// Loads the toolchain.conf that specifies the toolchains that are needed
     /// to build the board.
     ///
@@ -375,6 +527,80 @@ arm-none-eabi
         Ok(())
     }
 
+    #[test]
+    fn test_validate() -> Result<()> {
+        let config = ToolchainConfig {
+            default_index: Some(0),
+            toolchains: vec![
+                Toolchain::from_str(r#"x86_64-cros-linux-gnu {"default": true}"#)?,
+                Toolchain::from_str("mips-unknown-elf")?,
+                Toolchain::from_str(r#"aarch64-cros-linux-gnu {"default": true}"#)?,
+            ],
+        };
+
+        assert_eq!(
+            config.validate(),
+            vec![
+                ToolchainIssue {
+                    toolchain: Some("mips-unknown-elf".to_owned()),
+                    message: "Unknown arch mips".to_owned(),
+                },
+                ToolchainIssue {
+                    toolchain: None,
+                    message: "More than one toolchain is marked as default: \
+                              x86_64-cros-linux-gnu, aarch64-cros-linux-gnu"
+                        .to_owned(),
+                },
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_shadowed_default() -> Result<()> {
+        let config = ToolchainConfig {
+            default_index: Some(0),
+            toolchains: vec![
+                Toolchain::from_str("x86_64-cros-linux-gnu")?,
+                Toolchain::from_str(r#"armv7a-cros-linux-gnueabihf {"default": true}"#)?,
+            ],
+        };
+
+        assert_eq!(
+            config.validate(),
+            vec![ToolchainIssue {
+                toolchain: Some("armv7a-cros-linux-gnueabihf".to_owned()),
+                message: "Marked as default, but x86_64-cros-linux-gnu is chosen as the \
+                          default toolchain"
+                    .to_owned(),
+            }]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cross_package_names() -> Result<()> {
+        assert_eq!(
+            Toolchain::from_str("armv7a-cros-linux-gnueabihf")?.cross_package_names(),
+            vec![
+                "cross-armv7a-cros-linux-gnueabihf/binutils",
+                "cross-armv7a-cros-linux-gnueabihf/gcc",
+                "cross-armv7a-cros-linux-gnueabihf/glibc",
+            ]
+        );
+        assert_eq!(
+            Toolchain::from_str("arm-none-eabi")?.cross_package_names(),
+            vec![
+                "cross-arm-none-eabi/binutils",
+                "cross-arm-none-eabi/gcc",
+                "cross-arm-none-eabi/newlib",
+            ]
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_toolchain_simple() -> Result<()> {
         let toolchain = Toolchain::from_str("x86_64-cros-linux-gnu")?;
@@ -2,6 +2,14 @@
 // Use of this source code is governed by a BSD-style license that can be
 // found in the LICENSE file.
 
+//! End-to-end tests running `alchemist generate-repo` on fixture overlays.
+//!
+//! A fixture is a pair of directories under [`TESTDATA_DIR`]: an input
+//! directory laid out like a CrOS source checkout, and a golden directory
+//! holding the entire expected `@portage` output tree. To update golden
+//! directories after an intended change, run the tests with
+//! `ALCHEMY_REGENERATE_GOLDEN=1`.
+
 use std::{
     path::{Path, PathBuf},
     process::Command,
@@ -9,24 +17,24 @@ use std::{
 
 use anyhow::{ensure, Result};
 use runfiles::Runfiles;
+use serde_json::json;
 use tempfile::tempdir;
 use testutil::compare_with_golden_data;
 
 const TESTDATA_DIR: &str = "bazel/portage/bin/alchemist/src/bin/alchemist/testdata";
 
-#[test]
-fn test_generate_repo() -> Result<()> {
-    let temp_dir = tempdir()?;
-    let temp_dir = temp_dir.path();
-    let output_dir = temp_dir.join("portage-repo");
-    let deps_file = temp_dir.join("output_repos.json");
-
-    let input_dir = testutil::rename_bazel_input_testdata(
-        &testutil::runfiles_root()?.join(TESTDATA_DIR).join("input"),
-    )?;
-    let input_dir = input_dir.path();
+/// A fixture to run `generate-repo` on.
+struct GoldenCase<'a> {
+    /// The input directory name under [`TESTDATA_DIR`].
+    input: &'a str,
+    /// The golden directory name under [`TESTDATA_DIR`].
+    golden: &'a str,
+    /// Global flags passed to alchemist before the subcommand.
+    args: &'a [&'a str],
+}
 
-    let alchemist_path = match option_env!("CARGO_BIN_EXE_alchemist") {
+fn alchemist_path() -> Result<PathBuf> {
+    Ok(match option_env!("CARGO_BIN_EXE_alchemist") {
         Some(path) => PathBuf::from(path),
         None => {
             let r = Runfiles::create()?;
@@ -35,25 +43,206 @@ fn test_generate_repo() -> Result<()> {
                 "cros/bazel/portage/bin/alchemist/src/bin/alchemist/alchemist"
             )
         }
-    };
+    })
+}
 
-    let status = Command::new(alchemist_path)
+/// Runs `generate-repo` on the input directory `input` under [`TESTDATA_DIR`]
+/// and calls `check` with the output tree. `args` are passed before the
+/// subcommand and `generate_args` after it.
+///
+/// The output tree contains symlinks into the input directory, so it must be
+/// inspected before the input directory is cleaned up.
+fn run_generate_repo(
+    input: &str,
+    args: &[&str],
+    generate_args: &[&str],
+    check: impl FnOnce(&Path) -> Result<()>,
+) -> Result<()> {
+    let temp_dir = tempdir()?;
+    let temp_dir = temp_dir.path();
+    let output_dir = temp_dir.join("portage-repo");
+    let deps_file = temp_dir.join("output_repos.json");
+
+    let input_dir = testutil::rename_bazel_input_testdata(
+        &testutil::runfiles_root()?.join(TESTDATA_DIR).join(input),
+    )?;
+    let input_dir = input_dir.path();
+
+    let status = Command::new(alchemist_path()?)
+        .args(args)
+        .arg(format!("--source-dir={}", input_dir.display()))
         .args([
-            "--board=amd64-generic",
-            "--use-portage-site-configs=false",
-            "--force-accept-9999-ebuilds=false",
-            &format!("--source-dir={}", input_dir.display()),
             "generate-repo",
             &format!("--output-dir={}", output_dir.display()),
             &format!("--output-repos-json={}", deps_file.display()),
         ])
+        .args(generate_args)
         .status()?;
     ensure!(status.success());
 
     // trace.json changes every time we run.
     std::fs::remove_file(output_dir.join("trace.json"))?;
 
-    compare_with_golden_data(&output_dir, &Path::new(TESTDATA_DIR).join("golden"))?;
+    check(&output_dir)
+}
+
+/// Runs `generate-repo` on the input of `case` and compares the whole output
+/// tree with its golden directory.
+fn run_golden_case(case: &GoldenCase) -> Result<()> {
+    run_generate_repo(case.input, case.args, &[], |output_dir| {
+        compare_with_golden_data(output_dir, &Path::new(TESTDATA_DIR).join(case.golden))
+    })
+}
+
+/// Covers SDK stages, a cross-compiled board, cros-workon packages, SRC_URI,
+/// bashrcs, TOML metadata and ebuilds failing to evaluate.
+#[test]
+fn test_generate_repo() -> Result<()> {
+    run_golden_case(&GoldenCase {
+        input: "input",
+        golden: "golden",
+        args: &[
+            "--board=amd64-generic",
+            "--use-portage-site-configs=false",
+            "--force-accept-9999-ebuilds=false",
+        ],
+    })
+}
+
+/// Covers the same features on a trimmed-down overlay set with 9999 ebuilds
+/// force-accepted, as `cros build-packages` does by default.
+#[test]
+fn test_generate_repo_minimal() -> Result<()> {
+    run_golden_case(&GoldenCase {
+        input: "minimal-input",
+        golden: "minimal-golden",
+        args: &["--board=amd64-generic", "--use-portage-site-configs=false"],
+    })
+}
+
+/// Covers generating two boards into one repository. Each board gets its own
+/// public and internal targets, so that the boards don't overwrite each
+/// other's packages.
+#[test]
+fn test_generate_repo_multiple_boards() -> Result<()> {
+    run_generate_repo(
+        "minimal-input",
+        &[
+            "--board=amd64-generic",
+            "--board=betty:base",
+            "--use-portage-site-configs=false",
+        ],
+        &[],
+        |output_dir| {
+            let boards = ["amd64-generic", "betty"];
+            for board in boards {
+                for dir in [
+                    format!("target/{board}/sys-libs/glibc"),
+                    format!("images/{board}"),
+                    format!("internal/packages/stage2/target/{board}/chromiumos/sys-libs/glibc"),
+                ] {
+                    let path = output_dir.join(&dir).join("BUILD.bazel");
+                    ensure!(path.is_file(), "{} is missing", path.display());
+                }
+
+                // Public targets of a board must point to the board's own
+                // packages.
+                let build_file = std::fs::read_to_string(
+                    output_dir.join(format!("target/{board}/sys-libs/glibc/BUILD.bazel")),
+                )?;
+                ensure!(
+                    build_file.contains(&format!("//internal/packages/stage2/target/{board}/")),
+                    "target/{board}/sys-libs/glibc doesn't refer to the packages of \
+                    {board}:\n{build_file}"
+                );
+                for other_board in boards.into_iter().filter(|b| *b != board) {
+                    ensure!(
+                        !build_file.contains(&format!("/{other_board}/")),
+                        "target/{board}/sys-libs/glibc refers to the packages of \
+                        {other_board}:\n{build_file}"
+                    );
+                }
+            }
+
+            // The single-board layout must not be generated alongside.
+            ensure!(!output_dir.join("target/board").exists());
+            ensure!(!output_dir
+                .join("internal/packages/stage2/target/board")
+                .exists());
+
+            Ok(())
+        },
+    )
+}
+
+/// Covers the JSON backend, which writes `build.json` files in place of
+/// `BUILD.bazel` files for packages, public packages and images.
+#[test]
+fn test_generate_repo_json_backend() -> Result<()> {
+    run_generate_repo(
+        "minimal-input",
+        &["--board=amd64-generic", "--use-portage-site-configs=false"],
+        &["--backend=json"],
+        |output_dir| {
+            let read_json = |dir: &str| -> Result<serde_json::Value> {
+                let dir = output_dir.join(dir);
+                ensure!(
+                    !dir.join("BUILD.bazel").exists(),
+                    "{} has a BUILD.bazel",
+                    dir.display()
+                );
+                Ok(serde_json::from_str(&std::fs::read_to_string(
+                    dir.join("build.json"),
+                )?)?)
+            };
+
+            let package =
+                read_json("internal/packages/stage2/target/board/chromiumos/sys-libs/glibc")?;
+            assert_eq!(package["kind"], "package");
+            assert_eq!(package["target_board"], "amd64-generic");
+            assert_eq!(package["target_overlay_set"], "//internal/overlays:board");
+            assert_eq!(package["host_overlay_set"], "//internal/overlays:host");
+            assert_eq!(package["failures"], json!([]));
+            let ebuilds = package["ebuilds"].as_array().unwrap();
+            assert_eq!(ebuilds.len(), 1);
+            assert_eq!(ebuilds[0]["category"], "sys-libs");
+            assert_eq!(ebuilds[0]["package_name"], "glibc");
+            assert_eq!(ebuilds[0]["version"], "2.35-r25");
+            assert_eq!(ebuilds[0]["slot"], "0/0");
+            assert_eq!(ebuilds[0]["sdk"], "//internal/sdk/stage2/target/board:base");
+            assert_eq!(
+                ebuilds[0]["host_build_deps"],
+                json!(["//internal/packages/stage2/host/chromiumos/sys-devel/gcc:10.2.0"])
+            );
+
+            let public_package = read_json("target/sys-libs/glibc")?;
+            assert_eq!(public_package["kind"], "public_package");
+            assert_eq!(
+                public_package["best_version_selection_failure"],
+                json!(null)
+            );
+            let aliases = public_package["aliases"].as_array().unwrap();
+            assert!(aliases.contains(&json!({
+                "name": "2.35-r25",
+                "actual": "//internal/packages/stage2/target/board/chromiumos/sys-libs/glibc:2.35-r25",
+            })));
+            assert!(aliases.contains(&json!({"name": "glibc", "actual": ":2.35-r25"})));
+            assert!(public_package["test_suites"]
+                .as_array()
+                .unwrap()
+                .contains(&json!({"name": "glibc_test", "test_name": ":2.35-r25_test"})));
+
+            assert_eq!(
+                read_json("images")?,
+                json!({
+                    "kind": "images",
+                    "board": "amd64-generic",
+                    "target": "board",
+                    "target_packages": "@portage//target",
+                })
+            );
 
-    Ok(())
+            Ok(())
+        },
+    )
 }
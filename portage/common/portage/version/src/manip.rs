@@ -0,0 +1,411 @@
+// Copyright 2024 The ChromiumOS Authors
+// Use of this source code is governed by a BSD-style license that can be
+// found in the LICENSE file.
+
+//! Implements the version manipulation and comparison commands introduced in
+//! EAPI 7: `ver_cut`, `ver_rs` and `ver_test`.
+//!
+//! See PMS for the specification.
+//! https://projects.gentoo.org/pms/8/pms.html#x1-15400012.3.14
+
+use std::{cmp::Ordering, fmt::Display, str::FromStr};
+
+use anyhow::{bail, Context, Error, Result};
+
+use crate::Version;
+
+/// A version string split into separators and components for manipulation.
+///
+/// A version string is split into alternating separators and components. A
+/// component is a maximal run of ASCII digits or ASCII letters, and a
+/// separator is a maximal run of any other characters. Separator 0 precedes
+/// component 1, separator 1 follows component 1, and so on. Separators at
+/// the start and the end of the string may be empty.
+///
+/// Unlike [`Version`], any string can be split into components.
+///
+/// # Example
+///
+/// ```
+/// # use version::VersionComponents;
+/// let components: VersionComponents = "1.2b_alpha4".parse()?;
+/// assert_eq!(components.components().collect::<Vec<_>>(), ["1", "2", "b", "alpha", "4"]);
+/// assert_eq!(components.separators().collect::<Vec<_>>(), ["", ".", "", "_", ""]);
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VersionComponents {
+    /// Pairs of a separator and the component following it. The component of
+    /// the last pair is empty if the version string ends with a separator.
+    pairs: Vec<(String, String)>,
+}
+
+impl VersionComponents {
+    /// Returns the components, excluding the empty trailing one.
+    pub fn components(&self) -> impl Iterator<Item = &str> {
+        self.pairs
+            .iter()
+            .map(|(_, component)| component.as_str())
+            .filter(|component| !component.is_empty())
+    }
+
+    /// Returns the separators, starting from separator 0. The separator after
+    /// the last component is included only if the version string ends with
+    /// one.
+    pub fn separators(&self) -> impl Iterator<Item = &str> {
+        self.pairs.iter().map(|(separator, _)| separator.as_str())
+    }
+
+    /// Returns the string of the components in `range`, including the
+    /// separators between them. Separator 0 is included if the range starts
+    /// from 0, and the trailing separator is included if the range covers
+    /// the last component.
+    pub fn cut(&self, range: &VersionRange) -> String {
+        let max = self.pairs.len();
+        let (start, end) = range.resolve(max);
+        let flat = self.flatten();
+        let offset = if start > 0 { start * 2 - 1 } else { 0 };
+        let end = (end * 2).min(flat.len());
+        if offset >= end {
+            return String::new();
+        }
+        flat[offset..end].concat()
+    }
+
+    /// Replaces the separators in `range` with `replacement`.
+    ///
+    /// An empty separator 0 is not replaced, and no separator is added after
+    /// the last component.
+    pub fn replace_separators(&mut self, range: &VersionRange, replacement: &str) {
+        let Some(max) = self.pairs.len().checked_sub(1) else {
+            return;
+        };
+        let (start, end) = range.resolve(max);
+        for i in start..=end {
+            if i == 0 && self.pairs[0].0.is_empty() {
+                continue;
+            }
+            self.pairs[i].0 = replacement.to_owned();
+        }
+    }
+
+    fn flatten(&self) -> Vec<&str> {
+        self.pairs
+            .iter()
+            .flat_map(|(separator, component)| [separator.as_str(), component.as_str()])
+            .collect()
+    }
+}
+
+impl FromStr for VersionComponents {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut pairs = Vec::new();
+        let mut rest = s;
+        while !rest.is_empty() {
+            let separator_len = rest
+                .find(|c: char| c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len());
+            let (separator, tail) = rest.split_at(separator_len);
+
+            let component_len = match tail.chars().next() {
+                Some(c) if c.is_ascii_digit() => tail
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(tail.len()),
+                Some(_) => tail
+                    .find(|c: char| !c.is_ascii_alphabetic())
+                    .unwrap_or(tail.len()),
+                None => 0,
+            };
+            let (component, tail) = tail.split_at(component_len);
+
+            pairs.push((separator.to_owned(), component.to_owned()));
+            rest = tail;
+        }
+        Ok(Self { pairs })
+    }
+}
+
+impl Display for VersionComponents {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for s in self.flatten() {
+            write!(f, "{s}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A range of component or separator indices, e.g. `1`, `1-2` or `2-`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct VersionRange {
+    start: usize,
+    end: Option<usize>,
+}
+
+impl VersionRange {
+    /// Returns the start and the end of the range (inclusive), with the end
+    /// capped to `max`.
+    fn resolve(&self, max: usize) -> (usize, usize) {
+        (self.start, self.end.unwrap_or(max).min(max))
+    }
+}
+
+impl FromStr for VersionRange {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parse = |n: &str| -> Result<usize> {
+            if n.is_empty() || !n.bytes().all(|b| b.is_ascii_digit()) {
+                bail!("invalid range {s:?}");
+            }
+            n.parse().with_context(|| format!("invalid range {s:?}"))
+        };
+        let range = match s.split_once('-') {
+            None => {
+                let start = parse(s)?;
+                Self {
+                    start,
+                    end: Some(start),
+                }
+            }
+            Some((start, "")) => Self {
+                start: parse(start)?,
+                end: None,
+            },
+            Some((start, end)) => Self {
+                start: parse(start)?,
+                end: Some(parse(end)?),
+            },
+        };
+        if let Some(end) = range.end {
+            if end < range.start {
+                bail!("invalid range {s:?}: end of range must be >= start");
+            }
+        }
+        Ok(range)
+    }
+}
+
+/// Implements `ver_cut`: returns the substring of `version` that contains the
+/// components in `range`.
+///
+/// # Example
+///
+/// ```
+/// # use version::ver_cut;
+/// assert_eq!(ver_cut("1-2", "1.2.3")?, "1.2");
+/// assert_eq!(ver_cut("3-", "1.2.3b_alpha4")?, "3b_alpha4");
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn ver_cut(range: &str, version: &str) -> Result<String> {
+    let range: VersionRange = range.parse()?;
+    let components: VersionComponents = version.parse()?;
+    Ok(components.cut(&range))
+}
+
+/// Implements `ver_rs`: replaces the separators in each range with the paired
+/// replacement, in order.
+///
+/// # Example
+///
+/// ```
+/// # use version::ver_rs;
+/// assert_eq!(ver_rs(&[("1", "_"), ("2-", "-")], "1.2.3.4")?, "1_2-3-4");
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn ver_rs(replacements: &[(&str, &str)], version: &str) -> Result<String> {
+    let mut components: VersionComponents = version.parse()?;
+    for (range, replacement) in replacements {
+        let range: VersionRange = range.parse()?;
+        components.replace_separators(&range, replacement);
+    }
+    Ok(components.to_string())
+}
+
+/// A comparison operator accepted by `ver_test`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum VersionTestOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl VersionTestOp {
+    /// Returns whether `ordering` of the left-hand side against the
+    /// right-hand side satisfies the operator.
+    pub fn test(&self, ordering: Ordering) -> bool {
+        match self {
+            Self::Eq => ordering == Ordering::Equal,
+            Self::Ne => ordering != Ordering::Equal,
+            Self::Lt => ordering == Ordering::Less,
+            Self::Le => ordering != Ordering::Greater,
+            Self::Gt => ordering == Ordering::Greater,
+            Self::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+impl FromStr for VersionTestOp {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "-eq" => Self::Eq,
+            "-ne" => Self::Ne,
+            "-lt" => Self::Lt,
+            "-le" => Self::Le,
+            "-gt" => Self::Gt,
+            "-ge" => Self::Ge,
+            _ => bail!("Unsupported operator: {s}"),
+        })
+    }
+}
+
+/// Implements `ver_test`: compares two versions with the PMS version
+/// comparison algorithm.
+///
+/// # Example
+///
+/// ```
+/// # use version::ver_test;
+/// assert!(ver_test("1.2_rc1", "-lt", "1.2")?);
+/// assert!(ver_test("1.010", "-eq", "1.01")?);
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn ver_test(lhs: &str, op: &str, rhs: &str) -> Result<bool> {
+    let op: VersionTestOp = op.parse()?;
+    let lhs = Version::try_new(lhs)?;
+    let rhs = Version::try_new(rhs)?;
+    Ok(op.test(lhs.cmp(&rhs)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split() -> Result<()> {
+        // Taken from https://mgorny.pl/articles/the-ultimate-guide-to-eapi-7.html#version-strings-for-manipulation
+        for (input, components, separators) in [
+            ("", vec![], vec![]),
+            ("1.2.3", vec!["1", "2", "3"], vec!["", ".", "."]),
+            (
+                "1.2b_alpha4",
+                vec!["1", "2", "b", "alpha", "4"],
+                vec!["", ".", "", "_", ""],
+            ),
+            ("2Ab9s", vec!["2", "Ab", "9", "s"], vec!["", "", "", ""]),
+            ("A.4.", vec!["A", "4"], vec!["", ".", "."]),
+            (".11.", vec!["11"], vec![".", "."]),
+        ] {
+            let actual: VersionComponents = input.parse()?;
+            assert_eq!(actual.components().collect::<Vec<_>>(), components);
+            assert_eq!(actual.separators().collect::<Vec<_>>(), separators);
+            assert_eq!(actual.to_string(), input);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_range() -> Result<()> {
+        assert_eq!(
+            "2".parse::<VersionRange>()?,
+            VersionRange {
+                start: 2,
+                end: Some(2)
+            }
+        );
+        assert_eq!(
+            "1-".parse::<VersionRange>()?,
+            VersionRange {
+                start: 1,
+                end: None
+            }
+        );
+        assert_eq!(
+            "0-3".parse::<VersionRange>()?,
+            VersionRange {
+                start: 0,
+                end: Some(3)
+            }
+        );
+        for invalid in ["", "-", "-1", "a", "1-a", "3-2", "1-2-3"] {
+            assert!(
+                invalid.parse::<VersionRange>().is_err(),
+                "{invalid:?} should be rejected"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_ver_cut() -> Result<()> {
+        for (range, version, expected) in [
+            ("1", "1.2.3", "1"),
+            ("1-2", "1.2.3", "1.2"),
+            ("2-", "1.2.3", "2.3"),
+            ("0-1", "1.2.3", "1"),
+            ("0-1", ".11.", ".11"),
+            ("1-", "A.4.", "A.4."),
+            ("3-4", "1.2.3b_alpha4", "3b"),
+            ("5", "1.2.3b_alpha4", "alpha"),
+            ("4-", "1.2", ""),
+            ("1", "", ""),
+        ] {
+            assert_eq!(ver_cut(range, version)?, expected, "{range} {version}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_ver_rs() -> Result<()> {
+        // Taken from https://mgorny.pl/articles/the-ultimate-guide-to-eapi-7.html#replacing-version-separators-ver-rs
+        let inputs = ["1.2.3", "2 Ab 9 s", "A.4.", ".11.2."];
+        let table = [
+            ("0", ["1.2.3", "2 Ab 9 s", "A.4.", "#11.2."]),
+            ("0-1", ["1#2.3", "2#Ab 9 s", "A#4.", "#11#2."]),
+            ("1", ["1#2.3", "2#Ab 9 s", "A#4.", ".11#2."]),
+            ("1-", ["1#2#3", "2#Ab#9#s", "A#4#", ".11#2#"]),
+            ("1-2", ["1#2#3", "2#Ab#9 s", "A#4#", ".11#2#"]),
+            ("2", ["1.2#3", "2 Ab#9 s", "A.4#", ".11.2#"]),
+            ("2-3", ["1.2#3", "2 Ab#9#s", "A.4#", ".11.2#"]),
+            ("3", ["1.2.3", "2 Ab 9#s", "A.4.", ".11.2."]),
+        ];
+        for (range, expected_values) in table {
+            for (input, expected) in inputs.iter().zip(expected_values) {
+                assert_eq!(ver_rs(&[(range, "#")], input)?, expected, "{range} {input}");
+            }
+        }
+
+        assert_eq!(
+            ver_rs(&[("2-3", "#"), ("3-4", "-")], "1.2.3.4.5.6")?,
+            "1.2#3-4-5.6"
+        );
+        assert_eq!(ver_rs(&[("2-3", "#")], "")?, "");
+        // Empty separators between components are replaced.
+        assert_eq!(ver_rs(&[("2", "_")], "1.2b")?, "1.2_b");
+        Ok(())
+    }
+
+    #[test]
+    fn test_ver_test() -> Result<()> {
+        for (lhs, op, rhs, expected) in [
+            ("0.5", "-gt", "0.2", true),
+            ("0.2", "-gt", "0.5", false),
+            ("1.0", "-eq", "1.0", true),
+            ("1.0", "-ne", "1.0-r0", false),
+            ("1.0_p1", "-ge", "1.0", true),
+            ("1.0_alpha", "-le", "1.0", true),
+            ("1.0-r2", "-lt", "1.0-r10", true),
+        ] {
+            assert_eq!(ver_test(lhs, op, rhs)?, expected, "{lhs} {op} {rhs}");
+        }
+        assert!(ver_test("1.0", "-foo", "1.0").is_err());
+        assert!(ver_test("1.0", "-eq", "bogus").is_err());
+        Ok(())
+    }
+}
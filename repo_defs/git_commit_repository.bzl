@@ -0,0 +1,151 @@
+# Copyright 2024 The ChromiumOS Authors
+# Use of this source code is governed by a BSD-style license that can be
+# found in the LICENSE file.
+
+_BUILD_TEMPLATE = """
+filegroup(
+    name = "src",
+    srcs = ["{file}"],
+    # Use public visibility since bzlmod repo namespacing prevents unwanted
+    # visibility.
+    visibility = ["//visibility:public"],
+)
+"""
+
+def _exec(ctx, cmd, *kwargs):
+    st = ctx.execute(cmd, *kwargs)
+    if st.return_code != 0:
+        cmd_str = " ".join(["'%s'" % (arg) for arg in cmd])
+        fail("`%s`: %s" % (cmd_str, st.stderr))
+    return st.stdout
+
+def _fetch_commit(ctx, git_dir):
+    """Fetches the commit from the first remote that serves it."""
+    errors = []
+    for remote in ctx.attr.remotes:
+        # Most servers allow fetching a reachable commit by its hash, which
+        # avoids downloading the whole history.
+        st = ctx.execute([
+            "git",
+            "--git-dir",
+            git_dir,
+            "fetch",
+            "--depth",
+            "1",
+            remote,
+            ctx.attr.commit,
+        ])
+        if st.return_code == 0:
+            return
+
+        st = ctx.execute([
+            "git",
+            "--git-dir",
+            git_dir,
+            "fetch",
+            remote,
+            "+refs/heads/*:refs/remotes/origin/*",
+            "+refs/tags/*:refs/tags/*",
+        ])
+        if st.return_code == 0:
+            st = ctx.execute([
+                "git",
+                "--git-dir",
+                git_dir,
+                "cat-file",
+                "-e",
+                "%s^{commit}" % (ctx.attr.commit),
+            ])
+            if st.return_code == 0:
+                return
+            errors.append("%s: commit not found" % (remote))
+        else:
+            errors.append("%s: %s" % (remote, st.stderr))
+
+    fail("Failed to fetch %s:\n%s" % (ctx.attr.commit, "\n".join(errors)))
+
+def _git_commit_repository_impl(ctx):
+    """Repository rule that fetches a git repository at a pinned commit."""
+
+    if not ctx.which("tar"):
+        fail("tar was not found on the path")
+
+    # TODO(b/342064824): Stop using `pzstd` found on the system.
+    pzstd = ctx.which("pzstd")
+    if not pzstd:
+        fail("pzstd was not found on the path")
+
+    if not ctx.which("git"):
+        fail("git was not found on the path")
+
+    if not ctx.attr.remotes:
+        fail("At least one remote must be specified")
+
+    local_repo = "local.git"
+    _exec(ctx, ["git", "init", "--bare", "--quiet", local_repo])
+    _fetch_commit(ctx, local_repo)
+
+    # It would be great to use git archive for creating the tarball, but
+    # unfortunately the output is not hermetic.
+    _exec(ctx, ["mkdir", "work"])
+    _exec(ctx, [
+        "git",
+        "--git-dir",
+        local_repo,
+        "--work-tree",
+        ctx.path("work"),
+        "checkout",
+        ctx.attr.commit,
+        "--",
+        ".",
+    ])
+
+    ctx.delete(local_repo)
+
+    # See https://reproducible-builds.org/docs/archives/
+    dest_file = "%s.tar.zst" % (ctx.attr.commit)
+    _exec(ctx, [
+        "tar",
+        "--format",
+        "pax",
+        "--pax-option",
+        "exthdr.name=%d/PaxHeaders/%f,delete=atime,delete=ctime",
+        "--sort",
+        "name",
+        "--mtime",
+        "1970-1-1 00:00Z",
+        "--owner",
+        "0",
+        "--group",
+        "0",
+        "--numeric-owner",
+        "-I{}".format(pzstd),
+        "--remove-files",
+        "--create",
+        "--file",
+        dest_file,
+        "-C",
+        "work",
+        ".",
+    ])
+
+    ctx.file("BUILD.bazel", _BUILD_TEMPLATE.format(file = dest_file))
+
+# Generates a tarball from a git repository at the specified commit. This is
+# used for ebuilds inheriting git-r3 that pin EGIT_COMMIT.
+git_commit_repository = repository_rule(
+    implementation = _git_commit_repository_impl,
+    attrs = {
+        "commit": attr.string(
+            doc = """The full SHA-1 of the commit to check out.""",
+            mandatory = True,
+        ),
+        "remotes": attr.string_list(
+            doc = """The URIs of the git repository, tried in order.""",
+            mandatory = True,
+        ),
+    },
+    # This is expensive to compute so we don't want to recreate it ever, if
+    # possible.
+    local = False,
+)